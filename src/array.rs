@@ -0,0 +1,203 @@
+//! Streaming the elements out of top-level JSON arrays.
+
+use bytes::{Bytes, BytesMut};
+use events::unexpected;
+use lex::{is_whitespace, value_end, LexError, Lexer, TokenKind};
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// A [`Codec`] that decodes the elements of top-level JSON arrays one at a time.
+///
+/// The input is a sequence of arrays, typically a single one making up the whole stream, such
+/// as the response of an API returning a multi-gigabyte array. Each array is decoded element by
+/// element, so an array far larger than memory is decoded in memory bounded by its largest
+/// element. Each element is decoded as a frame of its own, so options such as limits and
+/// subscriptions apply to elements. Encoding is unaffected.
+///
+/// Unlike [`Codec::unbatch_arrays`], which needs each array in full, this doesn't decode values
+/// other than arrays at the top level.
+///
+/// Created by [`Codec::elements`].
+#[derive(Clone, Debug)]
+pub struct Elements<D, E> {
+    codec: Codec<D, E>,
+    state: State,
+}
+
+/// Where the decoder is within an array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Between arrays.
+    Start,
+    /// Expecting an element, or the end of the array if `first`.
+    Element { first: bool },
+    /// Expecting a comma or the end of the array.
+    ElementEnd,
+}
+
+impl<D, E> Elements<D, E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Elements`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to decode the elements of top-level arrays as they arrive.
+    pub fn elements(self) -> Elements<D, E> {
+        Elements {
+            codec: self,
+            state: State::Start,
+        }
+    }
+
+    /// Splits the next element off `src`, consuming the array around it.
+    fn next_element(
+        &mut self,
+        state: &mut State,
+        src: &mut BytesMut,
+        eof: bool,
+    ) -> Result<Option<Bytes>, Error> {
+        loop {
+            if self.sentinel_received {
+                return Ok(None);
+            }
+            let whitespace = src.iter().take_while(|&&b| is_whitespace(b)).count();
+            let element = match *state {
+                State::Element { first } => !first || src.get(whitespace) != Some(&b']'),
+                _ => false,
+            };
+            if element {
+                let end = match value_end(&src[..], eof, usize::MAX) {
+                    Ok(Some(end)) => end,
+                    Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                    Err(LexError::Invalid(at)) => return Err(unexpected(src[at])),
+                };
+                src.advance(whitespace);
+                let element = src.split_to(end - whitespace).freeze();
+                *state = State::ElementEnd;
+                self.split_off(end, Some(element.len()));
+                return Ok(Some(element));
+            }
+            let token = match Lexer::new(&src[..], whitespace, eof).next_token() {
+                Ok(Some(token)) => token,
+                Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                Err(LexError::Invalid(at)) => return Err(unexpected(src[at])),
+            };
+            *state = match (*state, token.kind) {
+                (State::Start, TokenKind::ArrayStart) => State::Element { first: true },
+                (State::Element { first: true }, TokenKind::ArrayEnd) => State::Start,
+                (State::ElementEnd, TokenKind::Comma) => State::Element { first: false },
+                (State::ElementEnd, TokenKind::ArrayEnd) => State::Start,
+                _ => return Err(unexpected(src[token.start])),
+            };
+            src.advance(token.end);
+            self.split_off(token.end, None);
+        }
+    }
+}
+
+impl<D, E> Elements<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    fn decode_elements(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<D>, Error> {
+        let state = &mut self.state;
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(element) = codec.next_element(state, src, eof)? {
+                if let Some(item) = codec.decode_value(element)? {
+                    return Ok(Some(item));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Decoder for Elements<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.decode_elements(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let item = self.decode_elements(src, true)?;
+        let pending = self.state != State::Start || src.iter().any(|&b| !is_whitespace(b));
+        if item.is_none() && pending && !self.codec.sentinel_received {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended in the middle of an array",
+            )
+            .into());
+        }
+        Ok(item)
+    }
+}
+
+impl<D, E> Encoder for Elements<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn elements() {
+        let input = br#"[1, {"a": [2, 3]}, "]"] [] [4]"#;
+        let mut codec = Codec::<Value, ()>::default().elements();
+        let mut buf = BytesMut::new();
+        let mut elements = Vec::new();
+        // Feed the input a byte at a time to check that decoding resumes correctly.
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            while let Some(element) = codec.decode(&mut buf).unwrap() {
+                elements.push(element);
+            }
+        }
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+        assert_eq!(
+            elements,
+            vec![json!(1), json!({"a": [2, 3]}), json!("]"), json!(4)]
+        );
+
+        let mut buf = BytesMut::from(&b"[1 2]"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(1)));
+        assert!(codec.decode(&mut buf).is_err());
+        let mut codec = Codec::<Value, ()>::default().elements();
+        let mut buf = BytesMut::from(&b"{}"[..]);
+        assert!(codec.decode(&mut buf).is_err());
+        let mut buf = BytesMut::from(&b"[1,"[..]);
+        let mut codec = Codec::<Value, ()>::default().elements();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(1)));
+        assert!(codec.decode_eof(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Converting decoded items into Arrow record batches.
+
+use arrow_array::RecordBatch;
+use arrow_json::reader::{Decoder, ReaderBuilder};
+use arrow_schema::SchemaRef;
+use futures::{Async, Poll, Stream};
+use serde::Serialize;
+use std::fmt;
+use {Error, FramedJson};
+
+/// A stream that converts the rows of a stream of decoded items into Arrow [`RecordBatch`]es
+/// with a given schema, `batch_size` rows at a time (requires the `arrow` feature).
+///
+/// Each item is a row, typically an object with a field for each of the schema's columns; rows
+/// are serialized into the batch as they arrive, without an intermediate `Vec<Value>`. The last
+/// batch holds whatever rows are left when the stream ends. Errors from the underlying stream are
+/// passed through. A batch whose rows don't fit the schema fails with an [`Error::Arrow`], and
+/// its rows are dropped, so that the stream can carry on with the next batch.
+///
+/// Created by [`FramedJson::into_record_batches`], or by [`RecordBatches::new`] for other
+/// streams of decoded items.
+pub struct RecordBatches<S> {
+    stream: S,
+    schema: SchemaRef,
+    batch_size: usize,
+    decoder: Decoder,
+    rows: usize,
+    done: bool,
+}
+
+impl<S> fmt::Debug for RecordBatches<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordBatches")
+            .field("stream", &self.stream)
+            .field("schema", &self.schema)
+            .field("batch_size", &self.batch_size)
+            .field("rows", &self.rows)
+            .finish()
+    }
+}
+
+/// Creates a decoder building batches with `schema`.
+fn decoder(schema: &SchemaRef, batch_size: usize) -> Result<Decoder, Error> {
+    Ok(ReaderBuilder::new(schema.clone())
+        .with_batch_size(batch_size)
+        .build_decoder()?)
+}
+
+impl<S> RecordBatches<S> {
+    /// Creates a `RecordBatches` converting the items of `stream` into batches of up to
+    /// `batch_size` rows. Fails if `schema` has a data type that can't be built from JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn new(stream: S, schema: SchemaRef, batch_size: usize) -> Result<Self, Error> {
+        assert!(batch_size > 0, "batches must hold at least one row");
+        Ok(RecordBatches {
+            decoder: decoder(&schema, batch_size)?,
+            stream,
+            schema,
+            batch_size,
+            rows: 0,
+            done: false,
+        })
+    }
+
+    /// Returns the schema of the batches.
+    pub fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the `RecordBatches`, returning the underlying stream. Rows that haven't been
+    /// yielded in a batch yet are dropped.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Builds a batch from the rows so far.
+    fn flush(&mut self) -> Result<Option<RecordBatch>, Error> {
+        self.rows = 0;
+        match self.decoder.flush() {
+            Ok(batch) => Ok(batch),
+            Err(e) => {
+                // The decoder keeps the rows that failed, so start over without them.
+                self.decoder = decoder(&self.schema, self.batch_size)?;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl<S> Stream for RecordBatches<S>
+where
+    S: Stream<Error = Error>,
+    S::Item: Serialize,
+{
+    type Item = RecordBatch;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<RecordBatch>, Error> {
+        while !self.done {
+            match try_ready!(self.stream.poll()) {
+                Some(row) => {
+                    self.decoder.serialize(&[row])?;
+                    self.rows += 1;
+                    if self.rows == self.batch_size {
+                        return self.flush().map(Async::Ready);
+                    }
+                }
+                None => self.done = true,
+            }
+        }
+        self.flush().map(Async::Ready)
+    }
+}
+
+impl<T, D, E> FramedJson<T, D, E> {
+    /// Converts the `FramedJson` into a stream of Arrow record batches with `schema`, of up to
+    /// `batch_size` decoded items each (requires the `arrow` feature); see [`RecordBatches`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn into_record_batches(
+        self,
+        schema: SchemaRef,
+        batch_size: usize,
+    ) -> Result<RecordBatches<Self>, Error> {
+        RecordBatches::new(self, schema, batch_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecordBatches;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::stream::iter_ok;
+    use futures::{Future, Stream};
+    use serde_json::Value;
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use {Codec, Error, ErrorKind, FramedJson};
+
+    #[test]
+    fn record_batches() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let input = b"{\"id\": 1, \"name\": \"a\"}\n{\"id\": 2}\n{\"id\": 3, \"name\": \"c\"}\n";
+        let codec: Codec<Value, ()> = Codec::default();
+        let batches: Vec<_> = FramedJson::new(Cursor::new(input.to_vec()), codec)
+            .into_record_batches(schema.clone(), 2)
+            .unwrap()
+            .collect()
+            .wait()
+            .unwrap();
+        let rows: Vec<_> = batches.iter().map(|batch| batch.num_rows()).collect();
+        assert_eq!(rows, vec![2, 1]);
+        assert_eq!(batches[0].schema(), schema);
+        let ids = batches[0].column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values(), &[1, 2]);
+        assert!(batches[0].column(1).is_null(1));
+        assert_eq!(batches[1].column(1).as_string::<i32>().value(0), "c");
+
+        // A batch that doesn't fit the schema is dropped, and the next one carries on.
+        let rows = vec![json!({"id": "x"}), json!({"id": 4}), json!({"id": 5})];
+        let mut batches = RecordBatches::new(iter_ok::<_, Error>(rows), schema, 1)
+            .unwrap()
+            .wait();
+        assert_eq!(batches.next().unwrap().unwrap_err().kind(), ErrorKind::Data);
+        assert_eq!(batches.next().unwrap().unwrap().num_rows(), 1);
+        assert_eq!(batches.next().unwrap().unwrap().num_rows(), 1);
+        assert!(batches.next().is_none());
+    }
+}
@@ -0,0 +1,234 @@
+//! Packing items into JSON array frames, and unpacking them again.
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use std::mem;
+use std::time::{Duration, Instant};
+use std::vec;
+use tokio_timer::Delay;
+
+/// A sink that packs items into batches of up to `max_items`, sending each batch as one frame.
+///
+/// The underlying sink is typically a [`Framed`][`tokio_codec::Framed`] or [`FramedJson`] with a
+/// `Codec<_, Vec<T>>`, so that each batch is encoded as a JSON array. This reduces the per-frame
+/// overhead of high-rate small messages; [`Unbatch`] unpacks the batches again.
+///
+/// A batch is sent once it is full or when the sink is flushed. With a
+/// [linger time][`Batch::linger`], flushing a batch that isn't full waits until the linger time
+/// has passed since its first item, so that more items can join it.
+///
+/// [`FramedJson`]: ::FramedJson
+#[derive(Debug)]
+pub struct Batch<S, T> {
+    sink: S,
+    max_items: usize,
+    linger: Option<Duration>,
+    batch: Vec<T>,
+    deadline: Option<Delay>,
+}
+
+impl<S, T> Batch<S, T>
+where
+    S: Sink<SinkItem = Vec<T>>,
+{
+    /// Creates a `Batch` that sends batches of up to `max_items` to `sink`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_items` is 0.
+    pub fn new(sink: S, max_items: usize) -> Self {
+        assert!(max_items > 0, "batches must hold at least one item");
+        Batch {
+            sink,
+            max_items,
+            linger: None,
+            batch: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Hands the current batch to the sink, if there is one.
+    fn send_batch(&mut self) -> Poll<(), S::SinkError> {
+        if self.batch.is_empty() {
+            return Ok(Async::Ready(()));
+        }
+        let batch = mem::take(&mut self.batch);
+        match self.sink.start_send(batch)? {
+            AsyncSink::Ready => {
+                self.deadline = None;
+                Ok(Async::Ready(()))
+            }
+            AsyncSink::NotReady(batch) => {
+                self.batch = batch;
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    /// Returns whether the current batch should wait for more items before it is sent.
+    fn lingering(&mut self) -> bool {
+        if self.batch.len() >= self.max_items {
+            return false;
+        }
+        match self.deadline {
+            // A timer error means there is no timer to wait for, so don't.
+            Some(ref mut deadline) => matches!(deadline.poll(), Ok(Async::NotReady)),
+            None => false,
+        }
+    }
+}
+
+impl<S, T> Batch<S, T> {
+    /// Set how long a batch that isn't full may wait for more items when the sink is flushed.
+    ///
+    /// Waiting requires a Tokio timer, so the sink must be used on a Tokio runtime. Batches are
+    /// sent without waiting by default.
+    pub fn linger(&mut self, linger: Option<Duration>) {
+        self.linger = linger;
+    }
+
+    /// Returns a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Consumes the `Batch`, returning the underlying sink. Items that haven't been sent yet are
+    /// lost.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S, T> Sink for Batch<S, T>
+where
+    S: Sink<SinkItem = Vec<T>>,
+{
+    type SinkItem = T;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: T) -> StartSend<T, S::SinkError> {
+        if self.batch.len() >= self.max_items && self.send_batch()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        if self.batch.is_empty() {
+            self.deadline = self
+                .linger
+                .map(|linger| Delay::new(Instant::now() + linger));
+        }
+        self.batch.push(item);
+        if self.batch.len() >= self.max_items {
+            self.send_batch()?;
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        if self.lingering() {
+            try_ready!(self.sink.poll_complete());
+            return Ok(Async::NotReady);
+        }
+        try_ready!(self.send_batch());
+        self.sink.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        try_ready!(self.send_batch());
+        self.sink.close()
+    }
+}
+
+/// A stream that unpacks batches of items, such as those sent by [`Batch`], into the items.
+///
+/// To also accept frames that aren't batched, use
+/// [`Codec::unbatch_arrays`][`::Codec::unbatch_arrays`] instead.
+#[derive(Debug)]
+pub struct Unbatch<S, T> {
+    stream: S,
+    batch: vec::IntoIter<T>,
+}
+
+impl<S, T> Unbatch<S, T>
+where
+    S: Stream<Item = Vec<T>>,
+{
+    /// Creates an `Unbatch` yielding the items of each batch from `stream`.
+    pub fn new(stream: S) -> Self {
+        Unbatch {
+            stream,
+            batch: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<S, T> Unbatch<S, T> {
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the `Unbatch`, returning the underlying stream. Items of the current batch that
+    /// haven't been yielded yet are lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, T> Stream for Unbatch<S, T>
+where
+    S: Stream<Item = Vec<T>>,
+{
+    type Item = T;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, S::Error> {
+        loop {
+            if let Some(item) = self.batch.next() {
+                return Ok(Async::Ready(Some(item)));
+            }
+            match try_ready!(self.stream.poll()) {
+                Some(batch) => self.batch = batch.into_iter(),
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, Unbatch};
+    use futures::stream::iter_ok;
+    use futures::{Future, Sink, Stream};
+    use std::time::{Duration, Instant};
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn batches() {
+        let batch = Batch::new(Vec::new(), 2);
+        let (batch, _) = batch.send_all(iter_ok::<_, ()>(1..6)).wait().unwrap();
+        let batches = batch.into_inner();
+        assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+        let items = Unbatch::new(iter_ok::<_, ()>(batches));
+        assert_eq!(items.collect().wait().unwrap(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn linger() {
+        let mut batch = Batch::new(Vec::new(), 2);
+        batch.linger(Some(Duration::from_millis(20)));
+        let started = Instant::now();
+        let mut runtime = Runtime::new().unwrap();
+        let batch = runtime.block_on(batch.send(1)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(batch.into_inner(), vec![vec![1]]);
+    }
+}
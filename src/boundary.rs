@@ -0,0 +1,113 @@
+//! Reporting frame boundaries to protocol analyzers.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Whether a frame was decoded or encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// The frame was split off the input.
+    Decode,
+    /// The frame was written to the output.
+    Encode,
+}
+
+/// A frame boundary identified by the codec; see [`Codec::on_frame`][`::Codec::on_frame`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameBoundary {
+    /// Whether the frame was decoded or encoded.
+    pub direction: Direction,
+    /// The offset of the first byte of the frame in the stream, counting from when the callback
+    /// was registered.
+    pub offset: u64,
+    /// The length of the frame in bytes, excluding surrounding whitespace.
+    pub len: usize,
+    /// When the boundary was identified.
+    pub timestamp: SystemTime,
+}
+
+/// A frame-boundary callback, along with how far into each direction of the stream it is.
+#[derive(Clone)]
+pub(crate) struct Boundaries {
+    callback: Arc<dyn Fn(&FrameBoundary) + Send + Sync>,
+    decoded: u64,
+    encoded: u64,
+}
+
+impl Boundaries {
+    pub(crate) fn new(callback: Arc<dyn Fn(&FrameBoundary) + Send + Sync>) -> Self {
+        Boundaries {
+            callback,
+            decoded: 0,
+            encoded: 0,
+        }
+    }
+
+    /// Records that `consumed` bytes of the input were consumed, ending with a frame of `len`
+    /// bytes if one was split off.
+    pub(crate) fn decoded(&mut self, consumed: usize, len: Option<usize>) {
+        let end = self.decoded.saturating_add(consumed as u64);
+        if let Some(len) = len {
+            self.report(Direction::Decode, end - len as u64, len);
+        }
+        self.decoded = end;
+    }
+
+    /// Records that a frame of `len` bytes was written to the output.
+    pub(crate) fn encoded(&mut self, len: usize) {
+        let offset = self.encoded;
+        self.report(Direction::Encode, offset, len);
+        self.encoded = offset.saturating_add(len as u64);
+    }
+
+    fn report(&self, direction: Direction, offset: u64, len: usize) {
+        (self.callback)(&FrameBoundary {
+            direction,
+            offset,
+            len,
+            timestamp: SystemTime::now(),
+        });
+    }
+}
+
+impl fmt::Debug for Boundaries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Boundaries")
+            .field("decoded", &self.decoded)
+            .field("encoded", &self.encoded)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Boundaries, Direction};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn offsets() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let mut boundaries = Boundaries::new(Arc::new(move |frame| {
+            sink.lock()
+                .unwrap()
+                .push((frame.direction, frame.offset, frame.len));
+        }));
+        boundaries.decoded(5, Some(4));
+        boundaries.decoded(2, None);
+        boundaries.encoded(3);
+        boundaries.decoded(4, Some(2));
+        boundaries.encoded(1);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (Direction::Decode, 1, 4),
+                (Direction::Encode, 0, 3),
+                (Direction::Decode, 9, 2),
+                (Direction::Encode, 3, 1),
+            ]
+        );
+    }
+}
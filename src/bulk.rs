@@ -0,0 +1,150 @@
+//! Encoding Elasticsearch bulk requests.
+
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// An operation of an Elasticsearch `_bulk` request, as encoded by [`Bulk`].
+///
+/// `metadata` is what goes with the action on its line, such as
+/// `{"_index": "logs", "_id": "1"}`.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum BulkOp<E> {
+    /// Index `source`, replacing any document with the same ID.
+    Index { metadata: Value, source: E },
+    /// Index `source`, failing if there is already a document with the same ID.
+    Create { metadata: Value, source: E },
+    /// Update a document as described by `body`, such as `{"doc": {"a": 1}}`.
+    Update { metadata: Value, body: E },
+    /// Delete a document.
+    Delete { metadata: Value },
+}
+
+/// A [`Codec`] that encodes [`BulkOp`]s in the newline-delimited format of the Elasticsearch
+/// (and OpenSearch) `_bulk` API.
+///
+/// Each operation is written as an action line followed, except for deletes, by a line holding
+/// the document or update, and every line ends with a newline, including the last as the API
+/// requires. Values are never pretty-printed. Decoding, such as of the API's response, is
+/// unaffected.
+///
+/// Created by [`Codec::bulk`].
+#[derive(Clone, Debug)]
+pub struct Bulk<D, E> {
+    codec: Codec<D, E>,
+}
+
+impl<D, E> Bulk<D, E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Bulk`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to encode Elasticsearch bulk requests, turning off pretty-printing.
+    pub fn bulk(mut self) -> Bulk<D, E> {
+        self.pretty = false;
+        Bulk { codec: self }
+    }
+}
+
+impl<D, E> Decoder for Bulk<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.codec.decode(src)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.codec.decode_eof(src)
+    }
+}
+
+impl<D, E> Encoder for Bulk<D, E>
+where
+    E: Serialize,
+{
+    type Item = BulkOp<E>;
+    type Error = Error;
+
+    fn encode(&mut self, op: BulkOp<E>, dst: &mut BytesMut) -> Result<(), Error> {
+        let (action, metadata, source) = match op {
+            BulkOp::Index { metadata, source } => ("index", metadata, Some(source)),
+            BulkOp::Create { metadata, source } => ("create", metadata, Some(source)),
+            BulkOp::Update { metadata, body } => ("update", metadata, Some(body)),
+            BulkOp::Delete { metadata } => ("delete", metadata, None),
+        };
+        let start = dst.len();
+        let mut line = Map::new();
+        line.insert(action.to_owned(), metadata);
+        self.codec.write(&Value::Object(line), dst)?;
+        dst.extend_from_slice(b"\n");
+        if let Some(source) = source {
+            if let Err(e) = self.codec.encode(source, dst) {
+                // Don't leave an action without its document.
+                dst.truncate(start);
+                return Err(e);
+            }
+            dst.extend_from_slice(b"\n");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BulkOp;
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::Encoder;
+    use Codec;
+
+    #[test]
+    fn bulk() {
+        let mut codec = Codec::<Value, Value>::pretty_printed().bulk();
+        let mut buf = BytesMut::new();
+        let ops = vec![
+            BulkOp::Index {
+                metadata: json!({"_index": "logs"}),
+                source: json!({"a": [1]}),
+            },
+            BulkOp::Delete {
+                metadata: json!({"_index": "logs", "_id": "1"}),
+            },
+            BulkOp::Update {
+                metadata: json!({"_id": "2"}),
+                body: json!({"doc": {"a": 2}}),
+            },
+        ];
+        for op in ops {
+            codec.encode(op, &mut buf).unwrap();
+        }
+        assert_eq!(
+            buf,
+            &br#"{"index":{"_index":"logs"}}
+{"a":[1]}
+{"delete":{"_id":"1","_index":"logs"}}
+{"update":{"_id":"2"}}
+{"doc":{"a":2}}
+"#[..]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Compressing individual frames.
+
+use bytes::{Bytes, BytesMut};
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Value};
+use std::io;
+use tokio_codec::{Decoder, Encoder};
+use {deserialize_frame, Codec, Error};
+
+/// A compression algorithm for [`Compressed`], such as gzip or zstd.
+///
+/// Gzip is provided as `Gzip` by the `gzip` feature.
+pub trait Compression {
+    /// Compresses `input`.
+    fn compress(&self, input: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decompresses `input`, stopping once the output is `limit` bytes long, so that a small
+    /// input can't decompress to an unbounded size.
+    fn decompress(&self, input: &[u8], limit: usize) -> io::Result<Vec<u8>>;
+}
+
+/// A [`Codec`] that compresses each frame, sending it in an envelope.
+///
+/// The envelope has a flag saying whether the frame is compressed: a compressed frame is sent
+/// base64-encoded, as in `{"compressed": true, "data": "..."}`, and a frame that isn't is sent as
+/// is, as in `{"compressed": false, "data": {...}}`. Frames smaller than the
+/// [threshold][`Compressed::threshold`] aren't compressed, so that small, latency-sensitive frames
+/// don't pay for compression. Both peers must use `Compressed`.
+///
+/// Created by [`Codec::compressed`].
+#[derive(Clone, Debug)]
+pub struct Compressed<D, E, C> {
+    codec: Codec<D, E>,
+    compression: C,
+    threshold: usize,
+}
+
+impl<D, E, C> Compressed<D, E, C> {
+    /// Set the size in bytes below which encoded frames aren't compressed. 0 by default, so that
+    /// every frame is compressed.
+    pub fn threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
+    /// Returns a reference to the compression algorithm.
+    pub fn compression(&self) -> &C {
+        &self.compression
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Compressed`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Records that a frame of `uncompressed` bytes was compressed to `compressed` bytes, or
+    /// decompressed from them.
+    fn compressed_frame(&mut self, uncompressed: usize, compressed: usize) {
+        self.uncompressed_bytes = self.uncompressed_bytes.saturating_add(uncompressed as u64);
+        self.compressed_bytes = self.compressed_bytes.saturating_add(compressed as u64);
+    }
+
+    /// Wraps the codec so that each frame is compressed with `compression`.
+    pub fn compressed<C>(self, compression: C) -> Compressed<D, E, C>
+    where
+        C: Compression,
+    {
+        Compressed {
+            codec: self,
+            compression,
+            threshold: 0,
+        }
+    }
+}
+
+impl<D, E, C> Decoder for Compressed<D, E, C>
+where
+    for<'de> D: Deserialize<'de>,
+    C: Compression,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let compression = &self.compression;
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(envelope) = codec.next_frame(src)? {
+                let envelope = deserialize_frame(envelope, false, codec.unbounded_depth)?;
+                let frame = open(codec, compression, envelope)?;
+                if let Some(item) = codec.decode_value(frame)? {
+                    return Ok(Some(item));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E, C> Encoder for Compressed<D, E, C>
+where
+    E: Serialize,
+    C: Compression,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        let (compression, threshold) = (&self.compression, self.threshold);
+        self.codec.encode_with(dst, |codec, dst| {
+            let mut frame = BytesMut::new();
+            codec.encode_frame(item, &mut frame)?;
+            if frame.len() < threshold {
+                dst.extend_from_slice(b"{\"compressed\":false,\"data\":");
+                dst.extend_from_slice(&frame);
+            } else {
+                let compressed = compression.compress(&frame)?;
+                codec.compressed_frame(frame.len(), compressed.len());
+                dst.extend_from_slice(b"{\"compressed\":true,\"data\":\"");
+                dst.extend_from_slice(base64_encode(&compressed).as_bytes());
+                dst.extend_from_slice(b"\"");
+            }
+            dst.extend_from_slice(b"}");
+            Ok(())
+        })
+    }
+}
+
+/// Takes the frame out of an envelope, decompressing it if needed.
+fn open<D, E, C>(codec: &mut Codec<D, E>, compression: &C, envelope: Value) -> Result<Bytes, Error>
+where
+    C: Compression,
+{
+    match (envelope.get("compressed"), envelope.get("data")) {
+        (Some(Value::Bool(true)), Some(Value::String(data))) => {
+            let compressed = base64_decode(data.as_bytes())
+                .ok_or_else(|| serde_json::Error::custom("invalid base64 in a compressed frame"))?;
+            // Decompress one byte past the maximum frame size, to tell whether it is exceeded.
+            let max = codec.max_frame_size;
+            let frame =
+                compression.decompress(&compressed, max.map_or(usize::MAX, |max| max + 1))?;
+            if let Some(max) = max.filter(|&max| frame.len() > max) {
+                return Err(Error::FrameTooLarge {
+                    max,
+                    resynced: true,
+                });
+            }
+            codec.compressed_frame(frame.len(), compressed.len());
+            Ok(frame.into())
+        }
+        (Some(Value::Bool(false)), Some(data)) => Ok(serde_json::to_vec(data)?.into()),
+        _ => Err(serde_json::Error::custom("expected a compression envelope").into()),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `input` as standard base64 with padding.
+fn base64_encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(char::from(BASE64[(n >> (18 - 6 * i)) as usize & 63]));
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Decodes standard base64 with padding, returning `None` if `input` isn't valid.
+fn base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    for (index, chunk) in input.chunks(4).enumerate() {
+        let last = index == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &b in &chunk[..4 - padding] {
+            let digit = BASE64.iter().position(|&d| d == b)?;
+            n = n << 6 | digit as u32;
+        }
+        n <<= 6 * padding as u32;
+        output.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_decode, base64_encode, Compression};
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use std::io;
+    use tokio_codec::{Decoder, Encoder};
+    use Codec;
+
+    /// A stand-in for a real algorithm, which reverses its input.
+    struct Reverse;
+
+    impl Compression for Reverse {
+        fn compress(&self, input: &[u8]) -> io::Result<Vec<u8>> {
+            Ok(input.iter().rev().cloned().collect())
+        }
+
+        fn decompress(&self, input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+            Ok(input.iter().rev().take(limit).cloned().collect())
+        }
+    }
+
+    #[test]
+    fn base64() {
+        for (raw, encoded) in &[
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64_encode(raw.as_bytes()), *encoded);
+            assert_eq!(base64_decode(encoded.as_bytes()).unwrap(), raw.as_bytes());
+        }
+        assert_eq!(base64_decode(b"Zg==Zg=="), None);
+        assert_eq!(base64_decode(b"Z!=="), None);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut codec = Codec::<Value, Value>::default().compressed(Reverse);
+        codec.threshold(3);
+        let mut buf = BytesMut::new();
+        codec.encode(json!(1), &mut buf).unwrap();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        assert_eq!(
+            buf,
+            "{\"compressed\":false,\"data\":1}{\"compressed\":true,\"data\":\"XTFb\"}"
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(1)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        let stats = codec.codec().stats_snapshot();
+        assert_eq!((stats.uncompressed_bytes, stats.compressed_bytes), (6, 6));
+        assert_eq!(stats.compression_ratio(), Some(1.0));
+        buf.extend_from_slice(b"{\"data\": 1}");
+        assert!(codec.decode(&mut buf).is_err());
+    }
+}
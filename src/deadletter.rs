@@ -0,0 +1,211 @@
+//! Forwarding frames that fail to decode to a dead-letter sink.
+
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use std::error::Error as StdError;
+use std::io;
+use Error;
+
+/// A stream that forwards the errors for frames that fail to decode to a dead-letter sink, such
+/// as a channel to a quarantine task, rather than failing, and carries on with the next frame.
+///
+/// As with [`Lossy`][`::Lossy`], only errors that lose nothing but the frame they occurred in are
+/// forwarded; the stream still fails with those that [are fatal][`Error::is_fatal`]. To forward
+/// the raw bytes of each frame along with its error, set
+/// [`Codec::capture_failed_frames`][`::Codec::capture_failed_frames`], and use [`Error::frame`].
+///
+/// While the dead-letter sink isn't ready for an error, the stream waits for it rather than
+/// dropping the error, and the stream ends once the sink has been flushed. The stream fails with
+/// an [`Error::Io`] if the sink does. The underlying sink, if any, is passed through.
+///
+/// Created by [`FramedJson::with_dead_letters`][`::FramedJson::with_dead_letters`], or by
+/// [`DeadLetters::new`] for other streams of decoded items.
+#[derive(Debug)]
+pub struct DeadLetters<S, K> {
+    stream: S,
+    dead_letters: K,
+    pending: Option<Error>,
+    /// Whether the underlying stream has ended.
+    done: bool,
+}
+
+impl<S, K> DeadLetters<S, K> {
+    /// Creates a `DeadLetters` yielding the items of `stream` and forwarding its non-fatal errors
+    /// to `dead_letters`.
+    pub fn new(stream: S, dead_letters: K) -> Self {
+        DeadLetters {
+            stream,
+            dead_letters,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Returns a reference to the dead-letter sink.
+    pub fn dead_letters(&self) -> &K {
+        &self.dead_letters
+    }
+
+    /// Consumes the `DeadLetters`, returning the underlying stream and the dead-letter sink. An
+    /// error that is yet to be forwarded is dropped.
+    pub fn into_inner(self) -> (S, K) {
+        (self.stream, self.dead_letters)
+    }
+}
+
+/// Converts an error from the dead-letter sink into an I/O error.
+fn sink_error<E>(e: E) -> Error
+where
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    Error::Io(io::Error::other(e))
+}
+
+impl<S, K> Stream for DeadLetters<S, K>
+where
+    S: Stream<Error = Error>,
+    K: Sink<SinkItem = Error>,
+    K::SinkError: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Item = S::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, Error> {
+        loop {
+            if let Some(e) = self.pending.take() {
+                if let AsyncSink::NotReady(e) =
+                    self.dead_letters.start_send(e).map_err(sink_error)?
+                {
+                    self.pending = Some(e);
+                    return Ok(Async::NotReady);
+                }
+            }
+            // Errors already handed over are flushed as the stream is polled.
+            let flushed = self.dead_letters.poll_complete().map_err(sink_error)?;
+            if self.done {
+                return Ok(flushed.map(|()| None));
+            }
+            match self.stream.poll() {
+                Ok(Async::Ready(None)) => self.done = true,
+                Ok(ready) => return Ok(ready),
+                Err(e) if e.is_fatal() => return Err(e),
+                Err(e) => self.pending = Some(e),
+            }
+        }
+    }
+}
+
+impl<S, K> Sink for DeadLetters<S, K>
+where
+    S: Sink,
+{
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: S::SinkItem) -> StartSend<S::SinkItem, S::SinkError> {
+        self.stream.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeadLetters;
+    use futures::stream::{iter_result, poll_fn};
+    use futures::sync::mpsc;
+    use futures::{task, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+    use std::io::{self, Cursor};
+    use {Codec, Error, ErrorKind, FramedJson, Framing};
+
+    #[test]
+    fn dead_letters() {
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.framing(Framing::delimited(b"\n"));
+        codec.capture_failed_frames(Some(16));
+        let input = b"1\n\"a\"\n?\n4\n".to_vec();
+        let (tx, rx) = mpsc::unbounded();
+        let items: Vec<_> = FramedJson::new(Cursor::new(input), codec)
+            .with_dead_letters(tx)
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(items, vec![1, 4]);
+        let dead: Vec<_> = rx.take(2).collect().wait().unwrap();
+        let dead: Vec<_> = dead
+            .iter()
+            .map(|e| (e.kind(), &e.frame().unwrap()[..]))
+            .collect();
+        assert_eq!(
+            dead,
+            vec![
+                (ErrorKind::Data, &b"\"a\""[..]),
+                (ErrorKind::Syntax, &b"?"[..])
+            ]
+        );
+
+        // The stream fails if the dead-letter sink does.
+        let (tx, rx) = mpsc::unbounded();
+        drop(rx);
+        let stream = iter_result(vec![Ok(1), Err(too_large())]);
+        let err = DeadLetters::new(stream, tx).collect().wait().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+
+        // Once the stream has ended, only the dead-letter sink is polled until it is flushed.
+        let mut results = vec![Ok(Async::Ready(Some(1))), Err(too_large())].into_iter();
+        let mut ended = false;
+        let stream = poll_fn(move || {
+            results.next().unwrap_or_else(|| {
+                assert!(!ended, "stream polled after it ended");
+                ended = true;
+                Ok(Async::Ready(None))
+            })
+        });
+        let items = DeadLetters::new(stream, SlowFlush(0)).collect().wait();
+        assert_eq!(items.unwrap(), vec![1]);
+    }
+
+    fn too_large() -> Error {
+        Error::FrameTooLarge {
+            max: 1,
+            resynced: true,
+        }
+    }
+
+    /// A dead-letter sink that takes a few polls to flush each error.
+    struct SlowFlush(usize);
+
+    impl Sink for SlowFlush {
+        type SinkItem = Error;
+        type SinkError = io::Error;
+
+        fn start_send(&mut self, _: Error) -> StartSend<Error, io::Error> {
+            self.0 = 3;
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), io::Error> {
+            if self.0 == 0 {
+                return Ok(Async::Ready(()));
+            }
+            self.0 -= 1;
+            task::current().notify();
+            Ok(Async::NotReady)
+        }
+    }
+}
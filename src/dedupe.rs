@@ -0,0 +1,75 @@
+//! Dropping frames whose id was seen recently.
+
+use serde_json::Value;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// A cache of recently seen frame ids; see [`Codec::deduplicate`][`::Codec::deduplicate`].
+#[derive(Clone, Debug)]
+pub(crate) struct Dedupe {
+    pointer: String,
+    ttl: Duration,
+    seen: HashSet<String>,
+    expiry: VecDeque<(Instant, String)>,
+    pub(crate) duplicates: u64,
+}
+
+impl Dedupe {
+    pub(crate) fn new(pointer: &str, ttl: Duration) -> Self {
+        Dedupe {
+            pointer: pointer.to_owned(),
+            ttl,
+            seen: HashSet::new(),
+            expiry: VecDeque::new(),
+            duplicates: 0,
+        }
+    }
+
+    /// Returns whether `frame` has the same id as a frame seen within the TTL, recording its id
+    /// if not. Frames without an id are never duplicates.
+    pub(crate) fn is_duplicate(&mut self, frame: &Value, now: Instant) -> bool {
+        while self
+            .expiry
+            .front()
+            .is_some_and(|&(expires, _)| expires <= now)
+        {
+            if let Some((_, id)) = self.expiry.pop_front() {
+                self.seen.remove(&id);
+            }
+        }
+        // Ids are compared in their serialized form, so `1` and `"1"` are distinct.
+        let id = match frame.pointer(&self.pointer) {
+            Some(id) => id.to_string(),
+            None => return false,
+        };
+        if self.seen.contains(&id) {
+            self.duplicates = self.duplicates.saturating_add(1);
+            return true;
+        }
+        self.seen.insert(id.clone());
+        self.expiry.push_back((now + self.ttl, id));
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dedupe;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ttl() {
+        let mut dedupe = Dedupe::new("/id", Duration::from_secs(10));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert!(!dedupe.is_duplicate(&json!({"id": 1}), at(0)));
+        assert!(!dedupe.is_duplicate(&json!({"id": "1"}), at(1)));
+        assert!(dedupe.is_duplicate(&json!({"id": 1}), at(9)));
+        assert!(!dedupe.is_duplicate(&json!({}), at(9)));
+        assert!(!dedupe.is_duplicate(&json!({}), at(9)));
+        assert!(!dedupe.is_duplicate(&json!({"id": 1}), at(10)));
+        assert!(dedupe.is_duplicate(&json!({"id": "1"}), at(10)));
+        assert_eq!(dedupe.duplicates, 2);
+        assert_eq!(dedupe.seen.len(), 2);
+    }
+}
@@ -0,0 +1,83 @@
+//! [`miette`] diagnostics (requires the `miette` feature).
+
+use error::Input;
+use miette::{Diagnostic, LabeledSpan, MietteError, SourceCode, SourceSpan, SpanContents};
+use std::fmt::Display;
+use {DecodeError, Error};
+
+impl SourceCode for Input {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError> {
+        self.0[..].read_span(span, context_lines_before, context_lines_after)
+    }
+}
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(format!("tokio_jsoncodec::{}", self.kind())))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        match self.unlabeled() {
+            Error::Decode(e) => e.source_code(),
+            _ => None,
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match self.unlabeled() {
+            Error::Decode(e) => e.labels(),
+            _ => None,
+        }
+    }
+}
+
+impl Diagnostic for DecodeError {
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.input)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        // The position is conveyed by the label itself, so strip it from the message.
+        let mut message = self.json().to_string();
+        if let Some(at) = message.rfind(" at line ") {
+            message.truncate(at);
+        }
+        let len = if self.offset() < self.input().len() {
+            1
+        } else {
+            0
+        };
+        Some(Box::new(
+            Some(LabeledSpan::new(Some(message), self.offset(), len)).into_iter(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use miette::Diagnostic;
+    use serde_json::Value;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn decode_diagnostic() {
+        let mut buf = BytesMut::from(&b"{\"butts\": lol}"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.code().unwrap().to_string(), "tokio_jsoncodec::syntax");
+        let labels: Vec<_> = err.labels().unwrap().collect();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].label(), Some("expected value"));
+        assert_eq!(labels[0].offset(), 10);
+        let source = err.source_code().unwrap();
+        let span = source.read_span(labels[0].inner(), 0, 0).unwrap();
+        assert_eq!(span.data(), &b"l"[..]);
+    }
+}
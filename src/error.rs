@@ -0,0 +1,1000 @@
+#[cfg(feature = "arrow")]
+use arrow_schema::ArrowError;
+use bytes::Bytes;
+#[cfg(feature = "json-patch")]
+use json_patch;
+use lex::is_whitespace;
+use serde_json;
+use serde_json::error::Category;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The [`Error`][`std::error::Error`] type for this crate.
+///
+/// This is necessary to not lose information about the error. [`Encoder`][`tokio_codec::Encoder`] requires that the Error
+/// implement `From<std::io::Error>`, and while a [`serde_json::Error`] can possibly be an IO
+/// error, there's no way to combine the two.
+///
+/// If you just want an [`io::Error`], `From<Error>` is implemented for it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A [`io::Error`].
+    Io(io::Error),
+    /// A [`serde_json::Error`].
+    Json(serde_json::Error),
+    /// A frame failed to decode.
+    Decode(DecodeError),
+    /// A value that had already been parsed failed to deserialize (requires the
+    /// `serde_path_to_error` feature).
+    ///
+    /// Errors deserializing a frame straight from the input are [`Error::Decode`] instead, with
+    /// the path in [`DecodeError::path`].
+    #[cfg(feature = "serde_path_to_error")]
+    Path {
+        /// The path of the value, such as `items[3].price`.
+        path: String,
+        /// The error itself.
+        error: serde_json::Error,
+    },
+    /// The input broke the rules of the codec's [framing][`::Codec::framing`], such as with an
+    /// invalid length prefix.
+    Framing {
+        /// What was wrong with the input.
+        message: &'static str,
+        /// Whether decoding skipped ahead to where the next frame should start, so that it can
+        /// carry on.
+        resynced: bool,
+    },
+    /// A frame contained a field that the decoded type doesn't know about; see
+    /// [`Codec::deny_unknown_fields`][`::Codec::deny_unknown_fields`].
+    UnknownField {
+        /// The path of the field, such as `items.3.price`.
+        path: String,
+    },
+    /// An object in a frame had the same key more than once; see
+    /// [`Codec::deny_duplicate_keys`][`::Codec::deny_duplicate_keys`].
+    DuplicateKey {
+        /// The path of the repeated member, such as `items.3.price`.
+        path: String,
+    },
+    /// A frame's top-level value had a type that isn't allowed; see
+    /// [`Codec::top_level_types`][`::Codec::top_level_types`].
+    UnexpectedType {
+        /// The type of the value.
+        found: JsonType,
+    },
+    /// A value in a frame exceeded a configured [`Limit`].
+    LimitExceeded {
+        /// The limit that was exceeded.
+        limit: Limit,
+        /// The path of the offending value, such as `items.3.price`, or `.` for the frame itself.
+        path: String,
+    },
+    /// The input exceeded `serde_json`'s recursion limit; see
+    /// [`Codec::unbounded_depth`][`::Codec::unbounded_depth`].
+    ///
+    /// This is usually a sign of hostile input rather than an ordinary malformed frame.
+    DepthExceeded {
+        /// How many bytes of the input had been consumed when the limit was reached.
+        consumed: usize,
+    },
+    /// A frame was larger than the configured maximum; see
+    /// [`Codec::max_frame_size`][`::Codec::max_frame_size`].
+    FrameTooLarge {
+        /// The maximum frame size, in bytes.
+        max: usize,
+        /// Whether decoding can carry on with the next frame. It can't if the frame was still
+        /// incomplete, and was discarded with the rest of the buffered input, with a framing that
+        /// gives no way to find where the next frame starts, such as [`::Framing::Concatenated`].
+        resynced: bool,
+    },
+    /// Too many frames failed to decode; see
+    /// [`Codec::max_malformed_frames`][`::Codec::max_malformed_frames`].
+    TooManyMalformedFrames {
+        /// How many frames failed to decode.
+        count: u64,
+    },
+    /// The codec decoded more bytes than its quota; see
+    /// [`Codec::byte_quota`][`::Codec::byte_quota`].
+    QuotaExceeded {
+        /// The quota, in bytes.
+        quota: u64,
+    },
+    /// The codec decoded more frames than its quota; see
+    /// [`Codec::frame_quota`][`::Codec::frame_quota`].
+    FrameQuotaExceeded {
+        /// The quota, in frames.
+        quota: u64,
+    },
+    /// Items arrived faster than the configured rate; see
+    /// [`FramedJson::max_frame_rate`][`::FramedJson::max_frame_rate`].
+    RateLimited {
+        /// The maximum number of items per second.
+        max: u32,
+    },
+    /// The input ended in the middle of a frame, such as when a connection is closed or an
+    /// upload is cut short.
+    IncompleteFrame {
+        /// How many bytes of the input were left over.
+        pending: usize,
+        /// The start of the left-over input, with control characters escaped.
+        snippet: String,
+    },
+    /// No item arrived in time; see [`TimedFramed`][`::TimedFramed`].
+    Timeout {
+        /// The time limit.
+        timeout: Duration,
+        /// Whether this was the [handshake timeout][`::TimedFramed::handshake_timeout`], for the
+        /// first item.
+        handshake: bool,
+    },
+    /// An error in a frame, along with the frame's raw bytes; see
+    /// [`Codec::capture_failed_frames`][`::Codec::capture_failed_frames`].
+    ///
+    /// Its `Display` output is that of the error itself.
+    WithFrame {
+        /// The raw bytes of the frame, up to the configured maximum.
+        frame: Bytes,
+        /// The length of the whole frame, which is longer than `frame` if it was cut short.
+        len: usize,
+        /// The error itself.
+        error: Box<Error>,
+    },
+    /// An error produced by a codec with a [peer label][`::Codec::peer_label`].
+    Labeled {
+        /// The codec's peer label, such as the peer's address.
+        label: Arc<str>,
+        /// The error itself.
+        error: Box<Error>,
+    },
+    /// Decoded items couldn't be converted into a record batch; see
+    /// [`RecordBatches`][`::RecordBatches`] (requires the `arrow` feature).
+    #[cfg(feature = "arrow")]
+    Arrow(ArrowError),
+    /// A patch couldn't be applied to the current state; see [`Patched`][`::Patched`] (requires
+    /// the `json-patch` feature).
+    #[cfg(feature = "json-patch")]
+    Patch(json_patch::PatchError),
+    /// A frame wasn't valid against the codec's [schema][`::Codec::schema`] (requires the
+    /// `jsonschema` feature).
+    #[cfg(feature = "jsonschema")]
+    SchemaViolation {
+        /// The JSON Pointer to the invalid value, such as `/items/3`, or an empty string for the
+        /// frame itself.
+        path: String,
+        /// What is wrong with the value.
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+            Error::Decode(e) => e.fmt(f),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { path, error } => write!(f, "{}: {}", path, error),
+            Error::Framing { message, .. } => f.write_str(message),
+            Error::UnknownField { path } => write!(f, "unknown field `{}`", path),
+            Error::DuplicateKey { path } => write!(f, "duplicate key `{}`", path),
+            Error::UnexpectedType { found } => write!(f, "top-level {} not allowed", found),
+            Error::LimitExceeded { limit, path } => write!(f, "{} at `{}`", limit, path),
+            Error::DepthExceeded { consumed } => {
+                write!(f, "recursion limit exceeded after {} bytes", consumed)
+            }
+            Error::FrameTooLarge { max, .. } => write!(f, "frame larger than {} bytes", max),
+            Error::TooManyMalformedFrames { count } => {
+                write!(f, "giving up after {} malformed frames", count)
+            }
+            Error::QuotaExceeded { quota } => write!(f, "byte quota of {} exceeded", quota),
+            Error::FrameQuotaExceeded { quota } => write!(f, "frame quota of {} exceeded", quota),
+            Error::RateLimited { max } => write!(f, "more than {} frames per second", max),
+            Error::IncompleteFrame { pending, snippet } => write!(
+                f,
+                "input ended in an incomplete frame of {} bytes: `{}`",
+                pending, snippet
+            ),
+            Error::Timeout { timeout, handshake } => {
+                let first = if *handshake { "first " } else { "" };
+                write!(f, "no {}frame received within {:?}", first, timeout)
+            }
+            Error::WithFrame { error, .. } => error.fmt(f),
+            Error::Labeled { label, error } => write!(f, "[{}] {}", label, error),
+            #[cfg(feature = "arrow")]
+            Error::Arrow(e) => e.fmt(f),
+            #[cfg(feature = "json-patch")]
+            Error::Patch(e) => e.fmt(f),
+            #[cfg(feature = "jsonschema")]
+            Error::SchemaViolation { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    /// Returns the source of the error this one wraps, such as the [`io::Error`] of an
+    /// [`Error::Io`] or the unlabeled error of an [`Error::Labeled`].
+    ///
+    /// The wrapped error itself isn't returned, since its message is already part of this one's,
+    /// and error reporters would show it twice; [`Error::as_io`] and [`Error::as_json`] return it.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => e.source(),
+            Error::Json(e) => e.source(),
+            Error::Decode(e) => e.source(),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { error, .. } => error.source(),
+            Error::WithFrame { error, .. } | Error::Labeled { error, .. } => error.source(),
+            #[cfg(feature = "arrow")]
+            Error::Arrow(e) => e.source(),
+            #[cfg(feature = "json-patch")]
+            Error::Patch(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the [`ErrorKind`] classifying this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(e) => io_kind(e),
+            Error::Json(e) => json_kind(e),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { error, .. } => json_kind(error),
+            Error::Decode(e) => json_kind(&e.error),
+            Error::Framing { .. } => ErrorKind::Framing,
+            Error::UnknownField { .. } => ErrorKind::Data,
+            Error::DuplicateKey { .. } => ErrorKind::Data,
+            Error::UnexpectedType { .. } => ErrorKind::Data,
+            Error::LimitExceeded {
+                limit: Limit::Depth(_),
+                ..
+            } => ErrorKind::Depth,
+            Error::LimitExceeded { .. } => ErrorKind::TooLarge,
+            Error::DepthExceeded { .. } => ErrorKind::Depth,
+            Error::FrameTooLarge { .. } => ErrorKind::TooLarge,
+            Error::TooManyMalformedFrames { .. } => ErrorKind::TooManyErrors,
+            Error::QuotaExceeded { .. } | Error::FrameQuotaExceeded { .. } => {
+                ErrorKind::QuotaExceeded
+            }
+            Error::RateLimited { .. } => ErrorKind::RateLimited,
+            Error::IncompleteFrame { .. } => ErrorKind::Eof,
+            Error::Timeout { .. } => ErrorKind::Timeout,
+            Error::WithFrame { error, .. } | Error::Labeled { error, .. } => error.kind(),
+            #[cfg(feature = "arrow")]
+            Error::Arrow(_) => ErrorKind::Data,
+            #[cfg(feature = "json-patch")]
+            Error::Patch(_) => ErrorKind::Data,
+            #[cfg(feature = "jsonschema")]
+            Error::SchemaViolation { .. } => ErrorKind::Data,
+        }
+    }
+
+    /// Returns the [peer label][`::Codec::peer_label`] of the codec that produced this error, if
+    /// it has one.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Error::Labeled { label, .. } => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the error leaves the codec unable to carry on decoding the stream, so that
+    /// the connection should be dropped.
+    ///
+    /// Otherwise, only the frame the error occurred in was lost, and decoding can carry on with
+    /// the next one. Syntax errors are fatal unless the frame had already been split off, as it is
+    /// with delimited framing, or the codec [resynchronized][`::Codec::resync`], since the codec
+    /// can't otherwise tell where the next frame starts. For the same reason, so is an
+    /// [`Error::FrameTooLarge`] for an incomplete frame that couldn't be skipped. Errors
+    /// that mean the peer should be given up on, such as [`Error::TooManyMalformedFrames`], a
+    /// quota being exceeded, or `serde_json`'s recursion limit being hit
+    /// ([`Error::DepthExceeded`]), are fatal too. A frame over the codec's own
+    /// [depth limit][`::Codec::max_depth`] is checked before it is parsed, so only it is lost.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Error::Io(_) => true,
+            Error::Json(e) => e.is_io(),
+            Error::Decode(e) => !e.in_frame && !e.resynced,
+            Error::Framing { resynced, .. } | Error::FrameTooLarge { resynced, .. } => !resynced,
+            Error::DepthExceeded { .. }
+            | Error::TooManyMalformedFrames { .. }
+            | Error::QuotaExceeded { .. }
+            | Error::FrameQuotaExceeded { .. }
+            | Error::IncompleteFrame { .. } => true,
+            Error::WithFrame { error, .. } | Error::Labeled { error, .. } => error.is_fatal(),
+            _ => false,
+        }
+    }
+
+    /// Returns the underlying [`io::Error`], if this is an [`Error::Io`].
+    pub fn as_io(&self) -> Option<&io::Error> {
+        match self.unlabeled() {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying [`serde_json::Error`], if this is an error from `serde_json`, such
+    /// as an [`Error::Json`] or [`Error::Decode`].
+    pub fn as_json(&self) -> Option<&serde_json::Error> {
+        match self.unlabeled() {
+            Error::Json(e) => Some(e),
+            Error::Decode(e) => Some(&e.error),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Consumes the error, returning the underlying [`io::Error`] like [`Error::as_io`], or the
+    /// error itself if there isn't one.
+    pub fn into_io(self) -> Result<io::Error, Error> {
+        match self {
+            Error::Io(e) => Ok(e),
+            Error::WithFrame { frame, len, error } => {
+                error.into_io().map_err(|error| Error::WithFrame {
+                    frame,
+                    len,
+                    error: Box::new(error),
+                })
+            }
+            Error::Labeled { label, error } => error.into_io().map_err(|error| Error::Labeled {
+                label,
+                error: Box::new(error),
+            }),
+            e => Err(e),
+        }
+    }
+
+    /// Consumes the error, returning the underlying [`serde_json::Error`] like
+    /// [`Error::as_json`], or the error itself if there isn't one.
+    pub fn into_json(self) -> Result<serde_json::Error, Error> {
+        match self {
+            Error::Json(e) => Ok(e),
+            Error::Decode(e) => Ok(e.error),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { error, .. } => Ok(error),
+            Error::WithFrame { frame, len, error } => {
+                error.into_json().map_err(|error| Error::WithFrame {
+                    frame,
+                    len,
+                    error: Box::new(error),
+                })
+            }
+            Error::Labeled { label, error } => error.into_json().map_err(|error| Error::Labeled {
+                label,
+                error: Box::new(error),
+            }),
+            e => Err(e),
+        }
+    }
+
+    /// Returns the raw bytes of the frame the error occurred in, if they were
+    /// [captured][`::Codec::capture_failed_frames`].
+    pub fn frame(&self) -> Option<&Bytes> {
+        match self {
+            Error::WithFrame { frame, .. } => Some(frame),
+            Error::Labeled { error, .. } => error.frame(),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without its peer label or captured frame, for matching on what went
+    /// wrong.
+    pub fn unlabeled(&self) -> &Error {
+        match self {
+            Error::WithFrame { error, .. } | Error::Labeled { error, .. } => error.unlabeled(),
+            e => e,
+        }
+    }
+
+    /// Converts an error from deserializing `input` into an `Error`.
+    pub(crate) fn decode(err: serde_json::Error, input: Bytes) -> Self {
+        let consumed = consumed(&input, err.line(), err.column());
+        if json_kind(&err) == ErrorKind::Depth {
+            Error::DepthExceeded { consumed }
+        } else {
+            Error::Decode(DecodeError {
+                error: err,
+                input: Input(input),
+                offset: consumed.saturating_sub(1),
+                context: false,
+                in_frame: false,
+                start: None,
+                frame: None,
+                path: None,
+                resynced: false,
+            })
+        }
+    }
+
+    /// Creates an [`Error::IncompleteFrame`] for the input left over at the end of the stream.
+    pub(crate) fn incomplete_frame(pending: &[u8]) -> Self {
+        Error::IncompleteFrame {
+            pending: pending.len(),
+            snippet: render(&pending[..pending.len().min(2 * EXCERPT_RADIUS)]),
+        }
+    }
+
+    /// Converts an error from deserializing `input` into an `Error`, like [`Error::decode`],
+    /// passing through errors that aren't from `serde_json`.
+    pub(crate) fn in_input(self, input: Bytes) -> Self {
+        match self {
+            Error::Json(e) => Error::decode(e, input),
+            #[cfg(feature = "serde_path_to_error")]
+            Error::Path { path, error } => match Error::decode(error, input) {
+                Error::Decode(mut e) => {
+                    e.path = Some(path);
+                    Error::Decode(e)
+                }
+                e => e,
+            },
+            e => e,
+        }
+    }
+}
+
+/// A limit on the values in a frame, checked before the frame is deserialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Limit {
+    /// A string or object key was longer than this many bytes, as they appear in the input; see
+    /// [`Codec::max_string_len`][`::Codec::max_string_len`].
+    StringLength(usize),
+    /// A number was longer than this many bytes, as it appears in the input; see
+    /// [`Codec::max_number_len`][`::Codec::max_number_len`].
+    NumberLength(usize),
+    /// An array had more than this many elements; see
+    /// [`Codec::max_array_len`][`::Codec::max_array_len`].
+    ArrayLength(usize),
+    /// An object had more than this many members; see
+    /// [`Codec::max_object_len`][`::Codec::max_object_len`].
+    ObjectLength(usize),
+    /// Arrays and objects were nested more than this many levels deep; see
+    /// [`Codec::max_depth`][`::Codec::max_depth`].
+    Depth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::StringLength(max) => write!(f, "string longer than {} bytes", max),
+            Limit::NumberLength(max) => write!(f, "number longer than {} bytes", max),
+            Limit::ArrayLength(max) => write!(f, "array with more than {} elements", max),
+            Limit::ObjectLength(max) => write!(f, "object with more than {} members", max),
+            Limit::Depth(max) => write!(f, "nested more than {} levels deep", max),
+        }
+    }
+}
+
+/// The type of a JSON value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum JsonType {
+    /// An object.
+    Object,
+    /// An array.
+    Array,
+    /// A string.
+    String,
+    /// A number.
+    Number,
+    /// `true` or `false`.
+    Bool,
+    /// `null`.
+    Null,
+}
+
+impl JsonType {
+    /// Returns the type of the value at the start of `input`, after any whitespace, judging by
+    /// its first byte.
+    pub(crate) fn of(input: &[u8]) -> Option<JsonType> {
+        match input.iter().find(|&&b| !is_whitespace(b))? {
+            b'{' => Some(JsonType::Object),
+            b'[' => Some(JsonType::Array),
+            b'"' => Some(JsonType::String),
+            b'-' | b'0'..=b'9' => Some(JsonType::Number),
+            b't' | b'f' => Some(JsonType::Bool),
+            b'n' => Some(JsonType::Null),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            JsonType::Object => "object",
+            JsonType::Array => "array",
+            JsonType::String => "string",
+            JsonType::Number => "number",
+            JsonType::Bool => "boolean",
+            JsonType::Null => "null",
+        })
+    }
+}
+
+/// An error deserializing a frame, along with the input it occurred in.
+#[derive(Debug)]
+pub struct DecodeError {
+    error: serde_json::Error,
+    pub(crate) input: Input,
+    offset: usize,
+    pub(crate) context: bool,
+    /// Whether the input is a frame that was split off, rather than the buffer.
+    pub(crate) in_frame: bool,
+    /// The stream offset of the start of the input, if known.
+    pub(crate) start: Option<u64>,
+    /// The number of the frame, counting from 1, if known.
+    pub(crate) frame: Option<u64>,
+    path: Option<String>,
+    /// Whether decoding skipped past the error; see [`Codec::resync`][`::Codec::resync`].
+    pub(crate) resynced: bool,
+}
+
+impl DecodeError {
+    /// Returns the underlying [`serde_json::Error`].
+    pub fn json(&self) -> &serde_json::Error {
+        &self.error
+    }
+
+    /// Returns the buffered input, starting with the frame that failed to decode.
+    pub fn input(&self) -> &[u8] {
+        &(self.input.0)[..]
+    }
+
+    /// Returns the byte offset within [`DecodeError::input`] at which the error was detected.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns an excerpt of the input around the error, of the line it is on and at most 32
+    /// bytes either side of it, with invalid UTF-8 replaced and control characters escaped.
+    ///
+    /// This is what [`Codec::error_context`][`::Codec::error_context`] adds to the `Display`
+    /// output, without the caret line.
+    pub fn excerpt(&self) -> String {
+        excerpt(self.input(), self.offset, EXCERPT_RADIUS).0
+    }
+
+    /// Returns the offset in the stream at which the error was detected, counting the bytes the
+    /// codec has consumed while decoding, as [`Codec::decoded_bytes`][`::Codec::decoded_bytes`]
+    /// does, or `None` if it isn't known.
+    ///
+    /// For a frame that didn't come straight from the input, such as one that was
+    /// [decompressed][`::Compressed`], this is an offset within the frame as received.
+    pub fn stream_offset(&self) -> Option<u64> {
+        self.start
+            .map(|start| start.saturating_add(self.offset as u64))
+    }
+
+    /// Returns the number of the frame that failed to decode, counting from 1 and including
+    /// frames that failed or were dropped, or `None` if it isn't known.
+    ///
+    /// Along with [`DecodeError::frame_start`], this identifies the record that failed in a
+    /// large input, such as an NDJSON dump.
+    pub fn frame_number(&self) -> Option<u64> {
+        self.frame
+    }
+
+    /// Returns the offset in the stream at which the input starts, which is how many bytes the
+    /// codec had consumed before the frame, or `None` if it isn't known.
+    pub fn frame_start(&self) -> Option<u64> {
+        self.start
+    }
+
+    /// Returns the line within the frame at which the error was detected, counting from 1.
+    pub fn line(&self) -> usize {
+        self.line_column().0
+    }
+
+    /// Returns the column within the line at which the error was detected, in bytes counting
+    /// from 1.
+    pub fn column(&self) -> usize {
+        self.line_column().1
+    }
+
+    /// Returns the line and column of the error, counting from the start of the frame rather
+    /// than any whitespace before it in the input.
+    fn line_column(&self) -> (usize, usize) {
+        let input = self.input();
+        let offset = self.offset.min(input.len());
+        let start = input[..offset]
+            .iter()
+            .take_while(|&&b| is_whitespace(b))
+            .count();
+        let before = &input[start..offset];
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(start, |i| start + i + 1);
+        (line, offset - line_start + 1)
+    }
+
+    /// Returns the path of the value that failed to deserialize, such as `items[3].price`, or
+    /// `None` for the frame itself (requires the `serde_path_to_error` feature).
+    #[cfg(feature = "serde_path_to_error")]
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Consumes the error, returning the underlying [`serde_json::Error`].
+    pub fn into_json(self) -> serde_json::Error {
+        self.error
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref path) = self.path {
+            write!(f, "{}: ", path)?;
+        }
+        self.error.fmt(f)?;
+        if self.context {
+            let (excerpt, caret) = excerpt(self.input(), self.offset, EXCERPT_RADIUS);
+            write!(f, "\n  {}\n  {:caret$}^", excerpt, "", caret = caret)?;
+        }
+        Ok(())
+    }
+}
+
+/// How many bytes either side of the error position an excerpt covers.
+const EXCERPT_RADIUS: usize = 32;
+
+/// Renders the line around `offset`, up to `radius` bytes either side of it, escaping control
+/// characters. Also returns the column of `offset` within the rendering.
+fn excerpt(input: &[u8], offset: usize, radius: usize) -> (String, usize) {
+    let offset = offset.min(input.len());
+    let start = input[..offset]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1)
+        .max(offset.saturating_sub(radius));
+    let end = input[offset..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(input.len(), |i| offset + i)
+        .min(offset + radius);
+    let before = render(&input[start..offset]);
+    let caret = before.chars().count();
+    (before + &render(&input[offset..end]), caret)
+}
+
+/// Renders `bytes` as text, escaping control characters so that it stays on one line.
+fn render(bytes: &[u8]) -> String {
+    let mut rendered = String::new();
+    for c in String::from_utf8_lossy(bytes).chars() {
+        if c.is_control() {
+            rendered.extend(c.escape_default());
+        } else {
+            rendered.push(c);
+        }
+    }
+    rendered
+}
+
+impl std::error::Error for DecodeError {
+    /// Returns the source of the `serde_json` error, whose message is already part of this one's.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// The input a [`DecodeError`] occurred in.
+#[derive(Debug)]
+pub(crate) struct Input(pub(crate) Bytes);
+
+/// Converts a one-based line and column reported by serde_json into a number of bytes consumed.
+fn consumed(input: &[u8], line: usize, column: usize) -> usize {
+    let line_start = if line > 1 {
+        input
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'\n')
+            .nth(line - 2)
+            .map_or(input.len(), |(i, _)| i + 1)
+    } else {
+        0
+    };
+    (line_start + column).min(input.len())
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Self {
+        Error::Arrow(err)
+    }
+}
+
+#[cfg(feature = "json-patch")]
+impl From<json_patch::PatchError> for Error {
+    fn from(err: json_patch::PatchError) -> Self {
+        Error::Patch(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => e,
+            Error::Json(e) => e.into(),
+            #[cfg(feature = "serde_path_to_error")]
+            e @ Error::Path { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+            Error::Decode(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+            e @ Error::Framing { .. }
+            | e @ Error::UnknownField { .. }
+            | e @ Error::DuplicateKey { .. }
+            | e @ Error::UnexpectedType { .. }
+            | e @ Error::LimitExceeded { .. }
+            | e @ Error::DepthExceeded { .. }
+            | e @ Error::FrameTooLarge { .. }
+            | e @ Error::TooManyMalformedFrames { .. }
+            | e @ Error::QuotaExceeded { .. }
+            | e @ Error::FrameQuotaExceeded { .. }
+            | e @ Error::RateLimited { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+            e @ Error::IncompleteFrame { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            e @ Error::Timeout { .. } => io::Error::new(io::ErrorKind::TimedOut, e),
+            Error::WithFrame { error, .. } => io::Error::from(*error),
+            Error::Labeled { label, error } => {
+                // Keep the kind of the underlying error, and the label in the message.
+                let error = io::Error::from(*error);
+                let kind = error.kind();
+                let error = Box::new(Error::Io(error));
+                io::Error::new(kind, Error::Labeled { label, error })
+            }
+            #[cfg(feature = "arrow")]
+            Error::Arrow(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+            #[cfg(feature = "json-patch")]
+            Error::Patch(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+            #[cfg(feature = "jsonschema")]
+            e @ Error::SchemaViolation { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// What the codec does with a frame that is valid JSON but fails to deserialize into the decoded
+/// type; see [`Codec::error_policy`][`::Codec::error_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorPolicy {
+    /// Fail to decode the frame.
+    #[default]
+    Fail,
+    /// Skip the frame, counting it in [`Codec::skipped_frames`][`::Codec::skipped_frames`].
+    SkipFrame,
+    /// Decode `D::default()` in place of the frame, counting it in
+    /// [`Codec::substituted_frames`][`::Codec::substituted_frames`].
+    SubstituteDefault,
+}
+
+/// A stable, machine-readable classification of an [`Error`].
+///
+/// Unlike error messages, kinds (and their numeric [codes][`ErrorKind::code`]) will not change
+/// between releases, so they are suitable for metrics labels and alerting rules. New kinds may
+/// be added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u16)]
+pub enum ErrorKind {
+    /// The input is not syntactically valid JSON.
+    Syntax = 1,
+    /// The input contains invalid UTF-8 or an invalid escaped code point.
+    Utf8 = 2,
+    /// The input is valid JSON, but does not match the expected type.
+    Data = 3,
+    /// The input ended in the middle of a value.
+    Eof = 4,
+    /// A frame or value exceeds a size limit.
+    TooLarge = 5,
+    /// The input is nested too deeply.
+    Depth = 6,
+    /// The connection was reset or aborted by the peer.
+    IoReset = 7,
+    /// An operation timed out.
+    Timeout = 8,
+    /// Any other I/O error.
+    Io = 9,
+    /// The peer sent too many malformed frames.
+    TooManyErrors = 10,
+    /// The peer sent frames faster than allowed.
+    RateLimited = 11,
+    /// The peer sent more data than its quota.
+    QuotaExceeded = 12,
+    /// The input broke the rules of the framing.
+    Framing = 13,
+}
+
+impl ErrorKind {
+    /// Returns the stable numeric code of this kind.
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+
+    /// Returns the stable name of this kind, such as `"syntax"` or `"io_reset"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Syntax => "syntax",
+            ErrorKind::Utf8 => "utf8",
+            ErrorKind::Data => "data",
+            ErrorKind::Eof => "eof",
+            ErrorKind::TooLarge => "too_large",
+            ErrorKind::Depth => "depth",
+            ErrorKind::IoReset => "io_reset",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Io => "io",
+            ErrorKind::TooManyErrors => "too_many_errors",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::QuotaExceeded => "quota_exceeded",
+            ErrorKind::Framing => "framing",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn io_kind(err: &io::Error) -> ErrorKind {
+    match err.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => ErrorKind::IoReset,
+        io::ErrorKind::TimedOut => ErrorKind::Timeout,
+        _ => ErrorKind::Io,
+    }
+}
+
+fn json_kind(err: &serde_json::Error) -> ErrorKind {
+    match err.classify() {
+        Category::Io => ErrorKind::Io,
+        Category::Data => ErrorKind::Data,
+        Category::Eof => ErrorKind::Eof,
+        Category::Syntax => {
+            // serde_json doesn't expose its error codes, so this is the only way to tell these
+            // apart from other syntax errors.
+            let message = err.to_string();
+            if message.starts_with("recursion limit exceeded") {
+                ErrorKind::Depth
+            } else if message.starts_with("invalid unicode code point")
+                || message.starts_with("lone leading surrogate")
+                || message.starts_with("unexpected end of hex escape")
+            {
+                ErrorKind::Utf8
+            } else {
+                ErrorKind::Syntax
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{consumed, excerpt};
+    use serde_json::{self, Value};
+    use std::error::Error as StdError;
+    use std::fmt;
+    use std::io;
+    use {Error, ErrorKind};
+
+    fn json_err(input: &[u8]) -> Error {
+        serde_json::from_slice::<Value>(input).unwrap_err().into()
+    }
+
+    #[test]
+    fn kind() {
+        assert_eq!(json_err(b"nul!").kind(), ErrorKind::Syntax);
+        assert_eq!(json_err(b"\"\xff\"").kind(), ErrorKind::Utf8);
+        assert_eq!(json_err(b"[1, 2").kind(), ErrorKind::Eof);
+        assert_eq!(json_err(&[b'['; 1024]).kind(), ErrorKind::Depth);
+        let err: Error = serde_json::from_str::<u8>("true").unwrap_err().into();
+        assert_eq!(err.kind(), ErrorKind::Data);
+        let err: Error = io::Error::from(io::ErrorKind::ConnectionReset).into();
+        assert_eq!(err.kind(), ErrorKind::IoReset);
+        assert_eq!(err.kind().code(), 7);
+        assert_eq!(err.kind().to_string(), "io_reset");
+    }
+
+    #[test]
+    fn accessors() {
+        let err = Error::Labeled {
+            label: "peer".into(),
+            error: Box::new(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+        };
+        assert_eq!(err.as_io().unwrap().kind(), io::ErrorKind::ConnectionReset);
+        assert!(err.as_json().is_none());
+        let err = err.into_json().unwrap_err();
+        assert_eq!(err.label(), Some("peer"));
+        assert!(err.into_io().is_ok());
+
+        let err = Error::decode(
+            serde_json::from_str::<Value>("[!]").unwrap_err(),
+            "[!]".into(),
+        );
+        assert!(err.as_io().is_none());
+        assert!(err.as_json().unwrap().is_syntax());
+        assert!(err.into_json().unwrap().is_syntax());
+        assert!(StdError::source(&Error::FrameTooLarge {
+            max: 1,
+            resynced: true
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn source_chain() {
+        #[derive(Debug)]
+        struct Handshake(io::Error);
+
+        impl fmt::Display for Handshake {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("handshake failed")
+            }
+        }
+
+        impl StdError for Handshake {
+            fn source(&self) -> Option<&(dyn StdError + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        // Each message in the chain appears once, as error reporters list them.
+        let chain = |err: &Error| {
+            let mut messages = vec![err.to_string()];
+            let mut source = err.source();
+            while let Some(err) = source {
+                messages.push(err.to_string());
+                source = err.source();
+            }
+            messages
+        };
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        let err = Error::Labeled {
+            label: "peer".into(),
+            error: Box::new(Error::Io(io::Error::other(Handshake(reset)))),
+        };
+        assert_eq!(
+            chain(&err),
+            vec!["[peer] handshake failed", "connection reset"]
+        );
+        let err = Error::decode(
+            serde_json::from_str::<Value>("[!]").unwrap_err(),
+            "[!]".into(),
+        );
+        assert_eq!(chain(&err), vec!["expected value at line 1 column 2"]);
+    }
+
+    #[test]
+    fn excerpts() {
+        assert_eq!(excerpt(b"[1, !]", 4, 32), ("[1, !]".to_owned(), 4));
+        assert_eq!(excerpt(b"[1,\n\t!]\n[2]", 5, 32), ("\\t!]".to_owned(), 2));
+        assert_eq!(excerpt(b"0123456789", 5, 2), ("3456".to_owned(), 2));
+        assert_eq!(excerpt(b"[1", 2, 32), ("[1".to_owned(), 2));
+    }
+
+    #[test]
+    fn consumed_bytes() {
+        assert_eq!(consumed(b"[!]", 1, 2), 2);
+        assert_eq!(consumed(b"[\n\n  !]", 3, 3), 6);
+        assert_eq!(consumed(b"[", 1, 5), 1);
+    }
+}
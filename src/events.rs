@@ -0,0 +1,311 @@
+//! Decoding documents as a stream of path/value events, like `jq --stream`.
+
+use bytes::BytesMut;
+use lex::{is_whitespace, LexError, Lexer, Token, TokenKind};
+use serde::de::Error as _;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+use std::io;
+use tokio_codec::{Decoder, Encoder};
+use {deserialize_frame, Codec, Error};
+
+/// A step in the path to a value within a document.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// An object member.
+    Key(String),
+    /// An array element.
+    Index(usize),
+}
+
+/// An event decoded by [`Events`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A scalar, or an empty array or object, and its path from the top of the document.
+    Leaf(Vec<PathSegment>, Value),
+    /// The end of an array or object, with the path of its last element.
+    Close(Vec<PathSegment>),
+}
+
+/// A [`Codec`] that decodes each document as a stream of [`Event`]s instead of whole values,
+/// like `jq --stream`.
+///
+/// Input is consumed as soon as each scalar is complete, so arbitrarily large documents are
+/// decoded in memory bounded by their largest scalar and their depth. For example,
+/// `{"a": [1, {}]}` is decoded as:
+///
+/// ```text
+/// Leaf([Key("a"), Index(0)], 1)
+/// Leaf([Key("a"), Index(1)], {})
+/// Close([Key("a"), Index(1)])
+/// Close([Key("a")])
+/// ```
+///
+/// A top-level scalar is a single leaf with an empty path. Options that act on whole frames,
+/// such as limits and subscriptions, don't apply. Encoding is unaffected.
+///
+/// Created by [`Codec::events`].
+#[derive(Clone, Debug)]
+pub struct Events<E> {
+    codec: Codec<Value, E>,
+    parser: Parser,
+}
+
+impl<E> Events<E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<Value, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<Value, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Events`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<Value, E> {
+        self.codec
+    }
+}
+
+impl<E> Codec<Value, E> {
+    /// Wraps the codec to decode documents as a stream of path/value events.
+    pub fn events(self) -> Events<E> {
+        Events {
+            codec: self,
+            parser: Parser::default(),
+        }
+    }
+}
+
+/// What the parser expects next.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Expect {
+    #[default]
+    Value,
+    Key,
+    Colon,
+    CommaOrEnd,
+}
+
+/// The state of a partially decoded document.
+#[derive(Clone, Debug, Default)]
+struct Parser {
+    path: Vec<PathSegment>,
+    /// Whether each open container is an object.
+    objects: Vec<bool>,
+    expect: Expect,
+    /// Whether the innermost container was just opened, so it may be closed right away.
+    opened: bool,
+}
+
+impl Parser {
+    /// Returns whether the parser is in the middle of a document.
+    fn is_pending(&self) -> bool {
+        !self.objects.is_empty()
+    }
+
+    /// Decodes the next event from `src`, consuming its tokens.
+    fn next_event(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<Event>, Error> {
+        loop {
+            let whitespace = src.iter().take_while(|&&b| is_whitespace(b)).count();
+            src.advance(whitespace);
+            let token = match Lexer::new(&src[..], 0, eof).next_token() {
+                Ok(Some(token)) => token,
+                Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                Err(LexError::Invalid(_)) => return Err(unexpected(src[0])),
+            };
+            let event = self.event(&token, &src[..])?;
+            src.advance(token.end);
+            if event.is_some() {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Processes a token at the start of `src`, returning the event it completes, if any.
+    fn event(&mut self, token: &Token, src: &[u8]) -> Result<Option<Event>, Error> {
+        let raw = &src[token.start..token.end];
+        let in_object = self.objects.last().cloned();
+        match (self.expect, token.kind) {
+            (Expect::Value, TokenKind::ArrayStart) => {
+                self.open(false);
+                self.path.push(PathSegment::Index(0));
+                Ok(None)
+            }
+            (Expect::Value, TokenKind::ObjectStart) => {
+                self.open(true);
+                self.expect = Expect::Key;
+                Ok(None)
+            }
+            (Expect::Value, TokenKind::String)
+            | (Expect::Value, TokenKind::Number)
+            | (Expect::Value, TokenKind::Literal) => {
+                let value = deserialize_frame(raw.into(), false, false)?;
+                let event = Event::Leaf(self.path.clone(), value);
+                self.end_value();
+                Ok(Some(event))
+            }
+            (Expect::Value, TokenKind::ArrayEnd) if self.opened && in_object == Some(false) => {
+                self.path.pop();
+                Ok(Some(self.close_empty(Value::Array(Vec::new()))))
+            }
+            (Expect::Key, TokenKind::String) => {
+                let key = deserialize_frame(raw.into(), false, false)?;
+                self.path.push(PathSegment::Key(key));
+                self.expect = Expect::Colon;
+                Ok(None)
+            }
+            (Expect::Key, TokenKind::ObjectEnd) if self.opened => {
+                Ok(Some(self.close_empty(Value::Object(Map::new()))))
+            }
+            (Expect::Colon, TokenKind::Colon) => {
+                self.expect = Expect::Value;
+                Ok(None)
+            }
+            (Expect::CommaOrEnd, TokenKind::Comma) => {
+                match self.path.pop() {
+                    Some(PathSegment::Index(i)) => {
+                        self.path.push(PathSegment::Index(i + 1));
+                        self.expect = Expect::Value;
+                    }
+                    _ => self.expect = Expect::Key,
+                }
+                Ok(None)
+            }
+            (Expect::CommaOrEnd, TokenKind::ArrayEnd) if in_object == Some(false) => {
+                Ok(Some(self.close()))
+            }
+            (Expect::CommaOrEnd, TokenKind::ObjectEnd) if in_object == Some(true) => {
+                Ok(Some(self.close()))
+            }
+            _ => Err(unexpected(raw[0])),
+        }
+    }
+
+    fn open(&mut self, object: bool) {
+        self.objects.push(object);
+        self.expect = Expect::Value;
+        self.opened = true;
+    }
+
+    /// Closes a container that has elements, returning its event.
+    fn close(&mut self) -> Event {
+        let event = Event::Close(self.path.clone());
+        self.path.pop();
+        self.objects.pop();
+        self.end_value();
+        event
+    }
+
+    /// Closes an empty container, returning it as a leaf.
+    fn close_empty(&mut self, empty: Value) -> Event {
+        self.objects.pop();
+        let event = Event::Leaf(self.path.clone(), empty);
+        self.end_value();
+        event
+    }
+
+    /// Moves on after a complete value.
+    fn end_value(&mut self) {
+        self.opened = false;
+        self.expect = if self.objects.is_empty() {
+            Expect::Value
+        } else {
+            Expect::CommaOrEnd
+        };
+    }
+}
+
+pub(crate) fn unexpected(b: u8) -> Error {
+    serde_json::Error::custom(format!("unexpected `{}` in JSON input", char::from(b))).into()
+}
+
+impl<E> Decoder for Events<E> {
+    type Item = Event;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Event>, Error> {
+        let parser = &mut self.parser;
+        self.codec
+            .decode_with(src, |_, src| parser.next_event(src, false))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Event>, Error> {
+        let parser = &mut self.parser;
+        let event = self
+            .codec
+            .decode_with(src, |_, src| parser.next_event(src, true))?;
+        if event.is_none() && (!src.is_empty() || self.parser.is_pending()) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended in the middle of a JSON value",
+            )
+            .into());
+        }
+        Ok(event)
+    }
+}
+
+impl<E> Encoder for Events<E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Event::{Close, Leaf};
+    use super::PathSegment::{Index, Key};
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn events() {
+        let input = br#"{"a": [1, {}, {"b": null}], "c": []} 2"#;
+        let mut codec = Codec::<Value, ()>::default().events();
+        let mut buf = BytesMut::new();
+        let mut events = Vec::new();
+        // Feed the input a byte at a time to check that decoding resumes correctly.
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            while let Some(event) = codec.decode(&mut buf).unwrap() {
+                events.push(event);
+            }
+        }
+        while let Some(event) = codec.decode_eof(&mut buf).unwrap() {
+            events.push(event);
+        }
+        let key = |k: &str| Key(k.to_owned());
+        assert_eq!(
+            events,
+            vec![
+                Leaf(vec![key("a"), Index(0)], json!(1)),
+                Leaf(vec![key("a"), Index(1)], json!({})),
+                Leaf(vec![key("a"), Index(2), key("b")], json!(null)),
+                Close(vec![key("a"), Index(2), key("b")]),
+                Close(vec![key("a"), Index(2)]),
+                Leaf(vec![key("c")], json!([])),
+                Close(vec![key("c")]),
+                Leaf(vec![], json!(2)),
+            ]
+        );
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&b"[1 2]"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode(&mut buf).is_err());
+        let mut codec = Codec::<Value, ()>::default().events();
+        let mut buf = BytesMut::from(&b"[1, "[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode_eof(&mut buf).is_err());
+    }
+}
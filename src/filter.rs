@@ -0,0 +1,70 @@
+//! Dropping frames that no subscriber is interested in before they are deserialized.
+
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// A predicate on the value at a JSON Pointer.
+pub(crate) type Predicate = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// Predicates registered with [`Codec::subscribe`][`::Codec::subscribe`].
+#[derive(Clone, Default)]
+pub(crate) struct Filters {
+    subscriptions: Vec<(String, Predicate)>,
+}
+
+impl Filters {
+    pub(crate) const fn new() -> Self {
+        Filters {
+            subscriptions: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        pointer: &str,
+        predicate: Arc<dyn Fn(&Value) -> bool + Send + Sync>,
+    ) {
+        self.subscriptions.push((pointer.to_owned(), predicate));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.subscriptions.clear();
+    }
+
+    /// Returns whether any subscription matches `frame`.
+    pub(crate) fn matches(&self, frame: &Value) -> bool {
+        self.subscriptions
+            .iter()
+            .any(|(pointer, predicate)| frame.pointer(pointer).is_some_and(|v| predicate(v)))
+    }
+}
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.subscriptions.iter().map(|(pointer, _)| pointer))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filters;
+    use std::sync::Arc;
+
+    #[test]
+    fn matches() {
+        let mut filters = Filters::default();
+        filters.push("/type", Arc::new(|v| v == "trade"));
+        filters.push("/symbols/0", Arc::new(|_| true));
+        assert!(filters.matches(&json!({"type": "trade"})));
+        assert!(filters.matches(&json!({"type": "quote", "symbols": ["A"]})));
+        assert!(!filters.matches(&json!({"type": "quote", "symbols": []})));
+        assert!(!filters.matches(&json!(["trade"])));
+    }
+}
@@ -0,0 +1,491 @@
+//! A [`Framed`] with hooks into the lifetime of the stream and sink.
+
+use bytes::BytesMut;
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use rate::RateLimiter;
+use serde::{Deserialize, Serialize};
+use tokio_codec::{Decoder, Encoder, Framed, FramedParts};
+use tokio_io::{AsyncRead, AsyncWrite};
+use {Codec, DeadLetters, Error, ErrorReplies, Lossy, TakeUntil, Terminator};
+
+/// A [`Framed`] using a [`Codec`], which can act when the stream ends or the sink is closed.
+///
+/// Closing the sink sends the codec's [sentinel][`Codec::sentinel`], if it has one, and closes
+/// the array if items are [encoded as one][`Codec::encode_array`]. The stream ends when the
+/// sentinel is received, once too many frames fail to decode (see
+/// [`Codec::max_malformed_frames`]), or once the [byte quota][`Codec::byte_quota`] or
+/// [frame quota][`Codec::frame_quota`] is exceeded.
+/// The stream can also be [rate-limited][`FramedJson::max_frame_rate`].
+#[derive(Debug)]
+pub struct FramedJson<T, D, E> {
+    inner: Framed<T, Inner<D, E>>,
+    closing: bool,
+    rate: Option<RateLimiter>,
+    fail_on_rate_limit: bool,
+}
+
+/// The codec for the underlying [`Framed`], which can encode more than just items.
+#[derive(Debug)]
+struct Inner<D, E>(Codec<D, E>);
+
+/// What [`Inner`] encodes.
+enum Outgoing<E> {
+    Item(E),
+    Batch(Vec<E>),
+    /// The sentinel, and the end of the array if items are encoded as one.
+    End,
+}
+
+impl<T, D, E> FramedJson<T, D, E>
+where
+    T: AsyncRead + AsyncWrite,
+    for<'de> D: Deserialize<'de>,
+    E: Serialize,
+{
+    /// Creates a `FramedJson` over `io` using `codec`.
+    pub fn new(io: T, codec: Codec<D, E>) -> Self {
+        FramedJson {
+            inner: Framed::new(io, Inner(codec)),
+            closing: false,
+            rate: None,
+            fail_on_rate_limit: false,
+        }
+    }
+
+    /// Creates a `FramedJson` over `io` using `codec`, with read and write buffers of the given
+    /// initial capacities.
+    ///
+    /// The underlying [`Framed`] grows each buffer to at least 8 KiB; for smaller read buffers,
+    /// or to size the read buffer by the frames received, see [`Codec::adaptive_buffer`].
+    pub fn with_capacity(
+        io: T,
+        codec: Codec<D, E>,
+        read_capacity: usize,
+        write_capacity: usize,
+    ) -> Self {
+        let mut inner = FramedParts::new(io, Inner(codec));
+        inner.read_buf = BytesMut::with_capacity(read_capacity);
+        inner.write_buf = BytesMut::with_capacity(write_capacity);
+        FramedJson {
+            inner: Framed::from_parts(inner),
+            closing: false,
+            rate: None,
+            fail_on_rate_limit: false,
+        }
+    }
+
+    /// Creates a `FramedJson` from a [`Framed`], keeping any buffered data.
+    pub fn from_framed(framed: Framed<T, Codec<D, E>>) -> Self {
+        let parts = framed.into_parts();
+        let mut inner = FramedParts::new(parts.io, Inner(parts.codec));
+        inner.read_buf = parts.read_buf;
+        inner.write_buf = parts.write_buf;
+        FramedJson {
+            inner: Framed::from_parts(inner),
+            closing: false,
+            rate: None,
+            fail_on_rate_limit: false,
+        }
+    }
+
+    /// Consumes the `FramedJson`, returning a [`Framed`] with any buffered data.
+    pub fn into_framed(self) -> Framed<T, Codec<D, E>> {
+        let parts = self.inner.into_parts();
+        let mut framed = FramedParts::new(parts.io, parts.codec.0);
+        framed.read_buf = parts.read_buf;
+        framed.write_buf = parts.write_buf;
+        Framed::from_parts(framed)
+    }
+}
+
+impl<T, D, E> FramedJson<T, D, E> {
+    /// Returns a reference to the underlying I/O stream.
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying I/O stream.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Returns a reference to the codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.inner.codec().0
+    }
+
+    /// Returns a mutable reference to the codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.inner.codec_mut().0
+    }
+
+    /// Converts the `FramedJson` into a stream that yields errors for frames that fail to decode
+    /// as items, carrying on past them; see [`Lossy`].
+    pub fn into_lossy_stream(self) -> Lossy<Self> {
+        Lossy::new(self)
+    }
+
+    /// Converts the `FramedJson` into a stream that forwards errors for frames that fail to
+    /// decode to `dead_letters`, carrying on past them; see [`DeadLetters`].
+    pub fn with_dead_letters<K>(self, dead_letters: K) -> DeadLetters<Self, K> {
+        DeadLetters::new(self, dead_letters)
+    }
+
+    /// Converts the `FramedJson` into a sink of `Result`s that sends errors to the peer as error
+    /// frames; see [`ErrorReplies`].
+    pub fn with_error_replies<H>(self) -> ErrorReplies<Self, H> {
+        ErrorReplies::new(self)
+    }
+
+    /// Set the maximum number of items the stream yields per second, in bursts of up to `max`
+    /// items, or `None` for no limit.
+    ///
+    /// Once the limit is reached, reading is delayed until the stream may yield another item,
+    /// which pushes back on the peer, or the stream fails with [`Error::RateLimited`] for each
+    /// item over the limit if [`FramedJson::fail_on_rate_limit`] is set. Delaying requires a
+    /// Tokio timer, so the stream must be used on a Tokio runtime. A `max` of 0 is taken as 1.
+    /// Unlimited by default.
+    pub fn max_frame_rate(&mut self, max: Option<u32>) {
+        self.rate = max.map(RateLimiter::new);
+    }
+
+    /// Set whether or not the stream fails for items over the
+    /// [rate limit][`FramedJson::max_frame_rate`], dropping them, rather than delaying reading.
+    /// Disabled by default.
+    pub fn fail_on_rate_limit(&mut self, fail: bool) {
+        self.fail_on_rate_limit = fail;
+    }
+}
+
+impl<T, D, E> FramedJson<T, D, E>
+where
+    T: AsyncWrite,
+    E: Serialize,
+{
+    /// Returns a future that sends all of `items` and then flushes the sink, resolving to the
+    /// `FramedJson`.
+    ///
+    /// Unlike sending the items one at a time, or with [`Sink::send_all`], the items are encoded
+    /// into the write buffer in one go and written with a single flush. `send_all` also closes
+    /// the sink, which sends the sentinel. If an item fails to encode, the items before it may
+    /// already have been sent.
+    pub fn send_batch<I>(self, items: I) -> SendBatch<T, D, E>
+    where
+        I: IntoIterator<Item = E>,
+    {
+        SendBatch {
+            framed: Some(self),
+            items: Some(items.into_iter().collect()),
+        }
+    }
+}
+
+impl<T, D, E> FramedJson<T, D, E>
+where
+    T: AsyncRead,
+    for<'de> D: Deserialize<'de>,
+{
+    /// Returns a stream of the items up to the first one matching `predicate`, after which this
+    /// `FramedJson` can be used again.
+    pub fn take_until<F>(&mut self, predicate: F, terminator: Terminator) -> TakeUntil<&mut Self, F>
+    where
+        F: FnMut(&D) -> bool,
+    {
+        TakeUntil::new(self, predicate, terminator)
+    }
+}
+
+impl<T, D, E> Stream for FramedJson<T, D, E>
+where
+    T: AsyncRead,
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<D>, Error> {
+        if self.codec().sentinel_received()
+            || self.codec().malformed_limit_reached()
+            || self.codec().quota_exceeded()
+        {
+            return Ok(Async::Ready(None));
+        }
+        if let Some(ref mut rate) = self.rate {
+            if !self.fail_on_rate_limit && rate.poll_ready().is_not_ready() {
+                return Ok(Async::NotReady);
+            }
+        }
+        match self.inner.poll()? {
+            Async::NotReady if self.codec().sentinel_received() => Ok(Async::Ready(None)),
+            Async::Ready(Some(item)) => {
+                if let Some(ref mut rate) = self.rate {
+                    if !rate.take() && self.fail_on_rate_limit {
+                        let e = Error::RateLimited { max: rate.max() };
+                        return Err(self.inner.codec().0.label_error(e));
+                    }
+                }
+                Ok(Async::Ready(Some(item)))
+            }
+            poll => Ok(poll),
+        }
+    }
+}
+
+impl<T, D, E> Sink for FramedJson<T, D, E>
+where
+    T: AsyncWrite,
+    E: Serialize,
+{
+    type SinkItem = E;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: E) -> StartSend<E, Error> {
+        match self.inner.start_send(Outgoing::Item(item))? {
+            AsyncSink::Ready => Ok(AsyncSink::Ready),
+            AsyncSink::NotReady(Outgoing::Item(item)) => Ok(AsyncSink::NotReady(item)),
+            AsyncSink::NotReady(_) => unreachable!(),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        self.inner.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Error> {
+        while !self.closing {
+            if self.codec().sentinel.is_none() && !self.codec().encode_array {
+                self.closing = true;
+            } else if let AsyncSink::Ready = self.inner.start_send(Outgoing::End)? {
+                self.closing = true;
+            } else {
+                try_ready!(self.inner.poll_complete());
+            }
+        }
+        self.inner.close()
+    }
+}
+
+/// A future that sends a batch of items; see [`FramedJson::send_batch`].
+#[derive(Debug)]
+pub struct SendBatch<T, D, E> {
+    framed: Option<FramedJson<T, D, E>>,
+    items: Option<Vec<E>>,
+}
+
+impl<T, D, E> Future for SendBatch<T, D, E>
+where
+    T: AsyncWrite,
+    E: Serialize,
+{
+    type Item = FramedJson<T, D, E>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<FramedJson<T, D, E>, Error> {
+        let framed = self
+            .framed
+            .as_mut()
+            .expect("polled SendBatch after completion");
+        while let Some(items) = self.items.take() {
+            if let AsyncSink::NotReady(Outgoing::Batch(items)) =
+                framed.inner.start_send(Outgoing::Batch(items))?
+            {
+                self.items = Some(items);
+                // If flushing made room, try again straight away, as nothing else will wake us.
+                try_ready!(framed.inner.poll_complete());
+            }
+        }
+        try_ready!(framed.inner.poll_complete());
+        Ok(Async::Ready(self.framed.take().unwrap()))
+    }
+}
+
+impl<D, E> Decoder for Inner<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.0.decode(src)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        if self.0.sentinel_received() {
+            // Whatever follows the sentinel is left for the next user of the connection.
+            return Ok(None);
+        }
+        self.0.decode_eof(src)
+    }
+}
+
+impl<D, E> Encoder for Inner<D, E>
+where
+    E: Serialize,
+{
+    type Item = Outgoing<E>;
+    type Error = Error;
+
+    fn encode(&mut self, item: Outgoing<E>, dst: &mut BytesMut) -> Result<(), Error> {
+        match item {
+            Outgoing::Item(item) => self.0.encode(item, dst),
+            // The codec reserves room for each item as it goes, by the size of recent frames.
+            Outgoing::Batch(items) => items
+                .into_iter()
+                .try_for_each(|item| self.0.encode(item, dst)),
+            Outgoing::End => self.0.encode_end(dst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FramedJson;
+    use futures::executor::{self, Notify};
+    use futures::future::poll_fn;
+    use futures::{Async, Future, Poll, Sink, Stream};
+    use std::io::{self, Cursor, Read, Write};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::runtime::current_thread::Runtime;
+    use tokio_io::{AsyncRead, AsyncWrite};
+    use {Codec, ErrorKind};
+
+    /// A sink for bytes that counts the writes to it.
+    #[derive(Default)]
+    struct Writes {
+        data: Vec<u8>,
+        writes: usize,
+        /// Whether the next write fails with `WouldBlock`.
+        blocked: bool,
+    }
+
+    impl Read for Writes {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl AsyncRead for Writes {}
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.blocked {
+                self.blocked = false;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.writes += 1;
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncWrite for Writes {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    #[test]
+    fn sentinel() {
+        let mut codec: Codec<String, String> = Codec::default();
+        codec.sentinel(Some(json!({"type": "bye"})));
+        let framed = FramedJson::new(Cursor::new(Vec::new()), codec.clone());
+        let mut framed = framed
+            .send("a".to_owned())
+            .and_then(|f| f.send("b".to_owned()))
+            .wait()
+            .unwrap();
+        poll_fn(|| framed.close()).wait().unwrap();
+        let mut io = framed.get_ref().get_ref().clone();
+        assert_eq!(io, b"\"a\"\"b\"{\"type\":\"bye\"}");
+
+        io.extend_from_slice(b"\"c\"");
+        let framed = FramedJson::new(Cursor::new(io), codec);
+        assert_eq!(framed.collect().wait().unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn encode_array() {
+        let mut codec: Codec<String, &str> = Codec::default();
+        codec.encode_array(true);
+        let mut framed = FramedJson::new(Cursor::new(Vec::new()), codec.clone());
+        poll_fn(|| framed.close()).wait().unwrap();
+        assert_eq!(framed.get_ref().get_ref(), b"[]");
+
+        codec.sentinel(Some(json!(null)));
+        let framed = FramedJson::new(Cursor::new(Vec::new()), codec);
+        let framed = framed.send("a").and_then(|f| f.send("b")).wait().unwrap();
+        let mut framed = framed.send_batch(vec!["c"]).wait().unwrap();
+        poll_fn(|| framed.close()).wait().unwrap();
+        assert_eq!(framed.get_ref().get_ref(), b"[\"a\",\"b\",\"c\",null]");
+    }
+
+    #[test]
+    fn with_capacity() {
+        let codec: Codec<String, String> = Codec::default();
+        let io = Cursor::new(Vec::new());
+        let framed = FramedJson::with_capacity(io, codec, 64 * 1024, 32 * 1024);
+        let parts = framed.into_framed().into_parts();
+        assert_eq!(parts.read_buf.capacity(), 64 * 1024);
+        assert_eq!(parts.write_buf.capacity(), 32 * 1024);
+    }
+
+    #[test]
+    fn send_batch() {
+        let mut codec: Codec<String, &str> = Codec::default();
+        codec.sentinel(Some(json!(null)));
+        let framed = FramedJson::new(Writes::default(), codec);
+        let framed = framed.send_batch(vec!["a", "b", "c"]).wait().unwrap();
+        assert_eq!(framed.get_ref().data, b"\"a\"\"b\"\"c\"");
+        assert_eq!(framed.get_ref().writes, 1);
+
+        // A full write buffer that a flush empties doesn't stall the batch.
+        let codec: Codec<String, String> = Codec::default();
+        let mut framed = FramedJson::new(Writes::default(), codec);
+        framed.get_mut().blocked = true;
+        assert!(framed.start_send("a".repeat(9000)).unwrap().is_ready());
+        let mut batch = executor::spawn(framed.send_batch(vec!["b".to_owned()]));
+        match batch.poll_future_notify(&Arc::new(NoWakeup), 0).unwrap() {
+            Async::Ready(framed) => {
+                let data = &framed.get_ref().data;
+                assert_eq!((data.len(), &data[9002..]), (9005, &b"\"b\""[..]));
+            }
+            Async::NotReady => panic!("batch not sent"),
+        }
+    }
+
+    /// A task that is never woken up.
+    struct NoWakeup;
+
+    impl Notify for NoWakeup {
+        fn notify(&self, _: usize) {}
+    }
+
+    #[test]
+    fn max_frame_rate() {
+        let codec: Codec<u32, ()> = Codec::default();
+        let mut framed = FramedJson::new(Cursor::new(b"1 2 3 4".to_vec()), codec.clone());
+        framed.max_frame_rate(Some(2));
+        framed.fail_on_rate_limit(true);
+        let items: Vec<_> = framed
+            .then(|item| Ok::<_, ()>(item.map_err(|e| e.kind())))
+            .collect()
+            .wait()
+            .unwrap();
+        let limited = Err(ErrorKind::RateLimited);
+        assert_eq!(items, vec![Ok(1), Ok(2), limited, limited]);
+
+        let started = Instant::now();
+        let input: String = (0..22).map(|n| format!("{} ", n)).collect();
+        let mut framed = FramedJson::new(Cursor::new(input.into_bytes()), codec);
+        framed.max_frame_rate(Some(20));
+        let mut runtime = Runtime::new().unwrap();
+        let items = runtime.block_on(framed.collect()).unwrap();
+        assert_eq!(items, (0..22).collect::<Vec<_>>());
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,687 @@
+//! How frames are delimited on the wire.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use lex::{is_whitespace, Scan};
+use std::io;
+use std::str;
+use {max_depth, next_frame, Error};
+
+/// The record separator that starts each record of a JSON text sequence.
+const RS: u8 = 0x1e;
+
+/// How frames are delimited on the wire; see [`Codec::framing`][`::Codec::framing`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Framing {
+    /// Frames follow each other, optionally separated by whitespace, and each ends where its
+    /// JSON value does.
+    #[default]
+    Concatenated,
+    /// A JSON text sequence as in [RFC 7464] (`application/json-seq`): each frame is preceded by
+    /// an RS (0x1E) byte and followed by a line feed.
+    ///
+    /// A record that is cut short by the next RS fails to decode without affecting the records
+    /// after it.
+    ///
+    /// [RFC 7464]: https://www.rfc-editor.org/rfc/rfc7464
+    JsonSeq,
+    /// Each frame is preceded by its length in bytes as a 4-byte big-endian integer.
+    ///
+    /// Frames are only parsed once they have been received in full.
+    LengthPrefixed,
+    /// Each frame is preceded by its length in bytes as an unsigned LEB128 varint, as with
+    /// delimited protobuf messages.
+    ///
+    /// Frames are only parsed once they have been received in full.
+    VarintPrefixed,
+    /// Each frame is a [netstring]: its length in bytes in decimal, a colon, the frame, and a
+    /// comma, as in `7:[1,2,3],`.
+    ///
+    /// Frames are only parsed once they have been received in full. As there is no way to find
+    /// the next netstring after a malformed one, the buffered input is discarded when one is
+    /// received.
+    ///
+    /// [netstring]: https://cr.yp.to/proto/netstrings.txt
+    Netstring,
+    /// Each frame is preceded by a `Content-Length` header giving its length in bytes, and
+    /// possibly other headers, as in the Language Server Protocol and Debug Adapter Protocol:
+    /// `Content-Length: 7\r\n\r\n[1,2,3]`.
+    ///
+    /// Other headers, such as `Content-Type`, are ignored, and only `Content-Length` is written.
+    /// Frames are only parsed once they have been received in full. As there is no way to find
+    /// the next frame after malformed headers, the buffered input is discarded when they are
+    /// received.
+    Headers,
+    /// Frames are separated by a delimiter, such as `b"\r\n\r\n"`, which follows each encoded
+    /// frame. Created by [`Framing::delimited`], for a delimiter that is only known at runtime.
+    ///
+    /// The delimiter must not appear within frames: decoding splits at every occurrence of it,
+    /// even within a string, and encoding a frame containing it fails. Input before the next
+    /// delimiter is only parsed once the delimiter has been received. An empty delimiter is the
+    /// same as [`Framing::Concatenated`].
+    Delimited(Bytes),
+    /// The framing is detected from the first byte received that isn't whitespace, and is then
+    /// used for both decoding and encoding from then on, so that replies are framed as the peer
+    /// frames its input.
+    ///
+    /// An RS byte selects [`Framing::JsonSeq`], a NUL byte [`Framing::LengthPrefixed`], a `C`
+    /// [`Framing::Headers`], and digits followed by a colon [`Framing::Netstring`]; anything else,
+    /// including newline-delimited JSON, selects [`Framing::Concatenated`]. Until then, frames
+    /// are encoded as by [`Framing::Concatenated`].
+    Auto,
+}
+
+impl Framing {
+    /// Frames separated by `delimiter`; see [`Framing::Delimited`].
+    pub fn delimited(delimiter: &[u8]) -> Framing {
+        Framing::Delimited(Bytes::from(delimiter))
+    }
+
+    /// Frames separated by NUL bytes, as for IPC with tools that use `-print0`-style output.
+    ///
+    /// A NUL byte never appears within JSON, not even within a string, so this never fails to
+    /// encode or splits a frame.
+    pub fn nul_delimited() -> Framing {
+        Framing::Delimited(Bytes::from_static(b"\0"))
+    }
+
+    /// Writes a frame to `dst` with `encode`, delimiting it.
+    pub(crate) fn encode<F>(&self, dst: &mut BytesMut, encode: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut BytesMut) -> Result<(), Error>,
+    {
+        match *self {
+            Framing::Concatenated | Framing::Auto => encode(dst),
+            Framing::JsonSeq => {
+                dst.reserve(1);
+                dst.put_u8(RS);
+                encode(dst)?;
+                dst.reserve(1);
+                dst.put_u8(b'\n');
+                Ok(())
+            }
+            Framing::LengthPrefixed => {
+                let start = dst.len();
+                dst.reserve(4);
+                dst.put_u32_be(0);
+                encode(dst)?;
+                let len = dst.len() - start - 4;
+                if len > u32::MAX as usize {
+                    dst.truncate(start);
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "frame too long for a 4-byte length prefix",
+                    )
+                    .into());
+                }
+                dst[start..start + 4].copy_from_slice(&(len as u32).to_be_bytes());
+                Ok(())
+            }
+            Framing::VarintPrefixed => prefixed(dst, encode, |dst, len| {
+                let mut len = len as u64;
+                dst.reserve(MAX_VARINT_LEN);
+                while len >= 0x80 {
+                    dst.put_u8(len as u8 | 0x80);
+                    len >>= 7;
+                }
+                dst.put_u8(len as u8);
+            }),
+            Framing::Netstring => {
+                prefixed(dst, encode, |dst, len| {
+                    dst.extend_from_slice(len.to_string().as_bytes());
+                    dst.extend_from_slice(b":");
+                })?;
+                dst.reserve(1);
+                dst.put_u8(b',');
+                Ok(())
+            }
+            Framing::Headers => prefixed(dst, encode, |dst, len| {
+                dst.extend_from_slice(format!("Content-Length: {}\r\n\r\n", len).as_bytes());
+            }),
+            Framing::Delimited(ref delimiter) if delimiter.is_empty() => encode(dst),
+            Framing::Delimited(ref delimiter) => {
+                let start = dst.len();
+                encode(dst)?;
+                if find(&dst[start..], delimiter).is_some() {
+                    dst.truncate(start);
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "frame contains the delimiter",
+                    )
+                    .into());
+                }
+                dst.extend_from_slice(delimiter);
+                Ok(())
+            }
+        }
+    }
+
+    /// Detects the framing of input starting with `src`, or returns `None` if more of it is
+    /// needed; see [`Framing::Auto`].
+    pub(crate) fn detect(src: &[u8]) -> Option<Framing> {
+        let start = src.iter().position(|&b| !is_whitespace(b))?;
+        let framing = match src[start] {
+            RS => Framing::JsonSeq,
+            0 => Framing::LengthPrefixed,
+            b'C' | b'c' => Framing::Headers,
+            b'0'..=b'9' => {
+                let digits = src[start..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count();
+                match src.get(start + digits)? {
+                    b':' => Framing::Netstring,
+                    _ => Framing::Concatenated,
+                }
+            }
+            _ => Framing::Concatenated,
+        };
+        Some(framing)
+    }
+
+    /// Returns whether the start of the next frame can be found after the buffered input has
+    /// been discarded partway through a frame.
+    pub(crate) fn finds_next_frame(&self) -> bool {
+        match *self {
+            Framing::JsonSeq => true,
+            Framing::Delimited(ref delimiter) => !delimiter.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Splits the next complete frame off `src`, along with how many bytes were consumed after
+    /// it. `scan` keeps track of a concatenated frame or JSON text sequence record that hasn't
+    /// fully arrived.
+    pub(crate) fn next_frame(
+        &self,
+        src: &mut BytesMut,
+        unbounded_depth: bool,
+        scan: &mut Scan,
+    ) -> Result<Option<(Bytes, usize)>, Error> {
+        match *self {
+            Framing::Concatenated => {
+                Ok(next_frame(src, unbounded_depth, scan)?.map(|frame| (frame, 0)))
+            }
+            Framing::JsonSeq => next_record(src, max_depth(unbounded_depth), scan),
+            Framing::LengthPrefixed => {
+                if src.len() < 4 {
+                    return Ok(None);
+                }
+                let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+                if src.len() - 4 < len {
+                    return Ok(None);
+                }
+                src.advance(4);
+                Ok(Some(trim(src.split_to(len).freeze())))
+            }
+            Framing::VarintPrefixed => {
+                let (prefix, len) = match varint(src)? {
+                    Some(varint) => varint,
+                    None => return Ok(None),
+                };
+                if ((src.len() - prefix) as u64) < len {
+                    return Ok(None);
+                }
+                src.advance(prefix);
+                Ok(Some(trim(src.split_to(len as usize).freeze())))
+            }
+            Framing::Netstring => next_netstring(src),
+            // Nothing can be split off until the framing has been detected.
+            Framing::Auto => Ok(None),
+            Framing::Headers => next_message(src),
+            Framing::Delimited(ref delimiter) if delimiter.is_empty() => {
+                Framing::Concatenated.next_frame(src, unbounded_depth, scan)
+            }
+            Framing::Delimited(ref delimiter) => loop {
+                let end = match find(src, delimiter) {
+                    Some(end) => end,
+                    None => return Ok(None),
+                };
+                let (frame, trailing) = trim(src.split_to(end).freeze());
+                src.advance(delimiter.len());
+                if !frame.is_empty() {
+                    return Ok(Some((frame, trailing + delimiter.len())));
+                }
+            },
+        }
+    }
+}
+
+/// Writes a frame to `dst` with `encode`, preceded by what `prefix` writes given its length.
+fn prefixed<F, P>(dst: &mut BytesMut, encode: F, prefix: P) -> Result<(), Error>
+where
+    F: FnOnce(&mut BytesMut) -> Result<(), Error>,
+    P: FnOnce(&mut BytesMut, usize),
+{
+    // The prefix's size depends on the frame's, so encode the frame first.
+    let start = dst.len();
+    encode(dst)?;
+    let frame = dst.split_off(start);
+    prefix(dst, frame.len());
+    dst.extend_from_slice(&frame);
+    Ok(())
+}
+
+/// The length of the longest LEB128 varint that fits a `u64`.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Reads a LEB128 varint at the start of `src`, returning its length and value, or `None` if it
+/// isn't complete yet.
+fn varint(src: &[u8]) -> Result<Option<(usize, u64)>, Error> {
+    let mut value = 0u64;
+    for (i, &b) in src.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= u64::from(b & 0x7f).checked_shl(7 * i as u32).unwrap_or(0);
+        if b & 0x80 == 0 {
+            if i == MAX_VARINT_LEN - 1 && b > 1 {
+                break;
+            }
+            return Ok(Some((i + 1, value)));
+        }
+    }
+    if src.len() < MAX_VARINT_LEN {
+        return Ok(None);
+    }
+    Err(Error::Framing {
+        message: "length prefix overflows 64 bits",
+        resynced: false,
+    })
+}
+
+/// The most digits a netstring's length may have.
+const MAX_NETSTRING_DIGITS: usize = 20;
+
+/// Splits the next netstring off `src`.
+fn next_netstring(src: &mut BytesMut) -> Result<Option<(Bytes, usize)>, Error> {
+    let digits = src
+        .iter()
+        .take(MAX_NETSTRING_DIGITS + 1)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    if digits == src.len() && digits <= MAX_NETSTRING_DIGITS {
+        return Ok(None);
+    }
+    let len = match src.get(digits) {
+        // Leading zeros aren't allowed.
+        Some(b':') if digits == 1 || (digits > 1 && src[0] != b'0') => {
+            str::from_utf8(&src[..digits])
+                .ok()
+                .and_then(|len| len.parse::<usize>().ok())
+        }
+        _ => None,
+    };
+    let end = match len.and_then(|len| len.checked_add(digits + 1)) {
+        Some(end) => end,
+        None => {
+            src.clear();
+            return Err(Error::Framing {
+                message: "invalid netstring length",
+                resynced: false,
+            });
+        }
+    };
+    if src.len() <= end {
+        return Ok(None);
+    }
+    if src[end] != b',' {
+        src.clear();
+        return Err(Error::Framing {
+            message: "expected a comma ending the netstring",
+            resynced: false,
+        });
+    }
+    src.advance(digits + 1);
+    let payload = src.split_to(end - digits - 1).freeze();
+    src.advance(1);
+    let (frame, trailing) = trim(payload);
+    Ok(Some((frame, trailing + 1)))
+}
+
+/// The most bytes a frame's headers may take up.
+const MAX_HEADERS_LEN: usize = 8 * 1024;
+
+/// Splits the next frame preceded by headers off `src`.
+fn next_message(src: &mut BytesMut) -> Result<Option<(Bytes, usize)>, Error> {
+    let searched = &src[..src.len().min(MAX_HEADERS_LEN)];
+    let end = match searched.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(end) => end,
+        None if searched.len() < MAX_HEADERS_LEN => return Ok(None),
+        None => {
+            src.clear();
+            return Err(Error::Framing {
+                message: "headers too long",
+                resynced: false,
+            });
+        }
+    };
+    let len = match content_length(&src[..end]) {
+        Some(len) => len,
+        None => {
+            src.clear();
+            return Err(Error::Framing {
+                message: "missing or invalid Content-Length header",
+                resynced: false,
+            });
+        }
+    };
+    if src.len() - (end + 4) < len {
+        return Ok(None);
+    }
+    src.advance(end + 4);
+    Ok(Some(trim(src.split_to(len).freeze())))
+}
+
+/// Returns the value of the single `Content-Length` header in `headers`, or `None` if there
+/// isn't one or they are malformed.
+fn content_length(headers: &[u8]) -> Option<usize> {
+    let mut len = None;
+    for header in str::from_utf8(headers).ok()?.split("\r\n") {
+        let (name, value) = header.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            if len.is_some() {
+                return None;
+            }
+            len = Some(value.trim().parse().ok()?);
+        }
+    }
+    len
+}
+
+/// Returns the offset of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Trims whitespace around a frame, also returning how much of it followed the frame.
+pub(crate) fn trim(payload: Bytes) -> (Bytes, usize) {
+    let leading = payload.iter().take_while(|&&b| is_whitespace(b)).count();
+    let trailing = payload[leading..]
+        .iter()
+        .rev()
+        .take_while(|&&b| is_whitespace(b))
+        .count();
+    let frame = payload.slice(leading, payload.len() - trailing);
+    (frame, trailing)
+}
+
+/// Splits the next record of a JSON text sequence off `src`, scanning its value with `scan`.
+fn next_record(
+    src: &mut BytesMut,
+    max_depth: usize,
+    scan: &mut Scan,
+) -> Result<Option<(Bytes, usize)>, Error> {
+    loop {
+        let start = match src.iter().position(|&b| !is_whitespace(b)) {
+            Some(start) => start,
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        };
+        if src[start] != RS {
+            // Skip to the next record, so that decoding can carry on from there.
+            let next = src[start..].iter().position(|&b| b == RS);
+            src.advance(next.map_or(src.len(), |next| start + next));
+            return Err(Error::Framing {
+                message: "expected a record separator",
+                resynced: true,
+            });
+        }
+        let (end, terminated) = match record_end(&src[start + 1..], max_depth, scan) {
+            Some((end, terminated)) => (start + 1 + end, terminated),
+            None => return Ok(None),
+        };
+        let record = src.split_to(end).freeze();
+        if terminated {
+            src.advance(1);
+        }
+        let (frame, trailing) = trim(record.slice_from(start + 1));
+        if frame.is_empty() {
+            // An empty record, which carries no frame.
+            continue;
+        }
+        return Ok(Some((frame, trailing + usize::from(terminated))));
+    }
+}
+
+/// Finds the end of the record starting at `input`, just after its RS: either a line feed after
+/// a complete JSON value, which is also consumed, or the next RS. `scan` resumes scanning the
+/// value where the last call left off.
+fn record_end(input: &[u8], max_depth: usize, scan: &mut Scan) -> Option<(usize, bool)> {
+    // JSON text can't contain an RS, even in a string, so the record ends at the next one if not
+    // before.
+    let next = input.iter().position(|&b| b == RS);
+    let text = &input[..next.unwrap_or(input.len())];
+    // Pretty-printed values contain line feeds of their own, so look for one after the value.
+    if let Ok(Some(end)) = scan.value_end(text, false, max_depth) {
+        let after = text[end..]
+            .iter()
+            .position(|&b| b == b'\n' || !is_whitespace(b));
+        if let Some(i) = after.filter(|&i| text[end + i] == b'\n') {
+            return Some((end + i, true));
+        }
+    }
+    let end = next?;
+    *scan = Scan::new();
+    Some((end, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::varint;
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Framing};
+
+    #[test]
+    fn length_prefixed() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::LengthPrefixed);
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        assert_eq!(buf, &b"\0\0\0\x03[1]"[..]);
+        let mut partial = buf.split_to(5);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.extend_from_slice(&buf);
+        assert_eq!(codec.decode(&mut partial).unwrap(), Some(json!([1])));
+        assert!(partial.is_empty());
+
+        let mut buf = BytesMut::from(&b"\0\0\0\x02[]\0\0\0\x03[1 \0\0\0\x01 \0\0\0\x01x"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([])));
+        assert!(codec.decode(&mut buf).is_err());
+        // The whitespace-only frame is skipped.
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn varint_prefixed() {
+        let mut codec: Codec<Value, String> = Codec::default();
+        codec.framing(Framing::VarintPrefixed);
+        let mut buf = BytesMut::new();
+        codec.encode("a".repeat(200), &mut buf).unwrap();
+        assert_eq!(&buf[..3], b"\xca\x01\"");
+        assert_eq!(buf.len(), 204);
+        let mut partial = buf.split_to(100);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.extend_from_slice(&buf);
+        assert_eq!(
+            codec.decode(&mut partial).unwrap(),
+            Some(json!("a".repeat(200)))
+        );
+        assert!(partial.is_empty());
+
+        assert_eq!(varint(b"\x80").unwrap(), None);
+        assert_eq!(
+            varint(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01").unwrap(),
+            Some((10, u64::MAX))
+        );
+        assert!(varint(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02").is_err());
+    }
+
+    #[test]
+    fn netstring() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::Netstring);
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1, 2, 3]), &mut buf).unwrap();
+        assert_eq!(buf, &b"7:[1,2,3],"[..]);
+        buf.extend_from_slice(b"3: 4 ,1");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1, 2, 3])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(4)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"0:4");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"123456789x");
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(buf.is_empty());
+
+        for input in &[&b"01:1,"[..], b":", b"1;1,", b"123456789012345678901"] {
+            let mut buf = BytesMut::from(*input);
+            assert!(codec.decode(&mut buf).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn headers() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::Headers);
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1, 2, 3]), &mut buf).unwrap();
+        assert_eq!(buf, &b"Content-Length: 7\r\n\r\n[1,2,3]"[..]);
+        buf.extend_from_slice(b"content-length:1\r\nContent-Type: application/json\r\n\r");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1, 2, 3])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"\n4");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(4)));
+        assert!(buf.is_empty());
+
+        for input in &[
+            &b"Content-Type: application/json\r\n\r\n"[..],
+            b"Content-Length: 1\r\nContent-Length: 1\r\n\r\n1",
+            b"Content-Length: -1\r\n\r\n",
+            &[b' '; 8192],
+        ] {
+            let mut buf = BytesMut::from(*input);
+            assert!(codec.decode(&mut buf).is_err());
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn delimited() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::delimited(b"\r\n\r\n"));
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        assert_eq!(buf, &b"[1]\r\n\r\n"[..]);
+        assert!(codec.encode(json!("\r\n\r\n"), &mut buf).is_ok());
+        codec.pretty(true);
+        codec.framing(Framing::delimited(b"\n"));
+        assert!(codec.encode(json!([1]), &mut buf).is_err());
+        codec.framing(Framing::delimited(b"\r\n\r\n"));
+
+        buf.extend_from_slice(b"\r\n\r\n[1, 2\r\n\r\n3\r\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!("\r\n\r\n")));
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"\r\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(3)));
+        assert!(buf.is_empty());
+
+        // A delimiter only known at runtime, such as one read from a config file.
+        let delimiter = format!("--{}--", "boundary").into_bytes();
+        codec.framing(Framing::delimited(&delimiter));
+        codec.encode(json!([1]), &mut buf).unwrap();
+        assert_eq!(buf, &b"[\n  1\n]--boundary--"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+    }
+
+    #[test]
+    fn nul_delimited() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::nul_delimited());
+        let mut buf = BytesMut::new();
+        codec.encode(json!(["\0"]), &mut buf).unwrap();
+        codec.encode(json!({}), &mut buf).unwrap();
+        assert_eq!(buf, &b"[\"\\u0000\"]\0{}\0"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(["\0"])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({})));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn auto() {
+        let inputs: &[(&[u8], Framing)] = &[
+            (b"\x1e[1]\n", Framing::JsonSeq),
+            (b"\0\0\0\x03[1]", Framing::LengthPrefixed),
+            (b"Content-Length: 3\r\n\r\n[1]", Framing::Headers),
+            (b"3:[1],", Framing::Netstring),
+            (b"\n[1]", Framing::Concatenated),
+        ];
+        for &(input, ref framing) in inputs {
+            let mut codec: Codec<Value, Value> = Codec::default();
+            codec.framing(Framing::Auto);
+            let mut buf = BytesMut::from(input);
+            assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+            codec.encode(json!([1]), &mut buf).unwrap();
+            assert_eq!(buf, input.trim_ascii_start(), "{:?}", framing);
+        }
+
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::Auto);
+        let mut buf = BytesMut::from(&b" 12"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b" ");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(12)));
+    }
+
+    #[test]
+    fn json_seq() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.framing(Framing::JsonSeq);
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        codec.encode(json!(2), &mut buf).unwrap();
+        assert_eq!(buf, &b"\x1e[1]\n\x1e2\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(2)));
+        assert!(buf.is_empty());
+
+        codec.pretty(true);
+        codec.encode(json!({"a": [1]}), &mut buf).unwrap();
+        // A record cut short by the next one fails on its own.
+        buf.extend_from_slice(b"\x1e{\"a\":\x1e\x1e3");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": [1]})));
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(3)));
+        buf.extend_from_slice(b"4\n\x1e5\n");
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(5)));
+
+        // Records are scanned as they arrive, even a string cut short by the next record.
+        let mut input = BytesMut::new();
+        codec
+            .encode(json!({"a": [1, {"b": "\n"}]}), &mut input)
+            .unwrap();
+        input.extend_from_slice(b"\x1e\"a\n\x1e[6]\n");
+        let (mut items, mut errors) = (Vec::new(), 0);
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            loop {
+                match codec.decode(&mut buf) {
+                    Ok(Some(item)) => items.push(item),
+                    Ok(None) => break,
+                    Err(_) => errors += 1,
+                }
+            }
+        }
+        assert_eq!(items, vec![json!({"a": [1, {"b": "\n"}]}), json!([6])]);
+        assert_eq!(errors, 1);
+    }
+}
@@ -0,0 +1,306 @@
+//! Streaming the features out of GeoJSON feature collections.
+
+use bytes::{Bytes, BytesMut};
+use events::unexpected;
+use lex::{is_whitespace, value_end, LexError, Lexer, TokenKind};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::io;
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// A GeoJSON feature, with its geometry left unparsed.
+///
+/// Geometries are usually the bulk of a feature and are often handed to a geometry library
+/// as is, so they are kept as raw JSON.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Feature {
+    /// The feature's `id`, a string or a number.
+    pub id: Option<Value>,
+    /// The feature's `geometry`, or `None` if it is `null`.
+    pub geometry: Option<Box<RawValue>>,
+    /// The feature's `properties`, or `None` if they are `null`.
+    pub properties: Option<Map<String, Value>>,
+    /// Any other members of the feature, such as `bbox`.
+    pub foreign_members: Map<String, Value>,
+}
+
+impl<'de> Deserialize<'de> for Feature {
+    fn deserialize<De>(deserializer: De) -> Result<Self, De::Error>
+    where
+        De: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(FeatureVisitor)
+    }
+}
+
+struct FeatureVisitor;
+
+impl<'de> Visitor<'de> for FeatureVisitor {
+    type Value = Feature;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a GeoJSON feature")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Feature, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut feature = Feature {
+            id: None,
+            geometry: None,
+            properties: None,
+            foreign_members: Map::new(),
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => {
+                    let kind: String = map.next_value()?;
+                    if kind != "Feature" {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Str(&kind),
+                            &"\"Feature\"",
+                        ));
+                    }
+                }
+                "id" => feature.id = map.next_value()?,
+                "geometry" => feature.geometry = map.next_value()?,
+                "properties" => feature.properties = map.next_value()?,
+                _ => {
+                    let value = map.next_value()?;
+                    feature.foreign_members.insert(key, value);
+                }
+            }
+        }
+        Ok(feature)
+    }
+}
+
+/// A [`Codec`] that decodes the features of GeoJSON feature collections one at a time.
+///
+/// Only the `features` array of each collection is decoded, element by element, so a collection
+/// far larger than memory is decoded in memory bounded by its largest feature. The collection's
+/// other members are skipped. Each feature is decoded as a frame of its own, so options such as
+/// limits and subscriptions apply to features. `D` is typically [`Feature`]. Encoding is
+/// unaffected.
+///
+/// Created by [`Codec::features`].
+#[derive(Clone, Debug)]
+pub struct Features<D, E> {
+    codec: Codec<D, E>,
+    state: State,
+}
+
+/// Where the decoder is within a feature collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Between collections.
+    Start,
+    /// Expecting a member name, or the end of the collection if `first`.
+    Key { first: bool },
+    /// Expecting the colon after a member name, which is `features` if `features`.
+    Colon { features: bool },
+    /// Expecting the value of a member other than `features`.
+    Member,
+    /// Expecting the start of the `features` array.
+    Features,
+    /// Expecting a feature, or the end of the array if `first`.
+    Feature { first: bool },
+    /// Expecting a comma or the end of the `features` array.
+    FeatureEnd,
+    /// Expecting a comma or the end of the collection.
+    MemberEnd,
+}
+
+impl<D, E> Features<D, E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Features`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to decode the features of GeoJSON feature collections (requires the
+    /// `geojson` feature).
+    pub fn features(self) -> Features<D, E> {
+        Features {
+            codec: self,
+            state: State::Start,
+        }
+    }
+
+    /// Splits the next feature off `src`, consuming the collection around it.
+    fn next_feature(
+        &mut self,
+        state: &mut State,
+        src: &mut BytesMut,
+        eof: bool,
+    ) -> Result<Option<Bytes>, Error> {
+        loop {
+            if self.sentinel_received {
+                return Ok(None);
+            }
+            let whitespace = src.iter().take_while(|&&b| is_whitespace(b)).count();
+            let value = match *state {
+                State::Member => true,
+                State::Feature { first } => !first || src.get(whitespace) != Some(&b']'),
+                _ => false,
+            };
+            if value {
+                let end = match value_end(&src[..], eof, usize::MAX) {
+                    Ok(Some(end)) => end,
+                    Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                    Err(LexError::Invalid(at)) => return Err(unexpected(src[at])),
+                };
+                src.advance(whitespace);
+                let value = src.split_to(end - whitespace).freeze();
+                if *state == State::Member {
+                    *state = State::MemberEnd;
+                    self.split_off(end, None);
+                    continue;
+                }
+                *state = State::FeatureEnd;
+                self.split_off(end, Some(value.len()));
+                return Ok(Some(value));
+            }
+            let token = match Lexer::new(&src[..], whitespace, eof).next_token() {
+                Ok(Some(token)) => token,
+                Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                Err(LexError::Invalid(at)) => return Err(unexpected(src[at])),
+            };
+            let raw = &src[token.start..token.end];
+            *state = match (*state, token.kind) {
+                (State::Start, TokenKind::ObjectStart) => State::Key { first: true },
+                (State::Key { .. }, TokenKind::String) => State::Colon {
+                    features: raw == b"\"features\"",
+                },
+                (State::Key { first: true }, TokenKind::ObjectEnd) => State::Start,
+                (State::Colon { features }, TokenKind::Colon) if features => State::Features,
+                (State::Colon { .. }, TokenKind::Colon) => State::Member,
+                (State::Features, TokenKind::ArrayStart) => State::Feature { first: true },
+                (State::Feature { first: true }, TokenKind::ArrayEnd) => State::MemberEnd,
+                (State::FeatureEnd, TokenKind::Comma) => State::Feature { first: false },
+                (State::FeatureEnd, TokenKind::ArrayEnd) => State::MemberEnd,
+                (State::MemberEnd, TokenKind::Comma) => State::Key { first: false },
+                (State::MemberEnd, TokenKind::ObjectEnd) => State::Start,
+                _ => return Err(unexpected(raw[0])),
+            };
+            src.advance(token.end);
+            self.split_off(token.end, None);
+        }
+    }
+}
+
+impl<D, E> Features<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    fn decode_features(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<D>, Error> {
+        let state = &mut self.state;
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(feature) = codec.next_feature(state, src, eof)? {
+                if let Some(item) = codec.decode_value(feature)? {
+                    return Ok(Some(item));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Decoder for Features<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.decode_features(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let item = self.decode_features(src, true)?;
+        let pending = self.state != State::Start || src.iter().any(|&b| !is_whitespace(b));
+        if item.is_none() && pending && !self.codec.sentinel_received {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended in the middle of a feature collection",
+            )
+            .into());
+        }
+        Ok(item)
+    }
+}
+
+impl<D, E> Encoder for Features<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Feature;
+    use bytes::BytesMut;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn features() {
+        let input = br#"{"type": "FeatureCollection", "bbox": [0, 0, 1, 1], "features": [
+            {"type": "Feature", "id": 1, "geometry": {"type": "Point", "coordinates": [0, 1]},
+             "properties": {"name": "a"}},
+            {"type": "Feature", "geometry": null, "properties": null, "title": "b"}
+        ]} {"type": "FeatureCollection", "features": []}"#;
+        let mut codec = Codec::<Feature, ()>::default().features();
+        let mut buf = BytesMut::new();
+        let mut features = Vec::new();
+        // Feed the input a byte at a time to check that decoding resumes correctly.
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            while let Some(feature) = codec.decode(&mut buf).unwrap() {
+                features.push(feature);
+            }
+        }
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0].id, Some(json!(1)));
+        assert_eq!(
+            features[0].geometry.as_ref().unwrap().get(),
+            r#"{"type": "Point", "coordinates": [0, 1]}"#
+        );
+        assert_eq!(features[0].properties.as_ref().unwrap()["name"], "a");
+        assert!(features[1].geometry.is_none());
+        assert!(features[1].properties.is_none());
+        assert_eq!(features[1].foreign_members["title"], "b");
+
+        let mut buf = BytesMut::from(&br#"{"features": [{"type": "Point"}]}"#[..]);
+        assert!(codec.decode(&mut buf).is_err());
+        let mut codec = Codec::<Feature, ()>::default().features();
+        let mut buf = BytesMut::from(&br#"{"features": ["#[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(codec.decode_eof(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Decoding gzip-compressed input, and compressing frames with gzip.
+
+use bytes::BytesMut;
+use flate2::read::GzDecoder;
+use flate2::write::{GzEncoder, MultiGzDecoder};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::mem;
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Compression, Error};
+
+/// The magic bytes that start gzip-compressed data.
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A [`Codec`] that decompresses its input first if it is gzip-compressed (requires the `gzip`
+/// feature).
+///
+/// Whether the input is compressed is detected from its first two bytes, so that the same
+/// decoder handles both compressed and uncompressed input, such as NDJSON log archives and HTTP
+/// bodies. Compressed input is decompressed as it arrives, and may consist of several gzip
+/// members one after another, as concatenated `.gz` files do. Encoding is unaffected.
+///
+/// Created by [`Codec::gunzip`].
+#[derive(Debug)]
+pub struct Gunzip<D, E> {
+    codec: Codec<D, E>,
+    state: State,
+}
+
+/// Whether the input is compressed.
+#[derive(Debug)]
+enum State {
+    /// Too little input has arrived to tell.
+    Detecting,
+    Plain,
+    /// The input is compressed, and `buf` holds the decompressed input yet to be decoded.
+    Compressed {
+        decoder: Box<MultiGzDecoder<Vec<u8>>>,
+        buf: BytesMut,
+    },
+}
+
+impl<D, E> Gunzip<D, E> {
+    /// Returns whether the input was detected to be gzip-compressed, or `None` if too little of
+    /// it has arrived to tell.
+    pub fn is_compressed(&self) -> Option<bool> {
+        match self.state {
+            State::Detecting => None,
+            State::Plain => Some(false),
+            State::Compressed { .. } => Some(true),
+        }
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Gunzip`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to decompress gzip-compressed input, detecting whether the input is
+    /// compressed (requires the `gzip` feature).
+    pub fn gunzip(self) -> Gunzip<D, E> {
+        Gunzip {
+            codec: self,
+            state: State::Detecting,
+        }
+    }
+}
+
+impl<D, E> Gunzip<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    fn decode_gzip(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<D>, Error> {
+        if let State::Detecting = self.state {
+            if src.len() < MAGIC.len() && MAGIC.starts_with(src) && !eof {
+                return Ok(None);
+            }
+            self.state = if src.starts_with(&MAGIC) {
+                State::Compressed {
+                    decoder: Box::new(MultiGzDecoder::new(Vec::new())),
+                    buf: BytesMut::new(),
+                }
+            } else {
+                State::Plain
+            };
+        }
+        match self.state {
+            State::Compressed {
+                ref mut decoder,
+                ref mut buf,
+            } => {
+                if !src.is_empty() {
+                    decoder.write_all(src)?;
+                    decoder.flush()?;
+                    src.clear();
+                }
+                if eof {
+                    decoder.try_finish()?;
+                }
+                buf.extend_from_slice(&mem::take(decoder.get_mut()));
+                if eof {
+                    self.codec.decode_eof(buf)
+                } else {
+                    self.codec.decode(buf)
+                }
+            }
+            _ if eof => self.codec.decode_eof(src),
+            _ => self.codec.decode(src),
+        }
+    }
+}
+
+impl<D, E> Decoder for Gunzip<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.decode_gzip(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        self.decode_gzip(src, true)
+    }
+}
+
+impl<D, E> Encoder for Gunzip<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+/// Gzip as a [`Compression`] for [`Compressed`][`::Compressed`] (requires the `gzip` feature).
+#[derive(Clone, Copy, Debug)]
+pub struct Gzip {
+    level: u32,
+}
+
+impl Gzip {
+    /// Creates a `Gzip` that compresses at `level`, from 0 (no compression) to 9 (best).
+    pub fn new(level: u32) -> Self {
+        Gzip {
+            level: level.min(9),
+        }
+    }
+}
+
+impl Default for Gzip {
+    /// Compresses at level 6, as the `gzip` command does by default.
+    fn default() -> Self {
+        Gzip::new(6)
+    }
+}
+
+impl Compression for Gzip {
+    fn compress(&self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(self.level));
+        encoder.write_all(input)?;
+        encoder.finish()
+    }
+
+    fn decompress(&self, input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        GzDecoder::new(input)
+            .take(limit as u64)
+            .read_to_end(&mut output)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gzip;
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Compression, Error};
+
+    fn gzip(input: &[u8]) -> Vec<u8> {
+        Gzip::default().compress(input).unwrap()
+    }
+
+    #[test]
+    fn gunzip() {
+        let mut input = gzip(b"{\"a\": 1}\n[2]\n");
+        input.extend_from_slice(&gzip(b"3\n"));
+        let mut codec = Codec::<Value, ()>::default().gunzip();
+        let mut buf = BytesMut::new();
+        let mut items = Vec::new();
+        for chunk in input.chunks(7) {
+            buf.extend_from_slice(chunk);
+            while let Some(item) = codec.decode(&mut buf).unwrap() {
+                items.push(item);
+            }
+        }
+        assert_eq!(codec.is_compressed(), Some(true));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+        assert_eq!(items, vec![json!({"a": 1}), json!([2]), json!(3)]);
+
+        let mut codec = Codec::<Value, ()>::default().gunzip();
+        let mut buf = BytesMut::from(&b"\x1f"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.is_compressed(), None);
+        assert!(codec.decode_eof(&mut buf).is_err());
+        assert_eq!(codec.is_compressed(), Some(false));
+
+        let mut codec = Codec::<Value, ()>::default().gunzip();
+        let mut buf = BytesMut::from(&b"[1]\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(codec.is_compressed(), Some(false));
+
+        let input = gzip(b"[1]");
+        let mut codec = Codec::<Value, ()>::default().gunzip();
+        let mut buf = BytesMut::from(&input[..input.len() - 4]);
+        assert!(codec.decode_eof(&mut buf).is_err());
+    }
+
+    #[test]
+    fn compressed() {
+        let mut codec = Codec::<Value, Value>::default().compressed(Gzip::new(9));
+        codec.threshold(16);
+        let mut buf = BytesMut::new();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        codec.encode(Value::from(vec!["a"; 100]), &mut buf).unwrap();
+        assert!(buf.starts_with(b"{\"compressed\":false,\"data\":[1]}"));
+        assert!(buf.len() < 200);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Value::from(vec!["a"; 100]))
+        );
+        assert!(Gzip::default().decompress(b"[1]", 10).is_err());
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        let bomb = Value::from(vec!["a"; 100_000]);
+        let mut codec = Codec::<Value, Value>::default().compressed(Gzip::new(9));
+        let mut buf = BytesMut::new();
+        codec.encode(bomb, &mut buf).unwrap();
+        codec.encode(json!([1]), &mut buf).unwrap();
+        assert!(buf.len() < 1000);
+        codec.codec_mut().max_frame_size(Some(1000));
+        match codec.decode(&mut buf) {
+            Err(Error::FrameTooLarge { max, resynced }) => {
+                assert_eq!((max, resynced), (1000, true))
+            }
+            other => panic!("expected FrameTooLarge, got {:?}", other),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        let output = Gzip::default()
+            .decompress(&gzip(&[0; 100_000]), 10)
+            .unwrap();
+        assert_eq!(output, [0; 10]);
+    }
+}
@@ -0,0 +1,79 @@
+//! Skipping keepalive tokens sent between frames.
+
+use bytes::BytesMut;
+use lex::is_whitespace;
+
+/// The configured keepalive tokens; see [`Codec::keepalive_token`][`::Codec::keepalive_token`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Keepalives {
+    tokens: Vec<Vec<u8>>,
+    pub(crate) count: u64,
+}
+
+impl Keepalives {
+    pub(crate) const fn new() -> Self {
+        Keepalives {
+            tokens: Vec::new(),
+            count: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, token: &[u8]) {
+        self.tokens.push(token.to_owned());
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.tokens.clear();
+    }
+
+    /// Skips whitespace and keepalive tokens at the start of `src`, counting the tokens.
+    ///
+    /// Returns `false` if what is left could be the start of a token that hasn't been fully
+    /// received yet.
+    pub(crate) fn skip(&mut self, src: &mut BytesMut) -> bool {
+        loop {
+            if let Some(len) = self
+                .tokens
+                .iter()
+                .find(|t| src.starts_with(t))
+                .map(Vec::len)
+            {
+                src.advance(len);
+                self.count = self.count.saturating_add(1);
+            } else if src.first().cloned().is_some_and(is_whitespace) {
+                src.advance(1);
+            } else {
+                return src.is_empty() || !self.tokens.iter().any(|t| t.starts_with(&src[..]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keepalives;
+    use bytes::BytesMut;
+
+    #[test]
+    fn skip() {
+        let mut keepalives = Keepalives::default();
+        keepalives.push(b"\r\n");
+        keepalives.push(b"PING");
+        let mut buf = BytesMut::from(&b" \r\n\nPING\r\n{}PING"[..]);
+        assert!(keepalives.skip(&mut buf));
+        assert_eq!(buf, "{}PING");
+        assert_eq!(keepalives.count, 3);
+
+        let mut buf = BytesMut::from(&b"\nPI"[..]);
+        assert!(!keepalives.skip(&mut buf));
+        assert_eq!(buf, "PI");
+        buf.extend_from_slice(b"NG");
+        assert!(keepalives.skip(&mut buf));
+        assert!(buf.is_empty());
+        assert_eq!(keepalives.count, 4);
+    }
+}
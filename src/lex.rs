@@ -0,0 +1,322 @@
+//! A minimal JSON lexer that can be resumed as more input arrives.
+
+/// The kind of a [`Token`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    Colon,
+    Comma,
+    String,
+    Number,
+    /// `true`, `false` or `null`.
+    Literal,
+}
+
+/// A token and the byte range it spans in the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub(crate) kind: TokenKind,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+impl Token {
+    pub(crate) fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// Why the lexer couldn't produce a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LexError {
+    /// The input ends in the middle of a token.
+    Incomplete,
+    /// The byte at this offset can't start or continue a token.
+    Invalid(usize),
+}
+
+/// Splits JSON input into tokens.
+///
+/// The lexer only checks what it needs to find token boundaries: string contents and number
+/// syntax are left for the parser to validate. If the input ends mid-token the lexer reports
+/// [`LexError::Incomplete`] without consuming the token, so lexing can resume from the same
+/// position once more input is available.
+#[derive(Debug)]
+pub(crate) struct Lexer<'a> {
+    input: &'a [u8],
+    pos: usize,
+    complete: bool,
+}
+
+impl<'a> Lexer<'a> {
+    /// Creates a lexer starting at `pos`. `complete` indicates that no more input will follow,
+    /// so a number running into the end of the input is complete.
+    pub(crate) fn new(input: &'a [u8], pos: usize, complete: bool) -> Self {
+        Lexer {
+            input,
+            pos,
+            complete,
+        }
+    }
+
+    /// Skips whitespace and returns the next token, or `Ok(None)` at the end of the input.
+    pub(crate) fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        while self.pos < self.input.len() && is_whitespace(self.input[self.pos]) {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        let kind = match self.input.get(start) {
+            None => return Ok(None),
+            Some(b'{') => TokenKind::ObjectStart,
+            Some(b'}') => TokenKind::ObjectEnd,
+            Some(b'[') => TokenKind::ArrayStart,
+            Some(b']') => TokenKind::ArrayEnd,
+            Some(b':') => TokenKind::Colon,
+            Some(b',') => TokenKind::Comma,
+            Some(b'"') => TokenKind::String,
+            Some(b'-') | Some(b'0'..=b'9') => TokenKind::Number,
+            Some(b't') | Some(b'f') | Some(b'n') => TokenKind::Literal,
+            Some(_) => return Err(LexError::Invalid(start)),
+        };
+        let end = match kind {
+            TokenKind::String => self.string_end(start)?,
+            TokenKind::Number => self.number_end(start)?,
+            TokenKind::Literal => self.literal_end(start)?,
+            _ => start + 1,
+        };
+        self.pos = end;
+        Ok(Some(Token { kind, start, end }))
+    }
+
+    fn string_end(&self, start: usize) -> Result<usize, LexError> {
+        let mut i = start + 1;
+        while i < self.input.len() {
+            match self.input[i] {
+                b'"' => return Ok(i + 1),
+                b'\\' => i += 2,
+                _ => i += 1,
+            }
+        }
+        Err(LexError::Incomplete)
+    }
+
+    fn number_end(&self, start: usize) -> Result<usize, LexError> {
+        let len = self.input[start..]
+            .iter()
+            .take_while(|&&b| matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+            .count();
+        if start + len == self.input.len() && !self.complete {
+            Err(LexError::Incomplete)
+        } else {
+            Ok(start + len)
+        }
+    }
+
+    fn literal_end(&self, start: usize) -> Result<usize, LexError> {
+        let literal: &[u8] = match self.input[start] {
+            b't' => b"true",
+            b'f' => b"false",
+            _ => b"null",
+        };
+        let available = &self.input[start..];
+        if available.len() < literal.len() {
+            if literal.starts_with(available) {
+                Err(LexError::Incomplete)
+            } else {
+                Err(LexError::Invalid(start))
+            }
+        } else if available.starts_with(literal) {
+            Ok(start + literal.len())
+        } else {
+            Err(LexError::Invalid(start))
+        }
+    }
+}
+
+/// Returns the end of the value at the start of `input`, after any whitespace, or `Ok(None)` if
+/// it isn't complete yet.
+///
+/// Like the lexer, this only finds where the value ends: it doesn't check that the value is
+/// well-formed. Arrays and objects nested more than `max_depth` levels deep are reported as
+/// [`LexError::Invalid`].
+pub(crate) fn value_end(
+    input: &[u8],
+    complete: bool,
+    max_depth: usize,
+) -> Result<Option<usize>, LexError> {
+    Scan::new().value_end(input, complete, max_depth)
+}
+
+/// How far [`value_end`] got through a value that isn't complete yet, so that scanning can
+/// resume there once more input arrives rather than starting over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Scan {
+    /// The end of the last token scanned.
+    pos: usize,
+    depth: usize,
+}
+
+impl Scan {
+    pub(crate) const fn new() -> Self {
+        Scan { pos: 0, depth: 0 }
+    }
+
+    /// Like [`value_end`], resuming from where the last call left off. `input` must start with
+    /// the input previously scanned; if it is shorter, scanning starts over.
+    ///
+    /// The scan is reset once the value is complete, or found to be invalid.
+    pub(crate) fn value_end(
+        &mut self,
+        input: &[u8],
+        complete: bool,
+        max_depth: usize,
+    ) -> Result<Option<usize>, LexError> {
+        if self.pos > input.len() {
+            *self = Scan::new();
+        }
+        let mut lexer = Lexer::new(input, self.pos, complete);
+        loop {
+            let token = match lexer.next_token() {
+                Ok(Some(token)) => token,
+                Ok(None) | Err(LexError::Incomplete) => return Ok(None),
+                Err(e) => {
+                    *self = Scan::new();
+                    return Err(e);
+                }
+            };
+            match token.kind {
+                // More digits may follow: only at the top level can the input ending end a number.
+                TokenKind::Number if self.depth > 0 && token.end == input.len() => return Ok(None),
+                TokenKind::ObjectStart | TokenKind::ArrayStart if self.depth == max_depth => {
+                    *self = Scan::new();
+                    return Err(LexError::Invalid(token.start));
+                }
+                TokenKind::ObjectStart | TokenKind::ArrayStart => self.depth += 1,
+                TokenKind::ObjectEnd | TokenKind::ArrayEnd => {
+                    self.depth = self.depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            if self.depth == 0 {
+                *self = Scan::new();
+                // As for `serde_json`, a number or literal must be followed by whitespace or
+                // punctuation, so that `nullnull` isn't taken for two values.
+                if let TokenKind::Number | TokenKind::Literal = token.kind {
+                    if !input.get(token.end).is_none_or(|&b| ends_value(b)) {
+                        return Err(LexError::Invalid(token.end));
+                    }
+                }
+                return Ok(Some(token.end));
+            }
+            self.pos = token.end;
+        }
+    }
+
+    /// Returns whether a value has been partly scanned.
+    pub(crate) fn is_started(&self) -> bool {
+        self.pos > 0
+    }
+}
+
+/// Returns whether `b` may follow a value that doesn't delimit itself.
+fn ends_value(b: u8) -> bool {
+    is_whitespace(b) || matches!(b, b'"' | b'[' | b']' | b'{' | b'}' | b',' | b':')
+}
+
+/// Returns whether `b` is JSON whitespace.
+pub(crate) fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\n' || b == b'\r'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{value_end, LexError, Lexer, Scan, Token, TokenKind};
+
+    fn kinds(input: &[u8]) -> Vec<TokenKind> {
+        let mut lexer = Lexer::new(input, 0, true);
+        let mut kinds = Vec::new();
+        while let Some(Token { kind, .. }) = lexer.next_token().unwrap() {
+            kinds.push(kind);
+        }
+        kinds
+    }
+
+    #[test]
+    fn tokens() {
+        use self::TokenKind::*;
+        assert_eq!(
+            kinds(br#" {"a\"": [1.5e3, true, null], "b": -2} "#),
+            vec![
+                ObjectStart,
+                String,
+                Colon,
+                ArrayStart,
+                Number,
+                Comma,
+                Literal,
+                Comma,
+                Literal,
+                ArrayEnd,
+                Comma,
+                String,
+                Colon,
+                Number,
+                ObjectEnd
+            ]
+        );
+    }
+
+    #[test]
+    fn incomplete() {
+        for input in &[&b"\"abc"[..], b"\"ab\\", b"12", b"tr"] {
+            let mut lexer = Lexer::new(input, 0, false);
+            assert_eq!(lexer.next_token(), Err(LexError::Incomplete));
+            assert_eq!(lexer.pos, 0);
+        }
+        let mut lexer = Lexer::new(b"12", 0, true);
+        assert_eq!(lexer.next_token().unwrap().unwrap().len(), 2);
+        assert_eq!(
+            Lexer::new(b"trap", 0, false).next_token(),
+            Err(LexError::Invalid(0))
+        );
+        assert_eq!(
+            Lexer::new(b" ?", 0, false).next_token(),
+            Err(LexError::Invalid(1))
+        );
+    }
+
+    #[test]
+    fn value_ends() {
+        assert_eq!(value_end(b" [1, {\"]\": 2}] 3", false, 2), Ok(Some(14)));
+        assert_eq!(value_end(b"\"a\" ", false, 2), Ok(Some(3)));
+        assert_eq!(value_end(b"{\"a\": [", false, 2), Ok(None));
+        assert_eq!(value_end(b"12", false, 2), Ok(None));
+        assert_eq!(value_end(b"12", true, 2), Ok(Some(2)));
+        assert_eq!(value_end(b"[?]", false, 2), Err(LexError::Invalid(1)));
+        assert_eq!(value_end(b"[[[", false, 2), Err(LexError::Invalid(2)));
+        assert_eq!(value_end(b"nullnull", false, 2), Err(LexError::Invalid(4)));
+        assert_eq!(value_end(b"1x", true, 2), Err(LexError::Invalid(1)));
+        assert_eq!(value_end(b"1[", true, 2), Ok(Some(1)));
+    }
+
+    #[test]
+    fn resumed_scan() {
+        let mut scan = Scan::new();
+        let input = b" [1, {\"a\": [2, \"]\"]}] 3";
+        for len in 0..21 {
+            assert_eq!(scan.value_end(&input[..len], false, 3), Ok(None));
+        }
+        assert!(scan.is_started());
+        assert_eq!(scan.value_end(input, false, 3), Ok(Some(21)));
+        assert!(!scan.is_started());
+
+        assert_eq!(scan.value_end(b"[1", true, 2), Ok(None));
+        assert_eq!(scan.value_end(b"[12]", true, 2), Ok(Some(4)));
+        assert_eq!(scan.value_end(b"[[", false, 2), Ok(None));
+        assert_eq!(scan.value_end(b"[[[", false, 2), Err(LexError::Invalid(2)));
+        assert_eq!(scan, Scan::new());
+    }
+}
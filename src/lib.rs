@@ -1,225 +1,3082 @@
 //! This crate integrates [`serde_json`] into a Tokio codec ([`tokio_codec::Decoder`] and
 //! [`Encoder`]).
 
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_json;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
 extern crate bytes;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[macro_use]
+extern crate futures;
+#[cfg(feature = "json-patch")]
+extern crate json_patch;
+#[cfg(feature = "jsonschema")]
+extern crate jsonschema;
 #[cfg(test)]
 #[macro_use]
 extern crate maplit;
+#[cfg(feature = "miette")]
+extern crate miette;
+#[cfg(feature = "otel")]
+extern crate opentelemetry;
 extern crate serde;
+#[cfg(test)]
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_ignored;
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
+#[cfg(feature = "serde_path_to_error")]
+extern crate serde_path_to_error;
+#[cfg(feature = "unbounded_depth")]
+extern crate serde_stacker;
+#[cfg(test)]
+extern crate tokio;
 extern crate tokio_codec;
+extern crate tokio_io;
+extern crate tokio_timer;
+#[cfg(feature = "websocket")]
+extern crate tokio_tungstenite;
+extern crate tracing;
 
-use bytes::BytesMut;
-use serde::{Deserialize, Serialize};
+mod array;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod batch;
+mod boundary;
+mod bulk;
+mod compress;
+mod deadletter;
+mod dedupe;
+#[cfg(feature = "miette")]
+mod diagnostic;
+mod error;
+mod events;
+mod filter;
+mod framed;
+mod framing;
+#[cfg(feature = "geojson")]
+mod geojson;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod keepalive;
+mod lex;
+mod lines;
+mod lossy;
+mod merge;
+mod meta;
+mod mux;
+#[cfg(feature = "otel")]
+mod otel;
+mod parser;
+#[cfg(feature = "json-patch")]
+mod patch;
+mod position;
+mod rate;
+mod raw;
+mod reliable;
+mod reply;
+mod reserve;
+#[cfg(feature = "jsonschema")]
+mod schema;
+mod sse;
+mod stats;
+mod timeout;
+mod until;
+mod validate;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use array::Elements;
+#[cfg(feature = "arrow")]
+pub use arrow::RecordBatches;
+pub use batch::{Batch, Unbatch};
+pub use boundary::{Direction, FrameBoundary};
+pub use bulk::{Bulk, BulkOp};
+pub use compress::{Compressed, Compression};
+pub use deadletter::DeadLetters;
+pub use error::{DecodeError, Error, ErrorKind, ErrorPolicy, JsonType, Limit};
+pub use events::{Event, Events, PathSegment};
+pub use framed::{FramedJson, SendBatch};
+pub use framing::Framing;
+#[cfg(feature = "geojson")]
+pub use geojson::{Feature, Features};
+#[cfg(feature = "gzip")]
+pub use gzip::{Gunzip, Gzip};
+pub use lines::{Line, Lines, TextLines};
+pub use lossy::Lossy;
+pub use merge::{Merge, Merged};
+pub use meta::{FrameMeta, WithMeta};
+pub use mux::{Channel, Mux};
+pub use parser::{Frame, FrameParser};
+#[cfg(feature = "json-patch")]
+pub use patch::{PatchFormat, Patched};
+pub use position::Position;
+pub use raw::WithRaw;
+pub use reliable::{Outbound, Reliable};
+pub use reply::{ErrorFrame, ErrorReplies};
+pub use sse::{ServerSentEvents, SseEvent};
+pub use stats::Stats;
+pub use timeout::TimedFramed;
+pub use until::{TakeUntil, Terminator};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketJson;
+
+use bytes::{Bytes, BytesMut};
+use lex::is_whitespace;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::de::SliceRead;
+use serde_json::Value;
+use std::any::type_name;
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt;
 use std::io;
 use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio_codec::{Decoder, Encoder};
 
 /// JSON-based codec.
 #[derive(Clone, Debug)]
 pub struct Codec<D, E> {
     pretty: bool,
-    _priv: (PhantomData<D>, PhantomData<E>),
+    js_compatible: bool,
+    framing: Framing,
+    terminator: Vec<u8>,
+    encode_array: bool,
+    array_open: bool,
+    error_context: bool,
+    deny_unknown_fields: bool,
+    error_policy: ErrorPolicy,
+    /// Makes the item substituted by [`ErrorPolicy::SubstituteDefault`].
+    default_item: Option<fn() -> D>,
+    unbounded_depth: bool,
+    lossy_utf8: bool,
+    max_frame_size: Option<usize>,
+    limits: validate::Limits,
+    filters: filter::Filters,
+    keepalives: keepalive::Keepalives,
+    skip_boms: bool,
+    /// Whether nothing has been decoded yet, so that a byte order mark may come next.
+    bom_due: bool,
+    dedupe: Option<dedupe::Dedupe>,
+    sentinel: Option<Value>,
+    sentinel_received: bool,
+    datagrams: bool,
+    require_separators: bool,
+    resync: bool,
+    /// Whether a frame was just split off, so that a separator must come next.
+    separator_due: bool,
+    unbatch_arrays: bool,
+    unbatched: VecDeque<Value>,
+    keep_last_frame: bool,
+    last_frame: Option<Bytes>,
+    stall: Option<Stall>,
+    on_error: Option<OnError>,
+    capture_frames: Option<usize>,
+    /// The frame being decoded, kept for `on_error` and `capture_frames`.
+    pending_frame: Option<Bytes>,
+    /// How far a concatenated frame that hasn't fully arrived has been scanned.
+    scan: lex::Scan,
+    reserve: Option<reserve::Reserve>,
+    /// The size of recently encoded frames, reserved in the write buffer before encoding.
+    encoded_size: reserve::Average,
+    boundaries: Option<boundary::Boundaries>,
+    positions: Option<position::Positions>,
+    slow_frame: Option<Duration>,
+    slow_frames: u64,
+    malformed: u64,
+    malformed_bytes: u64,
+    max_malformed: Option<u64>,
+    skipped: u64,
+    substituted: u64,
+    decoded_bytes: u64,
+    /// How many bytes `split_off` has been told were consumed.
+    split_bytes: u64,
+    /// The stream offset of the last frame split off.
+    frame_offset: u64,
+    /// How many frames have been split off, or decoded straight from the input.
+    split_frames: u64,
+    byte_quota: Option<u64>,
+    decoded_frames: u64,
+    frame_quota: Option<u64>,
+    label: Option<Arc<str>>,
+    compressed_bytes: u64,
+    uncompressed_bytes: u64,
+    decoded: u64,
+    encoded: u64,
+    #[cfg(feature = "otel")]
+    trace: Option<otel::TraceContext>,
+    #[cfg(feature = "jsonschema")]
+    schema: Option<Arc<jsonschema::Validator>>,
+    #[cfg(feature = "jsonschema")]
+    validate_encoded: bool,
+    _priv: PhantomData<fn(E) -> D>,
 }
 
 impl<D, E> Codec<D, E> {
     /// Creates a new `Codec`.
     ///
-    /// `pretty` controls whether or not encoded values are pretty-printed.
-    pub fn new(pretty: bool) -> Self {
+    /// `pretty` controls whether or not encoded values are pretty-printed; [`Codec::compact`] and
+    /// [`Codec::pretty_printed`] say which at the call site.
+    pub const fn new(pretty: bool) -> Self {
         Self {
             pretty,
-            _priv: (PhantomData, PhantomData),
+            js_compatible: false,
+            framing: Framing::Concatenated,
+            terminator: Vec::new(),
+            encode_array: false,
+            array_open: false,
+            error_context: false,
+            deny_unknown_fields: false,
+            error_policy: ErrorPolicy::Fail,
+            default_item: None,
+            unbounded_depth: false,
+            lossy_utf8: false,
+            max_frame_size: None,
+            limits: validate::Limits::new(),
+            filters: filter::Filters::new(),
+            keepalives: keepalive::Keepalives::new(),
+            skip_boms: false,
+            bom_due: true,
+            dedupe: None,
+            sentinel: None,
+            sentinel_received: false,
+            datagrams: false,
+            require_separators: false,
+            resync: false,
+            separator_due: false,
+            unbatch_arrays: false,
+            unbatched: VecDeque::new(),
+            keep_last_frame: false,
+            last_frame: None,
+            stall: None,
+            on_error: None,
+            capture_frames: None,
+            pending_frame: None,
+            scan: lex::Scan::new(),
+            reserve: None,
+            encoded_size: reserve::Average::new(0),
+            boundaries: None,
+            positions: None,
+            slow_frame: None,
+            slow_frames: 0,
+            malformed: 0,
+            malformed_bytes: 0,
+            max_malformed: None,
+            skipped: 0,
+            substituted: 0,
+            decoded_bytes: 0,
+            split_bytes: 0,
+            frame_offset: 0,
+            split_frames: 0,
+            byte_quota: None,
+            decoded_frames: 0,
+            frame_quota: None,
+            label: None,
+            compressed_bytes: 0,
+            uncompressed_bytes: 0,
+            decoded: 0,
+            encoded: 0,
+            #[cfg(feature = "otel")]
+            trace: None,
+            #[cfg(feature = "jsonschema")]
+            schema: None,
+            #[cfg(feature = "jsonschema")]
+            validate_encoded: false,
+            _priv: PhantomData,
         }
     }
 
+    /// Creates a `Codec` that encodes values compactly, as [`Default`] does.
+    pub const fn compact() -> Self {
+        Self::new(false)
+    }
+
+    /// Creates a `Codec` that pretty-prints encoded values.
+    ///
+    /// This isn't called `pretty` because that name is taken by the setter.
+    pub const fn pretty_printed() -> Self {
+        Self::new(true)
+    }
+
+    /// Creates a compact `Codec` suited to untrusted peers.
+    ///
+    /// [Unknown fields][`Codec::deny_unknown_fields`] and
+    /// [duplicate keys][`Codec::deny_duplicate_keys`] are rejected, as are frames that
+    /// [aren't separated][`Codec::require_separators`] from the one before, and frames are limited
+    /// to strings of 1 MiB, arrays of 100,000 elements, objects of 10,000 members and 64 levels of
+    /// nesting. Any of these can be changed afterwards.
+    pub fn strict() -> Self {
+        let mut codec = Self::compact();
+        codec.deny_unknown_fields(true);
+        codec.deny_duplicate_keys(true);
+        codec.require_separators(true);
+        codec.max_string_len(Some(1 << 20));
+        codec.max_array_len(Some(100_000));
+        codec.max_object_len(Some(10_000));
+        codec.max_depth(Some(64));
+        codec
+    }
+
+    /// Creates a compact `Codec` whose output JavaScript peers read as intended; see
+    /// [`Codec::js_compatible`].
+    pub fn interop_js() -> Self {
+        let mut codec = Self::compact();
+        codec.js_compatible(true);
+        codec
+    }
+
     /// Set whether or not encoded values are pretty-printed.
     pub fn pretty(&mut self, pretty: bool) {
         self.pretty = pretty;
     }
-}
 
-impl<D, E> Default for Codec<D, E> {
-    fn default() -> Self {
-        Self::new(false)
+    /// Set whether or not encoded values are adjusted for JavaScript peers.
+    ///
+    /// Integers that a JavaScript number can't hold exactly, beyond ±(2^53 - 1), are
+    /// encoded as strings, and U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR are escaped,
+    /// since JavaScript before ES2019 doesn't allow them in string literals. Like subscriptions,
+    /// this converts each item into a [`Value`] first. Disabled by default.
+    pub fn js_compatible(&mut self, js_compatible: bool) {
+        self.js_compatible = js_compatible;
     }
-}
 
-impl<D, E> Decoder for Codec<D, E>
-where
-    for<'de> D: Deserialize<'de>,
-{
-    type Item = D;
-    type Error = Error;
+    /// Set whether or not decode errors include an excerpt of the input around the error in their
+    /// `Display` output.
+    ///
+    /// The excerpt is followed by a line with a caret pointing at the error position. The excerpt
+    /// is available either way from [`DecodeError::excerpt`].
+    pub fn error_context(&mut self, error_context: bool) {
+        self.error_context = error_context;
+    }
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
-        let slice = &src.clone();
-        let mut de = serde_json::Deserializer::from_slice(slice).into_iter();
-        match de.next() {
-            Some(Ok(v)) => {
-                src.advance(de.byte_offset());
-                Ok(Some(v))
-            }
-            Some(Err(e)) => {
-                if e.is_eof() {
-                    Ok(None)
-                } else {
-                    Err(e.into())
-                }
+    /// Set whether or not decoding rejects fields that the decoded type doesn't know about.
+    ///
+    /// This behaves as if `D` and every type it contains were marked
+    /// `#[serde(deny_unknown_fields)]`, failing with [`Error::UnknownField`]. Disabled by default.
+    pub fn deny_unknown_fields(&mut self, deny_unknown_fields: bool) {
+        self.deny_unknown_fields = deny_unknown_fields;
+    }
+
+    /// Set whether or not frames that are valid JSON but fail to deserialize into `D`, such as
+    /// variants of a message type that `D` doesn't model, are skipped rather than failing to
+    /// decode. Disabled by default.
+    ///
+    /// Skipped frames are counted by [`Codec::skipped_frames`]. Frames that aren't valid JSON
+    /// still fail to decode. This sets the [error policy][`Codec::error_policy`] to
+    /// [`ErrorPolicy::SkipFrame`] or [`ErrorPolicy::Fail`], without requiring `D: Default`.
+    pub fn skip_invalid_frames(&mut self, skip: bool) {
+        self.error_policy = if skip {
+            ErrorPolicy::SkipFrame
+        } else {
+            ErrorPolicy::Fail
+        };
+    }
+
+    /// Set what happens to frames that are valid JSON but fail to deserialize into `D`.
+    /// [`ErrorPolicy::Fail`] by default.
+    ///
+    /// Frames that aren't valid JSON fail to decode whatever the policy.
+    pub fn error_policy(&mut self, policy: ErrorPolicy)
+    where
+        D: Default,
+    {
+        self.error_policy = policy;
+        self.default_item = Some(D::default);
+    }
+
+    /// Set whether or not invalid UTF-8 in decoded frames is replaced with U+FFFD (the
+    /// replacement character) before they are parsed, rather than failing to decode.
+    ///
+    /// This lets a frame with mangled bytes inside a string still decode, with the string
+    /// degraded, while invalid bytes elsewhere still fail as a syntax error. Disabled by default.
+    pub fn lossy_utf8(&mut self, lossy_utf8: bool) {
+        self.lossy_utf8 = lossy_utf8;
+    }
+
+    /// Set whether or not decoding rejects objects with the same key more than once.
+    ///
+    /// `serde_json` otherwise keeps the last value, while other parsers may keep the first, which
+    /// lets a peer smuggle a value past a check made by one of them. Frames with duplicate keys
+    /// fail with [`Error::DuplicateKey`] before they are deserialized. Keys are compared after
+    /// unescaping, so `"a"` and `"\u0061"` are the same key. Disabled by default.
+    pub fn deny_duplicate_keys(&mut self, deny_duplicate_keys: bool) {
+        self.limits.deny_duplicate_keys = deny_duplicate_keys;
+    }
+
+    /// Set the maximum length of strings and object keys in decoded frames, in bytes as they appear
+    /// in the input.
+    ///
+    /// Longer strings fail with [`Error::LimitExceeded`] before the frame is deserialized, so they
+    /// are never allocated. Unlimited by default.
+    pub fn max_string_len(&mut self, max: Option<usize>) {
+        self.limits.max_string_len = max;
+    }
+
+    /// Set the maximum length of numbers in decoded frames, in bytes as they appear in the input.
+    ///
+    /// Longer numbers fail with [`Error::LimitExceeded`] before the frame is deserialized, so
+    /// they are never parsed into big-integer or arbitrary-precision types. Unlimited by default.
+    pub fn max_number_len(&mut self, max: Option<usize>) {
+        self.limits.max_number_len = max;
+    }
+
+    /// Set the maximum nesting depth of arrays and objects in decoded frames.
+    ///
+    /// Deeper frames fail with [`Error::LimitExceeded`] before the frame is deserialized.
+    /// Unlimited by default, although `serde_json` separately fails with
+    /// [`Error::DepthExceeded`] past 128 levels unless [`Codec::unbounded_depth`] is set.
+    pub fn max_depth(&mut self, max: Option<usize>) {
+        self.limits.max_depth = max;
+    }
+
+    /// Set whether or not to lift `serde_json`'s recursion limit of 128 levels when decoding.
+    ///
+    /// Frames are then deserialized on a stack that grows as they nest deeper, so that deeply
+    /// nested but legitimate frames, such as machine-generated syntax trees, decode. That takes
+    /// unbounded memory, so this is only appropriate for trusted input, preferably along with
+    /// [`Codec::max_depth`]. Disabled by default.
+    #[cfg(feature = "unbounded_depth")]
+    pub fn unbounded_depth(&mut self, unbounded_depth: bool) {
+        self.unbounded_depth = unbounded_depth;
+    }
+
+    /// Set the maximum number of elements in each array in decoded frames.
+    ///
+    /// Larger arrays fail with [`Error::LimitExceeded`] before the frame is deserialized.
+    /// Unlimited by default.
+    pub fn max_array_len(&mut self, max: Option<usize>) {
+        self.limits.max_array_len = max;
+    }
+
+    /// Set the maximum number of members in each object in decoded frames.
+    ///
+    /// Larger objects fail with [`Error::LimitExceeded`] before the frame is deserialized.
+    /// Unlimited by default.
+    pub fn max_object_len(&mut self, max: Option<usize>) {
+        self.limits.max_object_len = max;
+    }
+
+    /// Set the maximum size of decoded frames, in bytes.
+    ///
+    /// Larger frames fail with [`Error::FrameTooLarge`]. This includes a frame that is still
+    /// incomplete once more than `max` bytes are buffered, which is discarded along with the rest
+    /// of the buffered input rather than buffered indefinitely, and a [`Compressed`] frame that
+    /// decompresses to more than `max` bytes. Unlimited by default.
+    pub fn max_frame_size(&mut self, max: Option<usize>) {
+        self.max_frame_size = max;
+    }
+
+    /// Set the types that the top-level value of each decoded frame may have, or `None` to allow
+    /// any, as by default.
+    ///
+    /// Frames of other types fail with [`Error::UnexpectedType`] before they are deserialized,
+    /// which makes for a clearer error than the decoded type's when, say, a protocol whose
+    /// messages are all objects receives a bare number.
+    pub fn top_level_types(&mut self, types: Option<&[JsonType]>) {
+        self.limits.top_level_types = types.map(<[JsonType]>::to_vec);
+    }
+
+    /// Subscribes to decoded frames where the value at `pointer` satisfies `predicate`.
+    ///
+    /// `pointer` is a JSON Pointer ([RFC 6901]) such as `/event/type`, or `""` for the whole
+    /// frame. Once any subscriptions are registered, each frame is parsed into a [`Value`] first,
+    /// and frames that no subscription matches are discarded without being deserialized into
+    /// `D`. Matching frames are deserialized from that `Value`, so their errors don't carry the
+    /// position in the input.
+    ///
+    /// [RFC 6901]: https://tools.ietf.org/html/rfc6901
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is neither empty nor starts with `/`.
+    pub fn subscribe<F>(&mut self, pointer: &str, predicate: F)
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        assert!(
+            pointer.is_empty() || pointer.starts_with('/'),
+            "invalid JSON Pointer `{}`",
+            pointer
+        );
+        self.filters.push(pointer, Arc::new(predicate));
+    }
+
+    /// Removes all subscriptions, so that every frame is decoded again.
+    pub fn clear_subscriptions(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Drops decoded frames with the same id as a frame seen within the last `ttl`.
+    ///
+    /// The id is the value at `pointer`, a JSON Pointer as for [`Codec::subscribe`]; frames
+    /// without one are always decoded. Dropped frames are counted in [`Codec::duplicates`]. Like
+    /// subscriptions, this parses each frame into a [`Value`] first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is neither empty nor starts with `/`.
+    pub fn deduplicate(&mut self, pointer: &str, ttl: Duration) {
+        assert!(
+            pointer.is_empty() || pointer.starts_with('/'),
+            "invalid JSON Pointer `{}`",
+            pointer
+        );
+        self.dedupe = Some(dedupe::Dedupe::new(pointer, ttl));
+    }
+
+    /// Returns how many frames were dropped as [duplicates][`Codec::deduplicate`].
+    pub fn duplicates(&self) -> u64 {
+        self.dedupe.as_ref().map_or(0, |dedupe| dedupe.duplicates)
+    }
+
+    /// Registers a keepalive token, such as `PING`, that the peer may send between frames.
+    ///
+    /// Keepalive tokens are skipped and counted in [`Codec::keepalives`] instead of failing to
+    /// parse. Whitespace between frames, including blank lines, is always skipped, but it is only
+    /// counted if it is registered as a token, such as `\r\n`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is empty.
+    pub fn keepalive_token(&mut self, token: &str) {
+        assert!(!token.is_empty(), "empty keepalive token");
+        self.keepalives.push(token.as_bytes());
+    }
+
+    /// Removes all keepalive tokens.
+    pub fn clear_keepalive_tokens(&mut self) {
+        self.keepalives.clear();
+    }
+
+    /// Returns how many [keepalive tokens][`Codec::keepalive_token`] were skipped.
+    pub fn keepalives(&self) -> u64 {
+        self.keepalives.count
+    }
+
+    /// Set whether or not to skip a UTF-8 byte order mark before each frame, as left by tools that
+    /// concatenate files exported on Windows. A byte order mark at the start of the input is
+    /// always skipped. Disabled by default.
+    pub fn skip_boms(&mut self, skip_boms: bool) {
+        self.skip_boms = skip_boms;
+    }
+
+    /// Set a sentinel value, such as `null` or `{"type": "bye"}`, that marks a graceful close.
+    ///
+    /// A decoded frame equal to the sentinel isn't decoded as an item: instead, decoding stops,
+    /// leaving any bytes after it in the buffer, and [`Codec::sentinel_received`] returns `true`.
+    /// A [`FramedJson`] ends its stream at that point, and sends the sentinel when its sink is
+    /// closed. Like subscriptions, this parses each frame into a [`Value`] first.
+    pub fn sentinel(&mut self, sentinel: Option<Value>) {
+        self.sentinel = sentinel;
+    }
+
+    /// Returns whether the [sentinel][`Codec::sentinel`] has been decoded.
+    pub fn sentinel_received(&self) -> bool {
+        self.sentinel_received
+    }
+
+    /// Set how frames are delimited on the wire, for both decoding and encoding.
+    ///
+    /// With any framing, frames holding only whitespace, such as the blank lines or records some
+    /// servers send as keepalives, are skipped. [`Framing::Concatenated`] by default.
+    pub fn framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// Set bytes to write after each encoded frame, such as `b"\n"` or `b"\r\n"`, for
+    /// line-oriented consumers, without affecting decoding.
+    ///
+    /// The terminator follows whatever the [framing][`Codec::framing`] adds. Nothing by default.
+    pub fn terminator(&mut self, terminator: &[u8]) {
+        self.terminator = terminator.to_owned();
+    }
+
+    /// Set whether or not to encode items as the elements of one JSON array, rather than as
+    /// frames of their own, for clients that only accept a single JSON document.
+    ///
+    /// The array is opened by the first item, and closed when the sink of a [`FramedJson`] is
+    /// closed, after the [sentinel][`Codec::sentinel`] if there is one; an empty array is sent if
+    /// there were no items. Items aren't delimited as set by [`Codec::framing`]. To decode such an
+    /// array as it arrives, see [`Codec::elements`]. Disabled by default.
+    pub fn encode_array(&mut self, encode_array: bool) {
+        self.encode_array = encode_array;
+    }
+
+    /// Set whether or not frames must be separated by whitespace, such as the newlines of
+    /// newline-delimited JSON, rather than just follow each other as in `{"a":1}{"b":2}`.
+    ///
+    /// Decoding a frame that directly follows the previous one fails once, and then carries on
+    /// with the frame. This only matters with [`Framing::Concatenated`], as
+    /// other framings delimit frames themselves. Disabled by default.
+    pub fn require_separators(&mut self, require_separators: bool) {
+        self.require_separators = require_separators;
+        self.separator_due = false;
+    }
+
+    /// Set whether or not decoding skips ahead after a syntax error that it can't otherwise get
+    /// past, so that one corrupt frame doesn't end the stream.
+    ///
+    /// Such errors happen with [`Framing::Concatenated`], where the codec can't tell where the
+    /// malformed frame ends. Decoding carries on after the next newline or RS byte following the
+    /// error, or if there isn't one buffered, from the next `{` or `[`, which may start the next
+    /// frame; if there is neither, the buffered input is dropped. The error is still returned,
+    /// and [`Error::is_fatal`] returns `false` for it. Disabled by default.
+    pub fn resync(&mut self, resync: bool) {
+        self.resync = resync;
+    }
+
+    /// Set whether or not each call to decode is given one whole datagram, as by tokio's
+    /// `UdpFramed`, which must then hold exactly one frame.
+    ///
+    /// Decoding then always consumes the input, and a datagram holding an incomplete frame, or
+    /// anything but whitespace after the frame, fails to decode instead of being left for the
+    /// next one. Datagrams holding only whitespace are skipped. Frames aren't delimited as set by
+    /// [`Codec::framing`]. Disabled by default.
+    pub fn datagrams(&mut self, datagrams: bool) {
+        self.datagrams = datagrams;
+    }
+
+    /// Set whether or not to decode the elements of each top-level array frame as separate items.
+    ///
+    /// Subscriptions, deduplication and the sentinel then apply to each element rather than to
+    /// the array. This only affects decoding with the `Codec` itself, not through wrappers such as
+    /// [`WithRaw`]. Disabled by default.
+    pub fn unbatch_arrays(&mut self, unbatch_arrays: bool) {
+        self.unbatch_arrays = unbatch_arrays;
+    }
+
+    /// Set whether or not to keep the raw bytes of the most recently decoded frame, for
+    /// [`Codec::last_frame_raw`]. Disabled by default.
+    pub fn keep_last_frame(&mut self, keep_last_frame: bool) {
+        self.keep_last_frame = keep_last_frame;
+        if !keep_last_frame {
+            self.last_frame = None;
+        }
+    }
+
+    /// Returns the raw bytes of the most recent frame, if [`Codec::keep_last_frame`] is enabled.
+    ///
+    /// The frame is kept whether or not it was deserialized successfully, so this can be used to
+    /// log exactly what was received when decoding fails. Input that isn't even syntactically
+    /// valid JSON never forms a frame; see [`DecodeError::input`] instead.
+    pub fn last_frame_raw(&self) -> Option<&Bytes> {
+        self.last_frame.as_ref()
+    }
+
+    /// Returns whether decoding needs each frame to be split off before it is deserialized.
+    fn needs_frame(&self) -> bool {
+        #[cfg(feature = "jsonschema")]
+        {
+            if self.schema.is_some() {
+                return true;
             }
-            None => {
-                // The remaining stream is whitespace; clear the buffer so Decoder::decode_eof
-                // doesn't return an Err
-                src.clear();
-                Ok(None)
+        }
+        #[cfg(feature = "otel")]
+        {
+            if self.trace.is_some() {
+                return true;
             }
         }
+        self.deny_unknown_fields
+            || self.error_policy != ErrorPolicy::Fail
+            || self.lossy_utf8
+            || self.framing != Framing::Concatenated
+            || self.require_separators
+            || self.max_frame_size.is_some()
+            || self.limits.is_enabled()
+            || !self.filters.is_empty()
+            || self.dedupe.is_some()
+            || self.sentinel.is_some()
+            || self.unbatch_arrays
+            || self.keep_last_frame
+            || self.boundaries.is_some()
+            || self.positions.is_some()
     }
-}
 
-impl<D, E> Encoder for Codec<D, E>
-where
-    E: Serialize,
-{
-    type Item = E;
-    type Error = Error;
+    /// Applies the codec's error reporting settings to an error produced while decoding.
+    fn annotate(&self, mut err: Error) -> Error {
+        if let Error::Decode(ref mut e) = err {
+            e.context = self.error_context;
+        }
+        self.label_error(self.capture_frame(err))
+    }
 
-    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
-        let writer = BytesWriter(dst);
-        if self.pretty {
-            serde_json::to_writer_pretty(writer, &item)?;
-        } else {
-            serde_json::to_writer(writer, &item)?;
+    /// Set a label identifying the peer, such as its address or a connection id, or `None` for
+    /// no label, as by default.
+    ///
+    /// Errors produced by the codec are then [`Error::Labeled`] with the label, which is shown in
+    /// their `Display` and `Debug` output. Use [`Error::unlabeled`] to match on the underlying
+    /// error.
+    pub fn peer_label(&mut self, label: Option<&str>) {
+        self.label = label.map(Arc::from);
+    }
+
+    /// Wraps `err` with the codec's peer label, if it has one.
+    fn label_error(&self, err: Error) -> Error {
+        match self.label {
+            Some(ref label) if err.label().is_none() => Error::Labeled {
+                label: label.clone(),
+                error: Box::new(err),
+            },
+            _ => err,
         }
-        Ok(())
     }
-}
 
-/// The [`Error`][`std::error::Error`] type for this crate.
-///
-/// This is necessary to not lose information about the error. [`Encoder`] requires that the Error
-/// implement `From<std::io::Error>`, and while a [`serde_json::Error`] can possibly be an IO
-/// error, there's no way to combine the two.
-///
-/// If you just want an [`io::Error`], `From<Error>` is implemented for it.
-#[derive(Debug)]
-pub enum Error {
-    /// A [`io::Error`].
-    Io(io::Error),
-    /// A [`serde_json::Error`].
-    Json(serde_json::Error),
-}
+    /// Set bounds for sizing the read buffer to fit the frames being received, or `None` to leave
+    /// that to the [`Framed`][`tokio_codec::Framed`], as by default.
+    ///
+    /// The codec keeps a moving average of frame sizes. While part of a frame is buffered, the
+    /// buffer is grown to fit a frame of that size at once, and an empty buffer that is more than
+    /// twice that size is replaced by a smaller one. The size is clamped to `bounds`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bounds` is empty.
+    pub fn adaptive_buffer(&mut self, bounds: Option<RangeInclusive<usize>>) {
+        self.reserve = bounds.map(|bounds| {
+            assert!(!bounds.is_empty(), "empty buffer size bounds");
+            reserve::Reserve::new(*bounds.start(), *bounds.end())
+        });
+    }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Error::Io(e) => e.fmt(f),
-            Error::Json(e) => e.fmt(f),
+    /// Registers a callback fired when the peer appears to be stalled.
+    ///
+    /// The callback is invoked, once per stall, when bytes have been buffered for longer than
+    /// `period` without a complete frame being decoded, and receives how long the incomplete frame
+    /// has been pending. This usually means the peer is desynchronized or misbehaving. Unlike an
+    /// idle timeout, a peer that sends nothing at all never stalls.
+    pub fn on_stall<F>(&mut self, period: Duration, callback: F)
+    where
+        F: Fn(Duration) + Send + Sync + 'static,
+    {
+        self.stall = Some(Stall {
+            period,
+            callback: Arc::new(callback),
+            pending_since: None,
+            fired: false,
+        });
+    }
+
+    /// Registers a callback invoked whenever a frame fails to decode, such as to record metrics
+    /// or sample bad input.
+    ///
+    /// The callback receives the error, as it is about to be returned, and the raw bytes of the
+    /// frame. For input that never formed a frame, those are the input from where decoding
+    /// stopped, as in [`DecodeError::input`], and they may be empty when the framing failed. It
+    /// is also invoked for frames that the [error policy][`Codec::error_policy`] skips or
+    /// substitutes instead of failing. I/O errors aren't reported.
+    pub fn on_error<F>(&mut self, callback: F)
+    where
+        F: Fn(&Error, &[u8]) + Send + Sync + 'static,
+    {
+        self.on_error = Some(OnError(Arc::new(callback)));
+    }
+
+    /// Passes a decode error to the [`Codec::on_error`] callback, if there is one.
+    fn report_error(&self, err: &Error) {
+        if let Some(OnError(ref callback)) = self.on_error {
+            if let Error::Io(_) = err.unlabeled() {
+                return;
+            }
+            callback(err, self.failed_input(err).unwrap_or(&[]));
         }
     }
-}
 
-impl std::error::Error for Error {}
+    /// Set the maximum number of bytes of a frame that fails to decode to keep in the error, or
+    /// `None` to keep none, as by default.
+    ///
+    /// Errors are then [`Error::WithFrame`] with the raw bytes of the frame, cut short to `max`,
+    /// so that the frame can be persisted for later analysis or reprocessing; see
+    /// [`Error::frame`]. For input that never formed a frame, those are the input from where
+    /// decoding stopped, as in [`DecodeError::input`]. Errors that don't come from a frame, such
+    /// as I/O errors and most framing errors, are left as they are. The bytes are copied, so
+    /// that the error doesn't hold on to the read buffer.
+    pub fn capture_failed_frames(&mut self, max: Option<usize>) {
+        self.capture_frames = max;
+    }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Error::Io(err)
+    /// Returns the raw bytes that a decode error occurred in, if they are known.
+    fn failed_input<'a>(&'a self, err: &'a Error) -> Option<&'a [u8]> {
+        match err.unlabeled() {
+            Error::Decode(e) if !e.in_frame => Some(e.input()),
+            _ => self.pending_frame.as_ref().map(|frame| &frame[..]),
+        }
     }
-}
 
-impl From<serde_json::Error> for Error {
-    fn from(err: serde_json::Error) -> Self {
-        Error::Json(err)
+    /// Wraps a decode error with the raw bytes of its frame if [`Codec::capture_failed_frames`]
+    /// is set.
+    fn capture_frame(&self, err: Error) -> Error {
+        let max = match (self.capture_frames, &err) {
+            (_, Error::Io(_)) | (None, _) => return err,
+            (Some(max), _) => max,
+        };
+        let (frame, len) = match self.failed_input(&err) {
+            Some(raw) => (Bytes::from(&raw[..raw.len().min(max)]), raw.len()),
+            None => return err,
+        };
+        Error::WithFrame {
+            frame,
+            len,
+            error: Box::new(err),
+        }
     }
-}
 
-impl From<Error> for io::Error {
-    fn from(err: Error) -> Self {
-        match err {
-            Error::Io(e) => e,
-            Error::Json(e) => e.into(),
+    /// Returns whether the frame being decoded needs to be kept in case it fails.
+    fn keeps_pending_frame(&self) -> bool {
+        self.on_error.is_some() || self.capture_frames.is_some()
+    }
+
+    /// Set whether or not to track the [`Position`] of each frame in the input, for
+    /// [`Codec::last_position`]. Offsets count from when tracking was enabled. Disabled by
+    /// default.
+    pub fn track_positions(&mut self, track: bool) {
+        if !track {
+            self.positions = None;
+        } else if self.positions.is_none() {
+            self.positions = Some(position::Positions::default());
         }
     }
-}
 
-/// Wrapper for `&mut [BytesMut]` that provides Write.
-///
-/// See also:
-/// * <https://github.com/vorner/tokio-serde-cbor/blob/a347107ad56f2ad8086998eb63ecb70b19f3b71d/src/lib.rs#L167-L181>
-/// * <https://github.com/carllerche/bytes/issues/77>
-struct BytesWriter<'a>(&'a mut BytesMut);
+    /// Returns the position of the most recent frame, if [`Codec::track_positions`] is enabled.
+    ///
+    /// Like [`Codec::last_frame_raw`], this is kept whether or not the frame was deserialized
+    /// successfully, so it can be used to report where invalid records are.
+    pub fn last_position(&self) -> Option<Position> {
+        self.positions.as_ref().and_then(|positions| positions.last)
+    }
 
-impl<'a> io::Write for BytesWriter<'a> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.extend(buf);
-        Ok(buf.len())
+    /// Records that `consumed` bytes of the input were consumed, ending with a frame of `len`
+    /// bytes if one was split off.
+    fn split_off(&mut self, consumed: usize, len: Option<usize>) {
+        self.split_bytes = self.split_bytes.saturating_add(consumed as u64);
+        if let Some(len) = len {
+            self.frame_offset = self.split_bytes.saturating_sub(len as u64);
+            self.split_frames = self.split_frames.saturating_add(1);
+        }
+        if let Some(ref mut boundaries) = self.boundaries {
+            boundaries.decoded(consumed, len);
+        }
+        if let Some(ref mut positions) = self.positions {
+            positions.decoded(consumed, len);
+        }
     }
-    fn flush(&mut self) -> io::Result<()> {
-        Ok(())
+
+    /// Registers a callback fired for every frame boundary the codec identifies.
+    ///
+    /// Inbound frames are reported as soon as they are split off the input, whether or not they
+    /// go on to deserialize successfully, and outbound frames as they are encoded. Stream offsets
+    /// count from when the callback was registered. Replaces any previously registered callback.
+    pub fn on_frame<F>(&mut self, callback: F)
+    where
+        F: Fn(&FrameBoundary) + Send + Sync + 'static,
+    {
+        self.boundaries = Some(boundary::Boundaries::new(Arc::new(callback)));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use bytes::{BufMut, BytesMut};
-    use tokio_codec::{Decoder, Encoder};
-    use Codec;
+    /// Set the duration above which decoding or encoding a single frame is considered slow.
+    ///
+    /// Slow frames are logged as a `tracing` warning with their size and type, and counted in
+    /// [`Codec::slow_frames`]. Disabled by default.
+    pub fn slow_frame_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_frame = threshold;
+    }
 
-    #[test]
-    fn decode_empty() {
-        let mut buf = BytesMut::from(&b""[..]);
-        let mut codec: Codec<(), ()> = Codec::default();
-        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    /// Returns how many frames took longer than the
+    /// [slow-frame threshold][`Codec::slow_frame_threshold`] to decode or encode.
+    pub fn slow_frames(&self) -> u64 {
+        self.slow_frames
     }
 
-    #[test]
-    fn decode() {
-        let mut buf = BytesMut::from(&b"null null null"[..]);
-        let mut codec: Codec<_, ()> = Codec::default();
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode(&mut buf).unwrap(), None);
-        assert!(buf.is_empty());
+    /// Set how many frames may fail to decode before the codec gives up on the peer, or `None`
+    /// for no limit, as by default.
+    ///
+    /// Once `max` frames have failed, decoding fails with [`Error::TooManyMalformedFrames`]
+    /// instead of trying again, and a [`FramedJson`] ends its stream.
+    pub fn max_malformed_frames(&mut self, max: Option<u64>) {
+        self.max_malformed = max;
     }
 
-    #[test]
-    fn decode_partial() {
-        let mut buf = BytesMut::from(&b"null null nu"[..]);
-        let mut codec: Codec<_, ()> = Codec::default();
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode(&mut buf).unwrap(), None);
-        assert_eq!(buf, &b" nu"[..]);
-        buf.put(&b"ll"[..]);
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert!(buf.is_empty());
+    /// Returns how many frames failed to decode.
+    pub fn malformed_frames(&self) -> u64 {
+        self.malformed
     }
 
-    #[test]
-    fn decode_eof_trailing_whitespae() {
-        let mut buf = BytesMut::from(&b"null\n"[..]);
-        let mut codec: Codec<_, ()> = Codec::default();
-        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(()));
-        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
-        assert!(buf.is_empty());
+    /// Returns how many bytes were consumed by frames that failed to decode.
+    pub fn malformed_bytes(&self) -> u64 {
+        self.malformed_bytes
     }
 
-    #[test]
-    fn decode_err() {
-        let mut buf = BytesMut::from(&b"null butts"[..]);
-        let mut codec: Codec<_, ()> = Codec::default();
-        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
-        assert!(codec.decode(&mut buf).is_err());
+    /// Returns how many frames were [skipped][`Codec::skip_invalid_frames`] for failing to
+    /// deserialize.
+    pub fn skipped_frames(&self) -> u64 {
+        self.skipped
     }
 
-    #[test]
-    fn encode() {
-        let mut buf = BytesMut::new();
-        let mut codec: Codec<(), _> = Codec::default();
-        codec.encode((), &mut buf).unwrap();
-        assert_eq!(buf, &b"null"[..]);
+    /// Returns how many frames were replaced with `D::default()` for failing to deserialize; see
+    /// [`ErrorPolicy::SubstituteDefault`].
+    pub fn substituted_frames(&self) -> u64 {
+        self.substituted
+    }
+
+    /// Returns whether the [malformed-frame limit][`Codec::max_malformed_frames`] was reached.
+    pub(crate) fn malformed_limit_reached(&self) -> bool {
+        self.max_malformed.is_some_and(|max| self.malformed >= max)
+    }
+
+    /// Set how many bytes the codec may decode over its lifetime, or `None` for no limit, as by
+    /// default.
+    ///
+    /// The frame that takes the [decoded bytes][`Codec::decoded_bytes`] past `quota` fails with
+    /// [`Error::QuotaExceeded`] instead of being returned, as does decoding from then on, and a
+    /// [`FramedJson`] ends its stream.
+    pub fn byte_quota(&mut self, quota: Option<u64>) {
+        self.byte_quota = quota;
+    }
+
+    /// Returns how many bytes of input the codec has consumed while decoding, including those of
+    /// frames that failed to decode. Unlike the [stats][`Codec::stats_reset`], this is never reset.
+    pub fn decoded_bytes(&self) -> u64 {
+        self.decoded_bytes
+    }
+
+    /// Set how many frames the codec may decode over its lifetime, or `None` for no limit, as by
+    /// default.
+    ///
+    /// Like the [byte quota][`Codec::byte_quota`], the frame after the first `quota` fails with
+    /// [`Error::FrameQuotaExceeded`] instead of being returned, as does decoding from then on, and
+    /// a [`FramedJson`] ends its stream.
+    pub fn frame_quota(&mut self, quota: Option<u64>) {
+        self.frame_quota = quota;
+    }
+
+    /// Returns how many frames the codec has decoded. Like [`Codec::decoded_bytes`], this is never
+    /// reset.
+    pub fn decoded_frames(&self) -> u64 {
+        self.decoded_frames
+    }
+
+    /// Returns the error for the [byte quota][`Codec::byte_quota`] or
+    /// [frame quota][`Codec::frame_quota`] if either was exceeded.
+    fn quota_error(&self) -> Option<Error> {
+        if let Some(quota) = self.byte_quota.filter(|&quota| self.decoded_bytes > quota) {
+            return Some(Error::QuotaExceeded { quota });
+        }
+        self.frame_quota
+            .filter(|&quota| self.decoded_frames > quota)
+            .map(|quota| Error::FrameQuotaExceeded { quota })
+    }
+
+    /// Returns whether the byte quota or frame quota was exceeded.
+    pub(crate) fn quota_exceeded(&self) -> bool {
+        self.quota_error().is_some()
+    }
+
+    /// Returns a snapshot of the codec's counters.
+    pub fn stats_snapshot(&self) -> Stats {
+        Stats {
+            decoded: self.decoded,
+            encoded: self.encoded,
+            duplicates: self.duplicates(),
+            keepalives: self.keepalives(),
+            malformed: self.malformed,
+            malformed_bytes: self.malformed_bytes,
+            skipped: self.skipped,
+            substituted: self.substituted,
+            uncompressed_bytes: self.uncompressed_bytes,
+            compressed_bytes: self.compressed_bytes,
+            slow_frames: self.slow_frames,
+        }
+    }
+
+    /// Resets the codec's counters to zero, returning a snapshot of them from before the reset.
+    pub fn stats_reset(&mut self) -> Stats {
+        let stats = self.stats_snapshot();
+        self.decoded = 0;
+        self.encoded = 0;
+        if let Some(ref mut dedupe) = self.dedupe {
+            dedupe.duplicates = 0;
+        }
+        self.keepalives.count = 0;
+        self.malformed = 0;
+        self.malformed_bytes = 0;
+        self.skipped = 0;
+        self.substituted = 0;
+        self.compressed_bytes = 0;
+        self.uncompressed_bytes = 0;
+        self.slow_frames = 0;
+        stats
+    }
+
+    /// Checks the time it took to process a frame against the slow-frame threshold.
+    fn check_slow_frame<T>(&mut self, direction: &str, started: Option<Instant>, size: usize) {
+        let elapsed = match started {
+            Some(started) => started.elapsed(),
+            None => return,
+        };
+        if self
+            .slow_frame
+            .is_some_and(|threshold| elapsed >= threshold)
+        {
+            self.slow_frames = self.slow_frames.saturating_add(1);
+            tracing::warn!(
+                direction,
+                size,
+                r#type = type_name::<T>(),
+                elapsed_us = elapsed.as_micros() as u64,
+                "slow JSON frame"
+            );
+        }
+    }
+
+    /// Enables OpenTelemetry trace-context propagation through the object member `field`
+    /// (requires the `otel` feature).
+    ///
+    /// When an inbound object frame has a `field` member holding a carrier (such as
+    /// `{"traceparent": "..."}`), the member is removed, the remote context is extracted with the
+    /// global text map propagator and a `decode` span is started as its child. The context
+    /// including that span is available from [`Codec::trace_context`] until the next frame.
+    ///
+    /// Outbound object frames have the current context injected under `field`.
+    #[cfg(feature = "otel")]
+    pub fn propagate_trace_context(&mut self, field: Option<&str>) {
+        self.trace = field.map(otel::TraceContext::new);
+    }
+
+    /// Returns the trace context of the most recently decoded frame, if trace-context propagation
+    /// is enabled (requires the `otel` feature).
+    #[cfg(feature = "otel")]
+    pub fn trace_context(&self) -> Option<&opentelemetry::Context> {
+        self.trace.as_ref().and_then(otel::TraceContext::last)
+    }
+
+    /// Set a compiled JSON Schema that decoded frames must be valid against, or `None` for no
+    /// schema, as by default (requires the `jsonschema` feature).
+    ///
+    /// Each frame is validated after it is parsed and before it is deserialized into `D`, failing
+    /// with [`Error::SchemaViolation`] if it isn't valid.
+    #[cfg(feature = "jsonschema")]
+    pub fn schema(&mut self, schema: Option<jsonschema::Validator>) {
+        self.schema = schema.map(Arc::new);
+    }
+
+    /// Set whether or not encoded items are validated against the [schema][`Codec::schema`] too,
+    /// failing to encode if they aren't valid (requires the `jsonschema` feature). Disabled by
+    /// default.
+    #[cfg(feature = "jsonschema")]
+    pub fn validate_encoded(&mut self, validate_encoded: bool) {
+        self.validate_encoded = validate_encoded;
+    }
+}
+
+type ErrorCallback = Arc<dyn Fn(&Error, &[u8]) + Send + Sync>;
+
+/// An error callback; see [`Codec::on_error`].
+#[derive(Clone)]
+struct OnError(ErrorCallback);
+
+impl fmt::Debug for OnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnError").finish()
+    }
+}
+
+/// Stall detection state; see [`Codec::on_stall`].
+#[derive(Clone)]
+struct Stall {
+    period: Duration,
+    callback: Arc<dyn Fn(Duration) + Send + Sync>,
+    pending_since: Option<Instant>,
+    fired: bool,
+}
+
+impl Stall {
+    /// Records that `decode` returned without a frame while `pending` bytes were buffered.
+    fn pending(&mut self, pending: bool) {
+        if !pending {
+            self.reset();
+            return;
+        }
+        let now = Instant::now();
+        let pending_for = now - *self.pending_since.get_or_insert(now);
+        if !self.fired && pending_for >= self.period {
+            self.fired = true;
+            (self.callback)(pending_for);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending_since = None;
+        self.fired = false;
+    }
+}
+
+impl fmt::Debug for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stall")
+            .field("period", &self.period)
+            .field("pending_since", &self.pending_since)
+            .field("fired", &self.fired)
+            .finish()
+    }
+}
+
+impl<D, E> Default for Codec<D, E> {
+    fn default() -> Self {
+        Self::compact()
+    }
+}
+
+impl<D, E> Decoder for Codec<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        if self.datagrams {
+            return self.decode_with(src, Self::decode_datagram);
+        }
+        self.decode_with(src, Self::decode_frame)
+    }
+
+    /// Decodes an item like `decode`, failing with [`Error::IncompleteFrame`] if the input ends in
+    /// the middle of a frame.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let decode = if self.datagrams {
+            Self::decode_datagram
+        } else {
+            Self::decode_frame
+        };
+        self.decode_with(src, |codec, src| match decode(codec, src)? {
+            None if !src.is_empty() => {
+                let len = src.len();
+                let pending = src.take().freeze();
+                codec.split_off(len, None);
+                codec.scan = lex::Scan::new();
+                let err = Error::incomplete_frame(&pending);
+                if codec.keeps_pending_frame() {
+                    codec.pending_frame = Some(pending);
+                }
+                Err(err)
+            }
+            item => Ok(item),
+        })
+    }
+}
+
+impl<D, E> Codec<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    /// Decodes an item from `src` with `decode`, taking care of error annotation and bookkeeping.
+    fn decode_with<T, F>(&mut self, src: &mut BytesMut, decode: F) -> Result<Option<T>, Error>
+    where
+        F: FnOnce(&mut Self, &mut BytesMut) -> Result<Option<T>, Error>,
+    {
+        if self.sentinel_received {
+            return Ok(None);
+        }
+        if self.malformed_limit_reached() {
+            return Err(self.label_error(Error::TooManyMalformedFrames {
+                count: self.malformed,
+            }));
+        }
+        if let Some(e) = self.quota_error() {
+            return Err(self.label_error(e));
+        }
+        let (started, len) = (self.slow_frame.map(|_| Instant::now()), src.len());
+        self.split_bytes = self.decoded_bytes;
+        // Elements queued by `unbatch_arrays` are still from the last frame.
+        if self.unbatched.is_empty() {
+            self.pending_frame = None;
+        }
+        let result = match decode(self, src) {
+            Err(Error::Decode(mut e)) if self.resync && !e.in_frame => {
+                e.start = Some(self.decoded_bytes + (len - src.len()) as u64);
+                let skip = resync_point(src, e.offset());
+                src.advance(skip);
+                self.split_off(skip, None);
+                self.scan = lex::Scan::new();
+                e.resynced = true;
+                Err(Error::Decode(e))
+            }
+            result => result,
+        };
+        self.decoded_bytes = self.decoded_bytes.saturating_add((len - src.len()) as u64);
+        if let Ok(Some(_)) = result {
+            self.decoded_frames = self.decoded_frames.saturating_add(1);
+        }
+        let item = match result {
+            Ok(item) => match self.quota_error() {
+                Some(e) => return Err(self.label_error(e)),
+                None => item,
+            },
+            Err(mut e) => {
+                if let Error::Decode(ref mut e) = e {
+                    // Unless it is in a frame, the input starts where decoding stopped, with the
+                    // next frame.
+                    let (start, frame) = if e.in_frame {
+                        (self.frame_offset, self.split_frames)
+                    } else {
+                        (self.decoded_bytes, self.split_frames.saturating_add(1))
+                    };
+                    e.start.get_or_insert(start);
+                    e.frame.get_or_insert(frame);
+                }
+                if !matches!(e, Error::Io(_)) {
+                    self.malformed = self.malformed.saturating_add(1);
+                    let consumed = (len - src.len()) as u64;
+                    self.malformed_bytes = self.malformed_bytes.saturating_add(consumed);
+                }
+                let e = self.annotate(e);
+                self.report_error(&e);
+                return Err(e);
+            }
+        };
+        if item.is_some() {
+            self.decoded = self.decoded.saturating_add(1);
+            self.check_slow_frame::<D>("decode", started, len - src.len());
+        }
+        if let Some(ref mut reserve) = self.reserve {
+            if item.is_some() {
+                reserve.record(len - src.len());
+            }
+            reserve.adjust(src);
+        }
+        if let Some(ref mut stall) = self.stall {
+            if item.is_some() {
+                stall.reset();
+            } else {
+                stall.pending(!src.is_empty());
+            }
+        }
+        Ok(item)
+    }
+
+    /// Decodes the whole of `src` as one frame; see [`Codec::datagrams`].
+    fn decode_datagram(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let len = src.len();
+        let (frame, trailing) = framing::trim(src.take().freeze());
+        let frame = self.strip_bom(frame);
+        if frame.is_empty() {
+            self.split_off(len, None);
+            return Ok(None);
+        }
+        self.split_off(len - trailing, Some(frame.len()));
+        self.split_off(trailing, None);
+        self.decode_value(frame)
+    }
+
+    fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        let unbounded_depth = self.unbounded_depth;
+        if let Some(item) = self.next_unbatched()? {
+            return Ok(Some(item));
+        }
+        if !self.needs_frame() {
+            if !self.skip_bom(src) || !self.skip_keepalives(src) {
+                return Ok(None);
+            }
+            let item = next_value(src, unbounded_depth, &mut self.scan)?;
+            if item.is_some() {
+                self.split_frames = self.split_frames.saturating_add(1);
+            }
+            return Ok(item);
+        }
+        while let Some(frame) = self.next_frame(src)? {
+            let item = if self.unbatch_arrays && frame.first() == Some(&b'[') {
+                self.decode_batch(frame)?
+            } else {
+                self.decode_value(frame)?
+            };
+            if item.is_some() {
+                return Ok(item);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Splits the next complete frame off `src`, reporting its boundary.
+    ///
+    /// Frames holding only whitespace, such as the blank records some servers send as
+    /// keepalives, are skipped.
+    fn next_frame(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, Error> {
+        loop {
+            if self.sentinel_received {
+                return Ok(None);
+            }
+            if self.separator_due && !src.is_empty() {
+                self.separator_due = false;
+                if self.framing == Framing::Concatenated && !is_whitespace(src[0]) {
+                    return Err(Error::Framing {
+                        message: "expected whitespace between frames",
+                        resynced: true,
+                    });
+                }
+            }
+            let len = src.len();
+            let frame = if self.skip_bom(src) && self.skip_keepalives(src) {
+                if self.framing == Framing::Auto {
+                    self.framing = Framing::detect(src).unwrap_or(Framing::Auto);
+                }
+                self.framing
+                    .next_frame(src, self.unbounded_depth, &mut self.scan)
+            } else {
+                Ok(None)
+            };
+            let frame = match frame {
+                Ok(Some((frame, trailing))) if self.framing != Framing::Concatenated => {
+                    Ok(Some((self.strip_bom(frame), trailing)))
+                }
+                frame => frame,
+            };
+            let (frame, trailing) = match frame {
+                Ok(Some((ref frame, _))) if frame.is_empty() => {
+                    self.split_off(len - src.len(), None);
+                    continue;
+                }
+                Ok(Some((frame, trailing))) => (Some(frame), trailing),
+                Ok(None) => match self.max_frame_size {
+                    Some(max) if src.len() > max => {
+                        self.split_off(len, None);
+                        src.clear();
+                        self.scan = lex::Scan::new();
+                        return Err(Error::FrameTooLarge {
+                            max,
+                            resynced: self.framing.finds_next_frame(),
+                        });
+                    }
+                    _ => (None, 0),
+                },
+                Err(e) => {
+                    self.split_off(len - src.len(), None);
+                    return Err(e);
+                }
+            };
+            self.split_off(len - src.len() - trailing, frame.as_ref().map(Bytes::len));
+            self.split_off(trailing, None);
+            if frame.is_some() {
+                self.separator_due = self.require_separators;
+            }
+            return Ok(frame);
+        }
+    }
+
+    /// Skips keepalive tokens at the start of `src`, returning `false` if a partial one is left.
+    fn skip_keepalives(&mut self, src: &mut BytesMut) -> bool {
+        self.keepalives.is_empty() || self.keepalives.skip(src)
+    }
+
+    /// Skips a byte order mark at the start of the input, or if [`Codec::skip_boms`] is set, one
+    /// after the whitespace between concatenated frames. Returns `false` if a partial one is
+    /// left.
+    ///
+    /// Other framings delimit frames themselves, so `strip_bom` takes care of those. Binary
+    /// framings are left alone, since their input can start with anything.
+    fn skip_bom(&mut self, src: &mut BytesMut) -> bool {
+        if let Framing::LengthPrefixed | Framing::VarintPrefixed = self.framing {
+            self.bom_due = false;
+            return true;
+        }
+        let start = if self.bom_due {
+            0
+        } else if self.skip_boms && self.framing == Framing::Concatenated {
+            src.iter().take_while(|&&b| is_whitespace(b)).count()
+        } else {
+            return true;
+        };
+        let rest = &src[start..];
+        if rest.is_empty() {
+            return true;
+        }
+        self.bom_due = false;
+        if rest.starts_with(BOM) {
+            src.advance(start + BOM.len());
+        } else if BOM.starts_with(rest) {
+            self.bom_due = start == 0;
+            return false;
+        }
+        true
+    }
+
+    /// Strips a byte order mark from the start of a frame if [`Codec::skip_boms`] is set, or if
+    /// it is the first.
+    fn strip_bom(&mut self, frame: Bytes) -> Bytes {
+        let skip = self.skip_boms || self.bom_due;
+        self.bom_due = false;
+        if skip && frame.starts_with(BOM) {
+            frame.slice_from(BOM.len())
+        } else {
+            frame
+        }
+    }
+
+    /// Decodes a complete frame split off by `next_frame`, or returns `None` if it is the sentinel
+    /// or is dropped because no subscription matches it or it is a duplicate.
+    fn decode_value(&mut self, frame: Bytes) -> Result<Option<D>, Error> {
+        self.check_frame(&frame)?;
+        let frame = self.repair_utf8(frame);
+        if !self.needs_value() {
+            let item = deserialize_frame(
+                frame.clone(),
+                self.deny_unknown_fields,
+                self.unbounded_depth,
+            );
+            return self.apply_error_policy(item, Some(frame));
+        }
+        let value: Value = deserialize_frame(frame, false, self.unbounded_depth)?;
+        self.decode_parsed(value)
+    }
+
+    /// Splits a top-level array frame into its elements, queueing them in `unbatched`, and
+    /// returns the first one that is decoded.
+    fn decode_batch(&mut self, frame: Bytes) -> Result<Option<D>, Error> {
+        self.check_frame(&frame)?;
+        let frame = self.repair_utf8(frame);
+        let values: Vec<Value> = deserialize_frame(frame, false, self.unbounded_depth)?;
+        self.unbatched.extend(values);
+        self.next_unbatched()
+    }
+
+    /// Decodes the next queued array element that isn't dropped.
+    ///
+    /// Elements are kept as [`Value`]s rather than decoded up front so that the codec never holds
+    /// a `D`, which keeps it `Send` and `Sync` whatever `D` is.
+    fn next_unbatched(&mut self) -> Result<Option<D>, Error> {
+        while let Some(value) = self.unbatched.pop_front() {
+            let item = self.decode_parsed(value)?;
+            if self.sentinel_received {
+                self.unbatched.clear();
+            }
+            if item.is_some() {
+                return Ok(item);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks a complete frame before it is parsed.
+    fn check_frame(&mut self, frame: &Bytes) -> Result<(), Error> {
+        if self.keep_last_frame {
+            self.last_frame = Some(frame.clone());
+        }
+        if self.keeps_pending_frame() {
+            self.pending_frame = Some(frame.clone());
+        }
+        if let Some(max) = self.max_frame_size {
+            if frame.len() > max {
+                return Err(Error::FrameTooLarge {
+                    max,
+                    resynced: true,
+                });
+            }
+        }
+        self.limits.validate(frame)
+    }
+
+    /// Replaces invalid UTF-8 in a frame if [`Codec::lossy_utf8`] is set.
+    fn repair_utf8(&self, frame: Bytes) -> Bytes {
+        if !self.lossy_utf8 {
+            return frame;
+        }
+        match String::from_utf8_lossy(&frame) {
+            Cow::Borrowed(_) => frame,
+            Cow::Owned(repaired) => Bytes::from(repaired),
+        }
+    }
+
+    /// Decodes a frame that has been parsed into a `Value`, or returns `None` like
+    /// `decode_value`.
+    fn decode_parsed(&mut self, value: Value) -> Result<Option<D>, Error> {
+        let deny_unknown_fields = self.deny_unknown_fields;
+        if self.sentinel.as_ref() == Some(&value) {
+            self.sentinel_received = true;
+            return Ok(None);
+        }
+        if !self.filters.is_empty() && !self.filters.matches(&value) {
+            return Ok(None);
+        }
+        if let Some(ref mut dedupe) = self.dedupe {
+            if dedupe.is_duplicate(&value, Instant::now()) {
+                return Ok(None);
+            }
+        }
+        #[cfg(feature = "jsonschema")]
+        {
+            if let Some(ref schema) = self.schema {
+                schema::validate(schema, &value)?;
+            }
+        }
+        #[cfg(feature = "otel")]
+        {
+            if let Some(ref mut trace) = self.trace {
+                let item = trace.decode(value, |value| deserialize(value, deny_unknown_fields));
+                return self.apply_error_policy(item, None);
+            }
+        }
+        let item = deserialize(value, deny_unknown_fields);
+        self.apply_error_policy(item, None)
+    }
+
+    /// Handles a data error as the [error policy][`Codec::error_policy`] says, returning `None`
+    /// for a skipped frame.
+    ///
+    /// A `frame` that hasn't been parsed yet is checked to be valid JSON first, since
+    /// deserializing it may have failed before reaching a syntax error.
+    fn apply_error_policy(
+        &mut self,
+        item: Result<D, Error>,
+        frame: Option<Bytes>,
+    ) -> Result<Option<D>, Error> {
+        match item {
+            Err(e) if self.error_policy != ErrorPolicy::Fail && e.kind() == ErrorKind::Data => {
+                if let Some(frame) = frame {
+                    deserialize_frame::<IgnoredAny>(frame, false, self.unbounded_depth)?;
+                }
+                self.report_error(&self.annotate(e));
+                match (self.error_policy, self.default_item) {
+                    (ErrorPolicy::SubstituteDefault, Some(default)) => {
+                        self.substituted = self.substituted.saturating_add(1);
+                        Ok(Some(default()))
+                    }
+                    _ => {
+                        self.skipped = self.skipped.saturating_add(1);
+                        Ok(None)
+                    }
+                }
+            }
+            item => item.map(Some),
+        }
+    }
+
+    /// Returns whether frames need to be parsed into a `Value` before they are deserialized.
+    fn needs_value(&self) -> bool {
+        #[cfg(feature = "otel")]
+        {
+            if self.trace.is_some() {
+                return true;
+            }
+        }
+        #[cfg(feature = "jsonschema")]
+        {
+            if self.schema.is_some() {
+                return true;
+            }
+        }
+        !self.filters.is_empty() || self.dedupe.is_some() || self.sentinel.is_some()
+    }
+}
+
+/// Deserializes a `T` from `de`.
+///
+/// If `deny_unknown_fields` is set, fields that `T` ignores are reported as an error.
+fn deserialize<'de, T, De>(de: De, deny_unknown_fields: bool) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+    De: Deserializer<'de, Error = serde_json::Error>,
+{
+    if !deny_unknown_fields {
+        return deserialize_tracked(de);
+    }
+    let mut unknown = None;
+    let mut callback = |path: serde_ignored::Path| {
+        if unknown.is_none() {
+            unknown = Some(path.to_string());
+        }
+    };
+    let value = deserialize_tracked(serde_ignored::Deserializer::new(de, &mut callback))?;
+    match unknown {
+        Some(path) => Err(Error::UnknownField { path }),
+        None => Ok(value),
+    }
+}
+
+/// Deserializes a `T` from `de`, reporting the path of a value of the wrong type or shape if
+/// the `serde_path_to_error` feature is enabled.
+fn deserialize_tracked<'de, T, De>(de: De) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+    De: Deserializer<'de, Error = serde_json::Error>,
+{
+    #[cfg(feature = "serde_path_to_error")]
+    {
+        serde_path_to_error::deserialize(de).map_err(|e| {
+            let path = e.path().to_string();
+            let error = e.into_inner();
+            // Syntax errors already say where they are.
+            if path == "." || !error.is_data() {
+                Error::Json(error)
+            } else {
+                Error::Path { path, error }
+            }
+        })
+    }
+    #[cfg(not(feature = "serde_path_to_error"))]
+    {
+        Ok(T::deserialize(de)?)
+    }
+}
+
+/// Creates a `serde_json` deserializer for `input`, with the recursion limit lifted if
+/// `unbounded_depth` is set and supported.
+#[cfg_attr(not(feature = "unbounded_depth"), allow(unused_variables))]
+fn deserializer(input: &[u8], unbounded_depth: bool) -> serde_json::Deserializer<SliceRead<'_>> {
+    #[allow(unused_mut)]
+    let mut de = serde_json::Deserializer::from_slice(input);
+    #[cfg(feature = "unbounded_depth")]
+    {
+        if unbounded_depth {
+            de.disable_recursion_limit();
+        }
+    }
+    de
+}
+
+/// Deserializes a `T` from `de` like `deserialize`, growing the stack as needed if
+/// `unbounded_depth` is set and supported.
+#[cfg_attr(not(feature = "unbounded_depth"), allow(unused_variables))]
+fn deserialize_deep<'de, T, R>(
+    de: &mut serde_json::Deserializer<R>,
+    deny_unknown_fields: bool,
+    unbounded_depth: bool,
+) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+    R: serde_json::de::Read<'de>,
+{
+    #[cfg(feature = "unbounded_depth")]
+    {
+        if unbounded_depth {
+            return deserialize(serde_stacker::Deserializer::new(de), deny_unknown_fields);
+        }
+    }
+    deserialize(de, deny_unknown_fields)
+}
+
+/// Deserializes a `T` from a complete frame.
+fn deserialize_frame<T>(
+    frame: Bytes,
+    deny_unknown_fields: bool,
+    unbounded_depth: bool,
+) -> Result<T, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let mut de = deserializer(&frame, unbounded_depth);
+    let value = deserialize_deep(&mut de, deny_unknown_fields, unbounded_depth).and_then(|value| {
+        de.end()?;
+        Ok(value)
+    });
+    value.map_err(|e| match e.in_input(frame.clone()) {
+        Error::Decode(mut e) => {
+            e.in_frame = true;
+            Error::Decode(e)
+        }
+        e => e,
+    })
+}
+
+/// Returns the number of whitespace bytes at the start of `src`.
+///
+/// If `src` is nothing but whitespace, it is cleared so `Decoder::decode_eof` doesn't return an
+/// `Err`.
+fn leading_whitespace(src: &mut BytesMut) -> usize {
+    let len = src.iter().take_while(|&&b| is_whitespace(b)).count();
+    if len == src.len() {
+        src.clear();
+    }
+    len
+}
+
+/// Deserializes the value at the start of `src`, returning it along with the number of bytes it
+/// spans.
+fn peek_value<T>(src: &BytesMut, unbounded_depth: bool) -> Result<Option<(T, usize)>, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    #[cfg(feature = "unbounded_depth")]
+    {
+        if unbounded_depth {
+            return peek_deep_value(src);
+        }
+    }
+    // The buffer is only copied for an error, which needs to own the input it occurred in.
+    let (next, len) = {
+        let mut de = deserializer(src, unbounded_depth).into_iter();
+        (de.next(), de.byte_offset())
+    };
+    match next {
+        Some(Ok(v)) => Ok(Some((v, len))),
+        Some(Err(e)) => {
+            if e.is_eof() {
+                return Ok(None);
+            }
+            #[cfg(feature = "serde_path_to_error")]
+            {
+                if e.is_data() {
+                    // Deserialize the value again to find the path of the error.
+                    let mut de = deserializer(src, unbounded_depth);
+                    if let Err(e) = deserialize_tracked::<T, _>(&mut de) {
+                        return Err(e.in_input(Bytes::from(&src[..])));
+                    }
+                }
+            }
+            Err(Error::decode(e, Bytes::from(&src[..])))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Like `peek_value` without a recursion limit.
+///
+/// The stream deserializer can't grow the stack, so this finds where the value ends first and
+/// deserializes just that.
+#[cfg(feature = "unbounded_depth")]
+fn peek_deep_value<T>(src: &BytesMut) -> Result<Option<(T, usize)>, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let end = match lex::value_end(src, true, usize::MAX) {
+        Ok(None) => return Ok(None),
+        Ok(Some(end)) => end,
+        // Let the deserializer report what's wrong.
+        Err(_) => src.len(),
+    };
+    let mut de = deserializer(&src[..end], true);
+    let value = deserialize_deep(&mut de, false, true).and_then(|value| {
+        de.end()?;
+        Ok(value)
+    });
+    match value {
+        Ok(value) => Ok(Some((value, end))),
+        Err(Error::Json(ref e)) if e.is_eof() => Ok(None),
+        Err(e) => Err(e.in_input(Bytes::from(&src[..]))),
+    }
+}
+
+/// Returns how far past a syntax error at `offset` decoding should carry on from; see
+/// [`Codec::resync`].
+fn resync_point(src: &[u8], offset: usize) -> usize {
+    let offset = offset.min(src.len());
+    if let Some(end) = src[offset..].iter().position(|&b| b == b'\n' || b == 0x1e) {
+        return offset + end + 1;
+    }
+    // Skip at least a byte, so that the same error isn't reported again.
+    let from = offset.max(1).min(src.len());
+    src[from..]
+        .iter()
+        .position(|&b| b == b'{' || b == b'[')
+        .map_or(src.len(), |start| from + start)
+}
+
+/// The UTF-8 byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The buffer size up to which `next_value` finds the end of a frame before deserializing it.
+const SMALL_FRAME: usize = 512;
+
+/// Returns how deeply `serde_json` lets values nest.
+fn max_depth(unbounded_depth: bool) -> usize {
+    if unbounded_depth {
+        usize::MAX
+    } else {
+        128
+    }
+}
+
+/// Returns whether more input is needed for the value at the start of `src`, which `scan` has
+/// partly scanned already; see [`lex::Scan`].
+///
+/// Without this, each call would deserialize a frame that is trickling in from the start again.
+fn still_incomplete(src: &[u8], unbounded_depth: bool, scan: &mut lex::Scan) -> bool {
+    scan.is_started() && scan.value_end(src, true, max_depth(unbounded_depth)) == Ok(None)
+}
+
+/// Like `peek_value`, first resuming `scan` if it is partway through the value, and scanning as
+/// far as the input goes if the value is incomplete.
+fn peek_scanned_value<T>(
+    src: &BytesMut,
+    unbounded_depth: bool,
+    scan: &mut lex::Scan,
+) -> Result<Option<(T, usize)>, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    if still_incomplete(src, unbounded_depth, scan) {
+        return Ok(None);
+    }
+    let value = peek_value(src, unbounded_depth);
+    if let Ok(None) = value {
+        let _ = scan.value_end(src, true, max_depth(unbounded_depth));
+    }
+    value
+}
+
+/// Deserializes the next value in `src`, advancing past it.
+fn next_value<T>(
+    src: &mut BytesMut,
+    unbounded_depth: bool,
+    scan: &mut lex::Scan,
+) -> Result<Option<T>, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    leading_whitespace(src);
+    if src.len() <= SMALL_FRAME || scan.is_started() {
+        // Scanning a small buffer for the end of the frame is cheaper than setting up a stream
+        // deserializer for it. If the scan fails, or the frame is too deep for `serde_json`, the
+        // stream deserializer reports the error.
+        match scan.value_end(src, true, max_depth(unbounded_depth)) {
+            Ok(None) => return Ok(None),
+            Ok(Some(end)) => {
+                let mut de = deserializer(&src[..end], unbounded_depth);
+                let value = deserialize_deep(&mut de, false, unbounded_depth)
+                    .and_then(|value| de.end().map(|()| value).map_err(Error::from));
+                match value {
+                    Ok(value) => {
+                        src.advance(end);
+                        return Ok(Some(value));
+                    }
+                    Err(e) => return Err(e.in_input(Bytes::from(&src[..]))),
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(
+        peek_scanned_value(src, unbounded_depth, scan)?.map(|(v, len)| {
+            src.advance(len);
+            v
+        }),
+    )
+}
+
+/// Splits the next complete frame off `src` without deserializing it, discarding whitespace before
+/// it.
+fn next_frame(
+    src: &mut BytesMut,
+    unbounded_depth: bool,
+    scan: &mut lex::Scan,
+) -> Result<Option<Bytes>, Error> {
+    let start = leading_whitespace(src);
+    Ok(
+        peek_scanned_value::<IgnoredAny>(src, unbounded_depth, scan)?.map(|(_, len)| {
+            src.advance(start);
+            src.split_to(len - start).freeze()
+        }),
+    )
+}
+
+impl<D, E> Encoder for Codec<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.encode_with(dst, |codec, dst| codec.encode_frame(item, dst))
+    }
+}
+
+impl<D, E> Codec<D, E>
+where
+    E: Serialize,
+{
+    /// Encodes an item into `dst` with `encode`, taking care of bookkeeping.
+    fn encode_with<F>(&mut self, dst: &mut BytesMut, encode: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self, &mut BytesMut) -> Result<(), Error>,
+    {
+        let (started, len) = (self.slow_frame.map(|_| Instant::now()), dst.len());
+        // Make room for a typical frame at once, rather than growing the buffer as it's written.
+        dst.reserve(self.encoded_size.get());
+        let result = if self.encode_array {
+            dst.extend_from_slice(if self.array_open { b"," } else { b"[" });
+            encode(self, dst).map(|()| self.array_open = true)
+        } else {
+            let framing = self.framing.clone();
+            framing
+                .encode(dst, |dst| encode(self, dst))
+                .map(|()| dst.extend_from_slice(&self.terminator))
+        };
+        if let Err(e) = result {
+            if self.encode_array {
+                // Leave the array as it was, without a dangling comma.
+                dst.truncate(len);
+            }
+            return Err(self.label_error(e));
+        }
+        if let Some(ref mut boundaries) = self.boundaries {
+            boundaries.encoded(dst.len() - len);
+        }
+        self.encoded = self.encoded.saturating_add(1);
+        self.encoded_size.record(dst.len() - len);
+        self.check_slow_frame::<E>("encode", started, dst.len() - len);
+        Ok(())
+    }
+
+    fn encode_frame(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        #[cfg(feature = "jsonschema")]
+        {
+            if let Some(schema) = self.schema.as_ref().filter(|_| self.validate_encoded) {
+                let frame = serde_json::to_value(&item)?;
+                schema::validate(schema, &frame)?;
+                return self.encode_value(frame, dst);
+            }
+        }
+        self.encode_value(item, dst)
+    }
+
+    /// Writes an item that may already have been converted into a `Value`.
+    fn encode_value<T>(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        #[cfg(feature = "otel")]
+        {
+            if let Some(ref trace) = self.trace {
+                let mut frame = serde_json::to_value(&item)?;
+                trace.inject(&mut frame);
+                return self.write(&frame, dst);
+            }
+        }
+        if self.js_compatible {
+            let mut frame = serde_json::to_value(&item)?;
+            stringify_big_ints(&mut frame);
+            return self.write(&frame, dst);
+        }
+        self.write(&item, dst)
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Encodes what ends the sink: the sentinel, if there is one, and then the end of the array
+    /// if items are encoded as one.
+    fn encode_end(&mut self, dst: &mut BytesMut) -> Result<(), Error> {
+        if !self.encode_array {
+            return self.encode_sentinel(dst);
+        }
+        if let Some(ref sentinel) = self.sentinel {
+            dst.extend_from_slice(if self.array_open { b"," } else { b"[" });
+            self.write(sentinel, dst)?;
+        } else if !self.array_open {
+            dst.extend_from_slice(b"[");
+        }
+        dst.extend_from_slice(b"]");
+        self.array_open = false;
+        Ok(())
+    }
+
+    /// Encodes the sentinel, if there is one.
+    fn encode_sentinel(&mut self, dst: &mut BytesMut) -> Result<(), Error> {
+        match self.sentinel {
+            Some(ref sentinel) => {
+                self.framing.encode(dst, |dst| self.write(sentinel, dst))?;
+                dst.extend_from_slice(&self.terminator);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn write<T: Serialize>(&self, item: &T, dst: &mut BytesMut) -> Result<(), Error> {
+        let start = dst.len();
+        let writer = BytesWriter(dst);
+        if self.pretty {
+            serde_json::to_writer_pretty(writer, item)?;
+        } else {
+            serde_json::to_writer(writer, item)?;
+        }
+        if self.js_compatible {
+            escape_line_separators(dst, start);
+        }
+        Ok(())
+    }
+}
+
+/// The largest integer a JavaScript number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Replaces the integers in `value` that JavaScript can't hold exactly with strings; see
+/// [`Codec::js_compatible`].
+fn stringify_big_ints(value: &mut Value) {
+    match value {
+        Value::Number(n) => {
+            let big = match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => n > MAX_SAFE_INTEGER,
+                (None, Some(n)) => n.unsigned_abs() > MAX_SAFE_INTEGER,
+                (None, None) => false,
+            };
+            if big {
+                *value = Value::String(n.to_string());
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(stringify_big_ints),
+        Value::Object(map) => map.values_mut().for_each(stringify_big_ints),
+        _ => {}
+    }
+}
+
+/// Escapes U+2028 and U+2029 in what was written to `dst` after `start`; see
+/// [`Codec::js_compatible`].
+fn escape_line_separators(dst: &mut BytesMut, start: usize) {
+    // Outside of strings, JSON is ASCII, so these can only be in strings.
+    let is_separator = |w: &[u8]| w == "\u{2028}".as_bytes() || w == "\u{2029}".as_bytes();
+    if !dst[start..].windows(3).any(is_separator) {
+        return;
+    }
+    let written = dst.split_off(start);
+    let mut rest = &written[..];
+    while let Some(i) = rest.windows(3).position(is_separator) {
+        dst.extend_from_slice(&rest[..i]);
+        dst.extend_from_slice(if rest[i + 2] == 0xa8 {
+            b"\\u2028"
+        } else {
+            b"\\u2029"
+        });
+        rest = &rest[i + 3..];
+    }
+    dst.extend_from_slice(rest);
+}
+
+/// Wrapper for `&mut [BytesMut]` that provides Write.
+///
+/// `BufMut::writer` won't do, since it doesn't grow the buffer: once the buffer is full, it
+/// writes nothing. Each write reserves what it needs and copies the whole slice at once, rather
+/// than going through `Extend` a byte at a time.
+///
+/// See also:
+/// * <https://github.com/vorner/tokio-serde-cbor/blob/a347107ad56f2ad8086998eb63ecb70b19f3b71d/src/lib.rs#L167-L181>
+/// * <https://github.com/carllerche/bytes/issues/77>
+struct BytesWriter<'a>(&'a mut BytesMut);
+
+impl<'a> io::Write for BytesWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::io;
+    #[cfg(feature = "unbounded_depth")]
+    use std::mem;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Direction, Error, ErrorKind, ErrorPolicy, Framing, JsonType, Reliable, Stats};
+
+    #[test]
+    fn decode_empty() {
+        let mut buf = BytesMut::from(&b""[..]);
+        let mut codec: Codec<(), ()> = Codec::default();
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn decode() {
+        let mut buf = BytesMut::from(&b"null null null"[..]);
+        let mut codec: Codec<_, ()> = Codec::default();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_partial() {
+        let mut buf = BytesMut::from(&b"null null nu"[..]);
+        let mut codec: Codec<_, ()> = Codec::default();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf, &b" nu"[..]);
+        buf.put(&b"ll"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_trickle() {
+        let mut input = b"[".to_vec();
+        input.extend_from_slice(&b"{\"a\": [123, \"]\"]}, ".repeat(100));
+        input.extend_from_slice(b"null] 4");
+        let expected: Value = serde_json::from_slice(&input[..input.len() - 2]).unwrap();
+        for &keep_last_frame in &[false, true] {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.keep_last_frame(keep_last_frame);
+            let mut buf = BytesMut::new();
+            let mut items = Vec::new();
+            for chunk in input.chunks(3) {
+                buf.extend_from_slice(chunk);
+                if let Some(item) = codec.decode(&mut buf).unwrap() {
+                    items.push(item);
+                } else {
+                    assert!(codec.scan.is_started());
+                }
+            }
+            items.extend(codec.decode_eof(&mut buf).unwrap());
+            assert_eq!(items, vec![expected.clone(), json!(4)]);
+        }
+    }
+
+    #[test]
+    fn decode_glued_scalars() {
+        let padding = b" [1]".repeat(super::SMALL_FRAME / 4);
+        for &input in &[&b"nullnull "[..], b"truefalse ", b"1true ", b"1x "] {
+            for &padding in &[&b""[..], &padding] {
+                let mut buf = BytesMut::from(input);
+                buf.extend_from_slice(padding);
+                let mut codec: Codec<Value, ()> = Codec::default();
+                let err = codec.decode(&mut buf).unwrap_err();
+                assert!(err.to_string().contains("trailing characters"), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_large_buffer() {
+        let mut input = b"[1] ".repeat(1000);
+        input.extend_from_slice(&b"[?] ".repeat(1000));
+        let mut buf = BytesMut::from(&input[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        for _ in 0..1000 {
+            assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        }
+        match codec.decode(&mut buf).unwrap_err() {
+            Error::Decode(e) => assert_eq!(e.input(), &buf[..]),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn decode_eof_trailing_whitespae() {
+        let mut buf = BytesMut::from(&b"null\n"[..]);
+        let mut codec: Codec<_, ()> = Codec::default();
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_eof_incomplete_frame() {
+        let mut buf = BytesMut::from(&b"[1] {\"a\":\n[2"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(json!([1])));
+        let err = codec.decode_eof(&mut buf).unwrap_err();
+        match err {
+            Error::IncompleteFrame {
+                pending,
+                ref snippet,
+            } => assert_eq!((pending, &snippet[..]), (9, " {\"a\":\\n[2")),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(err.kind(), ErrorKind::Eof);
+        assert_eq!(
+            err.to_string(),
+            "input ended in an incomplete frame of 9 bytes: ` {\"a\":\\n[2`"
+        );
+        assert!(buf.is_empty());
+        assert_eq!(codec.decoded_bytes(), 12);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn decode_err() {
+        let mut buf = BytesMut::from(&b"null butts"[..]);
+        let mut codec: Codec<_, ()> = Codec::default();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn decode_blank_keepalives() {
+        for framing in &[
+            Framing::Concatenated,
+            Framing::nul_delimited(),
+            Framing::LengthPrefixed,
+        ] {
+            let mut codec: Codec<Value, Value> = Codec::default();
+            codec.framing(framing.clone());
+            let mut buf = BytesMut::new();
+            codec.encode(json!(1), &mut buf).unwrap();
+            for _ in 0..3 {
+                framing
+                    .encode(&mut buf, |dst| {
+                        dst.extend_from_slice(b"\r\n");
+                        Ok(())
+                    })
+                    .unwrap();
+            }
+            codec.encode(json!(2), &mut buf).unwrap();
+            assert_eq!(
+                codec.decode(&mut buf).unwrap(),
+                Some(json!(1)),
+                "{:?}",
+                framing
+            );
+            assert_eq!(
+                codec.decode(&mut buf).unwrap(),
+                Some(json!(2)),
+                "{:?}",
+                framing
+            );
+            assert_eq!(codec.decode_eof(&mut buf).unwrap(), None, "{:?}", framing);
+        }
+    }
+
+    #[test]
+    fn decode_require_separators() {
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.require_separators(true);
+        let mut buf = BytesMut::from(&b"{\"a\":1}"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": 1})));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"{\"b\":2}\n{\"c\":3}");
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"b": 2})));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"c": 3})));
+    }
+
+    #[test]
+    fn decode_datagrams() {
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.datagrams(true);
+        for (datagram, ok) in &[(&b" [1] "[..], true), (b"[1", false), (b"[1] 2", false)] {
+            let mut buf = BytesMut::from(*datagram);
+            let item = codec.decode(&mut buf);
+            assert_eq!(item.is_ok(), *ok, "{:?}", datagram);
+            assert!(buf.is_empty());
+        }
+        let mut buf = BytesMut::from(&b"12"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(12)));
+        let mut buf = BytesMut::from(&b" \n"[..]);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn encode_terminator() {
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.terminator(b"\r\n");
+        codec.sentinel(Some(json!(null)));
+        let mut buf = BytesMut::new();
+        codec.encode(json!({"a": 1}), &mut buf).unwrap();
+        codec.encode(json!({"b": 2}), &mut buf).unwrap();
+        codec.encode_sentinel(&mut buf).unwrap();
+        assert_eq!(buf, &b"{\"a\":1}\r\n{\"b\":2}\r\nnull\r\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": 1})));
+    }
+
+    #[test]
+    fn decode_peer_label() {
+        let mut buf = BytesMut::from(&b"butts"[..]);
+        let mut codec: Codec<(), ()> = Codec::default();
+        codec.peer_label(Some("10.0.0.1:443"));
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.label(), Some("10.0.0.1:443"));
+        assert!(err.to_string().starts_with("[10.0.0.1:443] expected value"));
+        assert!(format!("{:?}", err).contains("10.0.0.1:443"));
+        assert_eq!(err.kind(), ErrorKind::Syntax);
+        assert!(matches!(err.unlabeled(), Error::Decode(_)));
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("[10.0.0.1:443] "));
+    }
+
+    #[test]
+    fn decode_depth_exceeded() {
+        let mut buf = BytesMut::from(&[b'['; 200][..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        match codec.decode(&mut buf) {
+            Err(Error::DepthExceeded { consumed }) => assert_eq!(consumed, 128),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "unbounded_depth")]
+    fn decode_unbounded_depth() {
+        let deep = format!("{}{}", "[".repeat(200), "]".repeat(200));
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.unbounded_depth(true);
+        let mut buf = BytesMut::from(deep.as_bytes());
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        codec.max_depth(Some(128));
+        let mut buf = BytesMut::from(deep.as_bytes());
+        match codec.decode(&mut buf).unwrap_err() {
+            Error::LimitExceeded { limit, .. } => assert_eq!(limit, ::Limit::Depth(128)),
+            e => panic!("unexpected {:?}", e),
+        }
+
+        // Deep enough to overflow the stack without growing it. Dropping the values would too.
+        codec.max_depth(None);
+        let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        let mut buf = BytesMut::from(deep.as_bytes());
+        mem::forget(codec.decode(&mut buf).unwrap().unwrap());
+        codec.keep_last_frame(true);
+        let mut buf = BytesMut::from(deep.as_bytes());
+        mem::forget(codec.decode(&mut buf).unwrap().unwrap());
+        let mut buf = BytesMut::from(&deep.as_bytes()[..150_000]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_err_position() {
+        let input = &b"[1]\n\n  {\n\"a\": 1,\n\"b\": x}\n\n"[..];
+        for framing in &[Framing::Concatenated, Framing::delimited(b"\n\n")] {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.framing(framing.clone());
+            let mut buf = BytesMut::from(input);
+            assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+            match codec.decode(&mut buf) {
+                Err(Error::Decode(e)) => {
+                    assert_eq!((e.line(), e.column()), (3, 6), "{:?}", framing);
+                    assert_eq!(e.stream_offset(), Some(22), "{:?}", framing);
+                    assert_eq!(input[22], b'x');
+                    assert_eq!(e.frame_number(), Some(2), "{:?}", framing);
+                }
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_err_frame_number() {
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.framing(Framing::delimited(b"\n"));
+        codec.skip_invalid_frames(true);
+        let mut buf = BytesMut::from(&b"1\n\"a\"\n\n2\n[?]\n3\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(2));
+        match codec.decode(&mut buf) {
+            Err(Error::Decode(e)) => {
+                assert_eq!(e.frame_number(), Some(4));
+                assert_eq!(e.frame_start(), Some(9));
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(3));
+    }
+
+    #[test]
+    #[cfg(feature = "serde_path_to_error")]
+    fn decode_err_path() {
+        let mut codec: Codec<HashMap<String, Vec<u32>>, ()> = Codec::default();
+        // Both small and large frames, which are deserialized differently.
+        for &len in &[1, 600] {
+            let input = format!("{{\"items\": [1, 2, \"{}\"]}}", "a".repeat(len));
+            let mut buf = BytesMut::from(input.as_bytes());
+            match codec.decode(&mut buf) {
+                Err(Error::Decode(e)) => assert_eq!(e.path(), Some("items[2]"), "{}", len),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+
+        codec.deny_unknown_fields(true);
+        let mut buf = BytesMut::from(&b"{\"a\": [true]}"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert!(err.to_string().starts_with("a[0]: invalid type"), "{}", err);
+
+        codec.sentinel(Some(json!(null)));
+        let mut buf = BytesMut::from(&b"{\"a\": [1, -1]}"[..]);
+        match codec.decode(&mut buf) {
+            Err(Error::Path { path, .. }) => assert_eq!(path, "a[1]"),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn decode_err_fatal() {
+        let decode_err = |framing, input: &[u8]| {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.framing(framing);
+            codec.max_string_len(Some(3));
+            codec.peer_label(Some("peer"));
+            codec.decode(&mut BytesMut::from(input)).unwrap_err()
+        };
+        let err = decode_err(Framing::Concatenated, b"[1, ?] [2]");
+        assert!(err.is_fatal());
+        assert!(!decode_err(Framing::Concatenated, b"\"long\" [2]").is_fatal());
+        assert!(!decode_err(Framing::delimited(b"\n"), b"[1, ?]\n[2]\n").is_fatal());
+        let err = decode_err(Framing::JsonSeq, b"[1]\n\x1e[2]\n");
+        assert_eq!(err.kind(), ErrorKind::Framing);
+        assert!(!err.is_fatal());
+        assert!(decode_err(Framing::Netstring, b"1x:[1],").is_fatal());
+    }
+
+    #[test]
+    fn decode_err_fatal_limits() {
+        let decode_err = |framing, input: &[u8]| {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.framing(framing);
+            codec.max_frame_size(Some(8));
+            codec.max_depth(Some(2));
+            codec.decode(&mut BytesMut::from(input)).unwrap_err()
+        };
+        // An incomplete frame is discarded, and only a delimiter shows where the next one starts.
+        let err = decode_err(Framing::Concatenated, b"[[[[1,2,3,4,5");
+        assert_eq!(err.kind(), ErrorKind::TooLarge);
+        assert!(err.is_fatal());
+        let err = decode_err(Framing::delimited(b"\n"), b"[[[[1,2,3,4,5");
+        assert_eq!(err.kind(), ErrorKind::TooLarge);
+        assert!(!err.is_fatal());
+        let err = decode_err(Framing::Concatenated, b"[1,2,3,4,5] [2]");
+        assert_eq!(err.kind(), ErrorKind::TooLarge);
+        assert!(!err.is_fatal());
+
+        // The codec's own depth limit only loses the frame, unlike `serde_json`'s.
+        let err = decode_err(Framing::Concatenated, b"[[[1]]] [2]");
+        assert_eq!(err.kind(), ErrorKind::Depth);
+        assert!(!err.is_fatal());
+        let mut codec: Codec<Value, ()> = Codec::default();
+        let err = codec
+            .decode(&mut BytesMut::from(&[b'['; 200][..]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Depth);
+        assert!(err.is_fatal());
+    }
+
+    #[test]
+    fn decode_resync() {
+        let input = &b"[1] {\"a\": tru, \"b\": {}}\n[2] [3, ?] [4] ?"[..];
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.resync(true);
+        let mut buf = BytesMut::from(input);
+        let mut items = Vec::new();
+        while !buf.is_empty() {
+            match codec.decode_eof(&mut buf) {
+                Ok(Some(item)) => items.push(Ok(item)),
+                Ok(None) => break,
+                Err(e) => {
+                    assert!(!e.is_fatal());
+                    items.push(Err(e.kind()));
+                }
+            }
+        }
+        let syntax = || Err(ErrorKind::Syntax);
+        let expected = vec![
+            Ok(json!([1])),
+            syntax(),
+            Ok(json!([2])),
+            syntax(),
+            Ok(json!([4])),
+            syntax(),
+        ];
+        assert_eq!(items, expected);
+        assert_eq!(codec.stats_snapshot().malformed, 3);
+    }
+
+    #[test]
+    fn decode_err_context() {
+        let mut buf = BytesMut::from(&b"{\"butts\": lol}\n{}"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "expected value at line 1 column 11");
+        match err {
+            Error::Decode(ref e) => assert_eq!(e.excerpt(), "{\"butts\": lol}"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        codec.error_context(true);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected value at line 1 column 11\n  {\"butts\": lol}\n            ^"
+        );
+    }
+
+    #[test]
+    fn decode_deny_unknown_fields() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Inner {
+            a: u8,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Outer {
+            inner: Vec<Inner>,
+        }
+
+        let input = &b"{\"inner\": [{\"a\": 1, \"b\": 2}]}"[..];
+        let mut codec: Codec<Outer, ()> = Codec::default();
+        assert_eq!(
+            codec.decode(&mut BytesMut::from(input)).unwrap(),
+            Some(Outer {
+                inner: vec![Inner { a: 1 }]
+            })
+        );
+        codec.deny_unknown_fields(true);
+        match codec.decode(&mut BytesMut::from(input)) {
+            Err(Error::UnknownField { path }) => assert_eq!(path, "inner.0.b"),
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut buf = BytesMut::from(&b"{\"inner\": [{\"a\": 1}]} {"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn encode_interop_js() {
+        let mut codec: Codec<Value, Value> = Codec::interop_js();
+        let mut buf = BytesMut::new();
+        let item = json!([
+            9007199254740991u64,
+            9007199254740992u64,
+            -9007199254740993i64,
+            1.5
+        ]);
+        codec.encode(item, &mut buf).unwrap();
+        codec
+            .encode(json!({"a": "x\u{2028}y\u{2029}"}), &mut buf)
+            .unwrap();
+        assert_eq!(
+            str::from_utf8(&buf).unwrap(),
+            r#"[9007199254740991,"9007199254740992","-9007199254740993",1.5]{"a":"x\u2028y\u2029"}"#
+        );
+        codec.js_compatible(false);
+        buf.clear();
+        codec
+            .encode(json!(["\u{2028}", 1u64 << 60]), &mut buf)
+            .unwrap();
+        assert_eq!(buf, "[\"\u{2028}\",1152921504606846976]".as_bytes());
+    }
+
+    #[test]
+    fn decode_strict() {
+        let mut buf = BytesMut::from(&b"{\"a\": 1, \"b\": 2}"[..]);
+        let mut codec: Codec<HashMap<String, u8>, ()> = Codec::strict();
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().len(), 2);
+        // Frames must be separated, so each input gets a codec of its own.
+        let mut codec: Codec<HashMap<String, u8>, ()> = Codec::strict();
+        let mut buf = BytesMut::from(&[b'['; 65][..]);
+        buf.extend_from_slice(&[b']'; 65]);
+        match codec.decode(&mut buf) {
+            Err(Error::LimitExceeded { limit, .. }) => assert_eq!(limit, ::Limit::Depth(64)),
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut codec: Codec<HashMap<String, u8>, ()> = Codec::strict();
+        let mut buf = BytesMut::from(&b"{\"a\": 1, \"a\": 2}"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "duplicate key `a`"
+        );
+        let mut codec: Codec<HashMap<String, u8>, ()> = Codec::strict();
+        let mut buf = BytesMut::from(&b"{\"a\": 1}{\"b\": 2}\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().len(), 1);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind(),
+            ErrorKind::Framing
+        );
+    }
+
+    #[test]
+    fn decode_max_string_len() {
+        let mut buf = BytesMut::from(&b"{\"a\": \"lol\"} {\"a\": \"lmao\"}"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.max_string_len(Some(3));
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "string longer than 3 bytes at `a`"
+        );
+    }
+
+    #[test]
+    fn decode_max_array_len() {
+        let mut buf = BytesMut::from(&b"{\"a\": [1, 2]} {\"a\": [1, 2, 3]}"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.max_array_len(Some(2));
+        codec.max_object_len(Some(1));
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "array with more than 2 elements at `a`"
+        );
+    }
+
+    #[test]
+    fn decode_max_frame_size() {
+        let mut buf = BytesMut::from(&b"[1, 2] [1, 2, 3] [1, 2"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.max_frame_size(Some(6));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1, 2])));
+        match codec.decode(&mut buf) {
+            Err(Error::FrameTooLarge { max, .. }) => assert_eq!(max, 6),
+            other => panic!("expected FrameTooLarge, got {:?}", other),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b", 3");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind(),
+            ErrorKind::TooLarge
+        );
+        assert!(buf.is_empty());
+
+        // Nothing of the discarded frame is remembered.
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.max_frame_size(Some(8));
+        let mut buf = BytesMut::from(&b"[[[[1,2,3,4,5"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind(),
+            ErrorKind::TooLarge
+        );
+        buf.extend_from_slice(b"[1]\n{\"a\":1}\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": 1})));
+    }
+
+    #[test]
+    fn decode_last_frame_raw() {
+        let mut buf = BytesMut::from(&b"{\"a\":1} {\"a\": \"b\"}"[..]);
+        let mut codec: Codec<HashMap<String, u32>, ()> = Codec::default();
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.last_frame_raw(), None);
+        codec.keep_last_frame(true);
+        assert_eq!(codec.last_frame_raw(), None);
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.last_frame_raw().unwrap(), &b"{\"a\": \"b\"}"[..]);
+    }
+
+    #[test]
+    fn decode_positions() {
+        let mut buf = BytesMut::from(&b"{\"a\":1}\n  {\"a\": \"b\"}"[..]);
+        let mut codec: Codec<HashMap<String, u32>, ()> = Codec::default();
+        codec.track_positions(true);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        let position = codec.last_position().unwrap();
+        assert_eq!(
+            (position.index, position.offset, position.line),
+            (0, 0, None)
+        );
+        assert!(codec.decode(&mut buf).is_err());
+        let position = codec.last_position().unwrap();
+        assert_eq!((position.index, position.offset), (1, 10));
+    }
+
+    #[test]
+    fn decode_unbatch_arrays() {
+        let mut buf = BytesMut::from(&b"[1, 2] 3 [] [4, 0]"[..]);
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.unbatch_arrays(true);
+        codec.sentinel(Some(json!(0)));
+        let mut items = Vec::new();
+        while let Some(item) = codec.decode(&mut buf).unwrap() {
+            items.push(item);
+        }
+        assert_eq!(items, vec![1, 2, 3, 4]);
+        assert!(codec.sentinel_received());
+    }
+
+    #[test]
+    fn decode_keepalives() {
+        let mut buf = BytesMut::from(&b"1\n\nPING\n2 PIN"[..]);
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.keepalive_token("PING");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(2));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put("G");
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+        assert_eq!(codec.keepalives(), 2);
+    }
+
+    #[test]
+    fn decode_malformed_frames() {
+        let mut buf = BytesMut::from(&b"\"a\" \"long\" \"b\" \"long\" \"c\""[..]);
+        let mut codec: Codec<String, ()> = Codec::default();
+        codec.max_string_len(Some(3));
+        codec.max_malformed_frames(Some(2));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("a".to_owned()));
+        assert!(codec.decode(&mut buf).is_err());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("b".to_owned()));
+        assert!(codec.decode(&mut buf).is_err());
+        match codec.decode(&mut buf) {
+            Err(Error::TooManyMalformedFrames { count }) => assert_eq!(count, 2),
+            res => panic!("unexpected result {:?}", res),
+        }
+        let stats = codec.stats_snapshot();
+        assert_eq!((stats.malformed, stats.malformed_bytes), (2, 14));
+    }
+
+    #[test]
+    fn decode_skip_invalid_frames() {
+        let mut buf = BytesMut::from(&b"1 \"a\" {\"b\": 2} 3 {\"c\": ?}"[..]);
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.skip_invalid_frames(true);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(3));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind(),
+            ErrorKind::Syntax
+        );
+        assert_eq!(codec.skipped_frames(), 2);
+        assert_eq!(codec.stats_reset().skipped, 2);
+        assert_eq!(codec.skipped_frames(), 0);
+
+        // A frame that fails to deserialize before reaching a syntax error isn't skipped.
+        let mut buf = BytesMut::from(&b"8:[\"x\", ?],1:4,"[..]);
+        codec.framing(Framing::Netstring);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().kind(),
+            ErrorKind::Syntax
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(4));
+        assert_eq!(codec.skipped_frames(), 0);
+    }
+
+    #[test]
+    fn decode_capture_failed_frames() {
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.framing(Framing::delimited(b"\n"));
+        codec.max_string_len(Some(3));
+        codec.capture_failed_frames(Some(4));
+        codec.peer_label(Some("peer"));
+        let mut buf = BytesMut::from(&b"{\"a\": \"long\"}\n[1]\n"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.frame().map(|frame| &frame[..]), Some(&b"{\"a\""[..]));
+        match err.unlabeled() {
+            Error::LimitExceeded { .. } => {}
+            e => panic!("unexpected error {:?}", e),
+        }
+        match err {
+            Error::Labeled { ref error, .. } => match **error {
+                Error::WithFrame { len, .. } => assert_eq!(len, 13),
+                ref e => panic!("unexpected error {:?}", e),
+            },
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(err.to_string(), "[peer] string longer than 3 bytes at `a`");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+
+        codec.capture_failed_frames(None);
+        let mut buf = BytesMut::from(&b"\"long\"\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap_err().frame().is_none());
+    }
+
+    #[test]
+    fn decode_error_policy() {
+        let input = &b"1 \"a\" 2"[..];
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.error_policy(ErrorPolicy::SubstituteDefault);
+        let mut buf = BytesMut::from(input);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(0));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(2));
+        assert_eq!((codec.substituted_frames(), codec.skipped_frames()), (1, 0));
+        assert_eq!(codec.stats_snapshot().substituted, 1);
+
+        codec.error_policy(ErrorPolicy::SkipFrame);
+        let mut buf = BytesMut::from(input);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(2));
+        assert_eq!(codec.skipped_frames(), 1);
+
+        codec.error_policy(ErrorPolicy::Fail);
+        let mut buf = BytesMut::from(input);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap_err().kind(), ErrorKind::Data);
+    }
+
+    #[test]
+    fn decode_boms() {
+        let input = "\u{feff}[1]\n\u{feff}[2]\n";
+        let mut codec: Codec<Value, ()> = Codec::default();
+        let mut buf = BytesMut::from(input.as_bytes());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert!(codec.decode(&mut buf).is_err());
+
+        for framing in &[Framing::Concatenated, Framing::delimited(b"\n")] {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.framing(framing.clone());
+            codec.skip_boms(true);
+            let mut buf = BytesMut::new();
+            let mut items = Vec::new();
+            for &b in input.as_bytes() {
+                buf.put_u8(b);
+                while let Some(item) = codec.decode(&mut buf).unwrap() {
+                    items.push(item);
+                }
+            }
+            assert_eq!(items, vec![json!([1]), json!([2])], "{:?}", framing);
+        }
+
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.datagrams(true);
+        let mut buf = BytesMut::from("\u{feff}[1]".as_bytes());
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+    }
+
+    #[test]
+    fn decode_lossy_utf8() {
+        let input = &b"{\"a\": \"x\xff\"}\n[\"\xe2\x82\"]\n\xff\n"[..];
+        let mut codec: Codec<Value, ()> = Codec::default();
+        let mut buf = BytesMut::from(input);
+        assert!(codec.decode(&mut buf).is_err());
+
+        for framing in &[Framing::Concatenated, Framing::delimited(b"\n")] {
+            let mut codec: Codec<Value, ()> = Codec::default();
+            codec.framing(framing.clone());
+            codec.lossy_utf8(true);
+            let mut buf = BytesMut::from(input);
+            assert_eq!(
+                codec.decode(&mut buf).unwrap(),
+                Some(json!({"a": "x\u{fffd}"}))
+            );
+            assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(["\u{fffd}"])));
+            assert!(codec.decode_eof(&mut buf).is_err(), "{:?}", framing);
+        }
+    }
+
+    #[test]
+    fn decode_top_level_types() {
+        let mut buf = BytesMut::from(&b"{\"a\": 1} 2"[..]);
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.top_level_types(Some(&[JsonType::Object]));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": 1})));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "top-level number not allowed"
+        );
+    }
+
+    #[test]
+    fn decode_byte_quota() {
+        let mut buf = BytesMut::from(&b"\"a\" \"b\" \"c\""[..]);
+        let mut codec: Codec<String, ()> = Codec::default();
+        codec.byte_quota(Some(8));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("a".to_owned()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some("b".to_owned()));
+        assert_eq!(codec.decoded_bytes(), 7);
+        for _ in 0..2 {
+            match codec.decode(&mut buf) {
+                Err(Error::QuotaExceeded { quota }) => assert_eq!(quota, 8),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        codec.stats_reset();
+        assert_eq!(codec.decoded_bytes(), 11);
+    }
+
+    #[test]
+    fn decode_frame_quota() {
+        let mut buf = BytesMut::from(&b"1 2 3 4"[..]);
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.frame_quota(Some(2));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(2));
+        for _ in 0..2 {
+            match codec.decode(&mut buf) {
+                Err(Error::FrameQuotaExceeded { quota }) => assert_eq!(quota, 2),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+        assert_eq!(codec.decoded_frames(), 3);
+        assert_eq!(buf, &b" 4"[..]);
+    }
+
+    #[test]
+    fn decode_subscriptions() {
+        let mut buf =
+            BytesMut::from(&b"{\"n\": 1} {\"n\": 2} {\"n\": 3} {\"n\": 4} {\"n\": \"#\"}"[..]);
+        let mut codec: Codec<HashMap<String, u32>, ()> = Codec::default();
+        codec.subscribe("/n", |n| n.as_u64().is_some_and(|n| n % 2 == 0));
+        codec.subscribe("/n", |n| n == "#");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(hashmap! {"n".to_owned() => 2})
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(hashmap! {"n".to_owned() => 4})
+        );
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_deduplicate() {
+        let mut buf = BytesMut::from(
+            &b"{\"id\": 1, \"n\": 1} {\"id\": 1, \"n\": 2} {\"id\": 2, \"n\": 3}"[..],
+        );
+        let mut codec: Codec<HashMap<String, u32>, ()> = Codec::default();
+        codec.deduplicate("/id", Duration::from_secs(60));
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap()["n"], 1);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap()["n"], 3);
+        assert_eq!(codec.duplicates(), 1);
+    }
+
+    #[test]
+    fn frame_boundaries() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let mut codec: Codec<u32, u32> = Codec::default();
+        codec.on_frame(move |frame| {
+            sink.lock()
+                .unwrap()
+                .push((frame.direction, frame.offset, frame.len));
+        });
+        let mut buf = BytesMut::from(&b" 1 \"a\""[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(1));
+        assert!(codec.decode(&mut buf).is_err());
+        codec.encode(10, &mut buf).unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (Direction::Decode, 1, 1),
+                (Direction::Decode, 3, 3),
+                (Direction::Encode, 0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_stall() {
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = stalls.clone();
+        let mut buf = BytesMut::from(&b"nu"[..]);
+        let mut codec: Codec<_, ()> = Codec::default();
+        codec.on_stall(Duration::from_secs(0), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        buf.put(&b"l"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        buf.put(&b"l nu"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(stalls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn decode_no_stall() {
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = stalls.clone();
+        let mut buf = BytesMut::from(&b"nu"[..]);
+        let mut codec: Codec<(), ()> = Codec::default();
+        codec.on_stall(Duration::from_secs(3600), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(stalls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn decode_on_error() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let seen = errors.clone();
+        let mut buf = BytesMut::from(&b"\"a\" 2 \"toolong\" [?"[..]);
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.max_string_len(Some(3));
+        codec.skip_invalid_frames(true);
+        codec.peer_label(Some("peer"));
+        codec.on_error(move |e, raw| {
+            assert_eq!(e.label(), Some("peer"));
+            seen.lock().unwrap().push((e.kind(), raw.to_vec()));
+        });
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(2));
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(codec.decode_eof(&mut buf).is_err());
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec![
+                (ErrorKind::Data, b"\"a\"".to_vec()),
+                (ErrorKind::TooLarge, b"\"toolong\"".to_vec()),
+                (ErrorKind::Syntax, b" [?".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_frames() {
+        let mut buf = BytesMut::new();
+        let mut codec: Codec<(), ()> = Codec::default();
+        codec.encode((), &mut buf).unwrap();
+        assert_eq!(codec.slow_frames(), 0);
+        codec.slow_frame_threshold(Some(Duration::from_secs(0)));
+        buf.put(&b" "[..]);
+        codec.encode((), &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(()));
+        assert_eq!(codec.slow_frames(), 3);
+        let stats = codec.stats_reset();
+        assert_eq!((stats.decoded, stats.encoded, stats.slow_frames), (2, 2, 3));
+        assert_eq!(codec.stats_snapshot(), Stats::default());
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Codec<Rc<()>, Rc<()>>>();
+        assert_send_sync::<Reliable<Rc<()>, Rc<()>>>();
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = BytesMut::new();
+        let mut codec: Codec<(), _> = Codec::default();
+        codec.encode((), &mut buf).unwrap();
+        assert_eq!(buf, &b"null"[..]);
+    }
+
+    #[test]
+    fn encode_large() {
+        let item = Value::from(vec!["abcdefgh"; 2048]);
+        let mut buf = BytesMut::from(&b"[1]"[..]);
+        let mut codec: Codec<Value, _> = Codec::default();
+        codec.encode(item.clone(), &mut buf).unwrap();
+        assert_eq!(buf.len(), 3 + 2048 * 11 + 1);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!([1])));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(item.clone()));
+
+        // Room is made for frames the size of those encoded recently.
+        for _ in 0..32 {
+            codec.encode(item.clone(), &mut buf).unwrap();
+        }
+        let expected = codec.encoded_size.get();
+        assert!(expected > 20000 && expected <= 2048 * 11 + 1);
+        let mut buf = BytesMut::new();
+        codec.encode(json!(1), &mut buf).unwrap();
+        assert!(buf.capacity() >= expected);
+    }
+
+    #[test]
+    fn const_constructors() {
+        static CODEC: Codec<(), Value> = Codec::pretty_printed();
+        let mut buf = BytesMut::new();
+        CODEC.clone().encode(json!([1]), &mut buf).unwrap();
+        assert_eq!(buf, &b"[\n  1\n]"[..]);
+        let mut buf = BytesMut::new();
+        Codec::<(), _>::compact()
+            .encode(json!([1]), &mut buf)
+            .unwrap();
+        assert_eq!(buf, &b"[1]"[..]);
     }
 
     #[test]
@@ -0,0 +1,243 @@
+//! Decoding newline-delimited JSON, which may be interleaved with plain text.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use lex::is_whitespace;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use tokio_codec::{Decoder, Encoder};
+use {deserialize_frame, Codec, Error};
+
+/// An item decoded by [`Lines`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line<D> {
+    /// A line holding a JSON value.
+    Value(D),
+    /// A line that isn't JSON, without its line ending.
+    Text(String),
+}
+
+/// What [`Lines`] does with lines that aren't JSON at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextLines {
+    /// Fail with a decode error, as for any other invalid frame.
+    Error,
+    /// Skip the line.
+    Skip,
+    /// Decode the line as a [`Line::Text`].
+    Emit,
+}
+
+/// A [`Codec`] for newline-delimited JSON ([NDJSON]), with one value per line.
+///
+/// Unlike the plain codec, a value can't span lines, and lines that aren't JSON at all, such as
+/// a stack trace interleaved in a log stream, can be skipped or decoded as text; see
+/// [`Lines::text_lines`]. Blank lines are skipped. A line that is valid JSON but doesn't
+/// deserialize into `D` always fails. Each encoded value is followed by a newline, and is never
+/// pretty-printed.
+///
+/// Created by [`Codec::lines`].
+///
+/// [NDJSON]: http://ndjson.org/
+#[derive(Clone, Debug)]
+pub struct Lines<D, E> {
+    codec: Codec<D, E>,
+    text: TextLines,
+}
+
+impl<D, E> Lines<D, E> {
+    /// Set what to do with lines that aren't JSON. [`TextLines::Error`] by default.
+    pub fn text_lines(&mut self, text: TextLines) {
+        self.text = text;
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Lines`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to decode and encode one value per line, turning off pretty-printing.
+    pub fn lines(mut self) -> Lines<D, E> {
+        self.pretty = false;
+        Lines {
+            codec: self,
+            text: TextLines::Error,
+        }
+    }
+
+    /// Splits the next line that isn't blank off `src`, without surrounding whitespace, reporting
+    /// its boundary.
+    ///
+    /// At `eof`, the rest of `src` is a line even without a line ending.
+    fn next_line(&mut self, src: &mut BytesMut, eof: bool) -> Option<Bytes> {
+        if self.sentinel_received {
+            return None;
+        }
+        loop {
+            let end = match src.iter().position(|&b| b == b'\n') {
+                Some(pos) => pos + 1,
+                None if eof && !src.is_empty() => src.len(),
+                None => return None,
+            };
+            let line = src.split_to(end).freeze();
+            let start = line.iter().take_while(|&&b| is_whitespace(b)).count();
+            let trailing = line[start..]
+                .iter()
+                .rev()
+                .take_while(|&&b| is_whitespace(b))
+                .count();
+            let frame = line.slice(start, line.len() - trailing);
+            if let Some(ref mut positions) = self.positions {
+                positions.line();
+            }
+            let len = Some(frame.len()).filter(|&len| len > 0);
+            self.split_off(line.len() - trailing, len);
+            self.split_off(trailing, None);
+            if !frame.is_empty() {
+                return Some(frame);
+            }
+        }
+    }
+}
+
+impl<D, E> Lines<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    fn decode_lines(&mut self, src: &mut BytesMut, eof: bool) -> Result<Option<Line<D>>, Error> {
+        let text = self.text;
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(line) = codec.next_line(src, eof) {
+                if text != TextLines::Error
+                    && deserialize_frame::<IgnoredAny>(line.clone(), false, codec.unbounded_depth)
+                        .is_err()
+                {
+                    if text == TextLines::Emit {
+                        let text = String::from_utf8_lossy(&line).into_owned();
+                        return Ok(Some(Line::Text(text)));
+                    }
+                    continue;
+                }
+                if let Some(value) = codec.decode_value(line)? {
+                    return Ok(Some(Line::Value(value)));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Decoder for Lines<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = Line<D>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Line<D>>, Error> {
+        self.decode_lines(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Line<D>>, Error> {
+        // Every line is taken, even without a line ending. Whatever is left after the sentinel
+        // is left for the next user of the connection rather than being an error.
+        self.decode_lines(src, true)
+    }
+}
+
+impl<D, E> Encoder for Lines<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)?;
+        dst.reserve(1);
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Line, TextLines};
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use Codec;
+
+    #[test]
+    fn decode_lines() {
+        let input = &b"{\"a\": 1}\r\n\nat foo.rs:1\n  [2]  \n3"[..];
+        let mut codec = Codec::<Value, ()>::default().lines();
+        let mut buf = BytesMut::from(input);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Line::Value(json!({"a": 1})))
+        );
+        assert!(codec.decode(&mut buf).is_err());
+
+        let mut buf = BytesMut::from(input);
+        codec.text_lines(TextLines::Emit);
+        let lines: Vec<_> = (0..4)
+            .map(|_| codec.decode_eof(&mut buf).unwrap().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                Line::Value(json!({"a": 1})),
+                Line::Text("at foo.rs:1".to_owned()),
+                Line::Value(json!([2])),
+                Line::Value(json!(3)),
+            ]
+        );
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+
+        let mut buf = BytesMut::from(input);
+        codec.text_lines(TextLines::Skip);
+        codec.decode(&mut buf).unwrap();
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Line::Value(json!([2])))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf, "3");
+    }
+
+    #[test]
+    fn line_positions() {
+        let mut buf = BytesMut::from(&b"1\n\n  \"a\"\r\n3\n"[..]);
+        let mut codec = Codec::<u32, ()>::default().lines();
+        codec.codec_mut().track_positions(true);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode(&mut buf).is_err());
+        let position = codec.codec().last_position().unwrap();
+        assert_eq!(
+            (position.index, position.offset, position.line),
+            (1, 5, Some(3))
+        );
+    }
+
+    #[test]
+    fn encode_lines() {
+        let mut codec = Codec::<(), Value>::new(true).lines();
+        let mut buf = BytesMut::new();
+        codec.encode(json!({"a": [1]}), &mut buf).unwrap();
+        codec.encode(json!(2), &mut buf).unwrap();
+        assert_eq!(buf, "{\"a\":[1]}\n2\n");
+    }
+}
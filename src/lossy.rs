@@ -0,0 +1,110 @@
+//! Carrying on past frames that fail to decode.
+
+use futures::{Async, Poll, Sink, StartSend, Stream};
+use Error;
+
+/// A stream that yields the errors for frames that fail to decode as items, rather than failing,
+/// so that a consumer can count or quarantine bad frames and carry on.
+///
+/// Only errors that lose nothing but the frame they occurred in are yielded as items; the stream
+/// still fails with those that [are fatal][`Error::is_fatal`], such as I/O errors. With
+/// [`Framing::Concatenated`][`::Framing::Concatenated`], syntax errors are fatal unless the
+/// codec [resynchronizes][`::Codec::resync`]. The underlying sink, if any, is passed through.
+///
+/// Created by [`FramedJson::into_lossy_stream`][`::FramedJson::into_lossy_stream`], or by
+/// [`Lossy::new`] for other streams of decoded items.
+#[derive(Debug)]
+pub struct Lossy<S> {
+    stream: S,
+}
+
+impl<S> Lossy<S> {
+    /// Creates a `Lossy` yielding the items and non-fatal errors of `stream`.
+    pub fn new(stream: S) -> Self {
+        Lossy { stream }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the `Lossy`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> Stream for Lossy<S>
+where
+    S: Stream<Error = Error>,
+{
+    type Item = Result<S::Item, Error>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Result<S::Item, Error>>, Error> {
+        match self.stream.poll() {
+            Ok(Async::Ready(item)) => Ok(Async::Ready(item.map(Ok))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) if e.is_fatal() => Err(e),
+            Err(e) => Ok(Async::Ready(Some(Err(e)))),
+        }
+    }
+}
+
+impl<S> Sink for Lossy<S>
+where
+    S: Sink,
+{
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: S::SinkItem) -> StartSend<S::SinkItem, S::SinkError> {
+        self.stream.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use std::io::Cursor;
+    use {Codec, ErrorKind, FramedJson, Framing};
+
+    #[test]
+    fn lossy() {
+        let mut codec: Codec<u32, ()> = Codec::default();
+        codec.framing(Framing::delimited(b"\n"));
+        let input = b"1\n\"a\"\n?\n4\n".to_vec();
+        let items: Vec<_> = FramedJson::new(Cursor::new(input), codec.clone())
+            .into_lossy_stream()
+            .map(|item| item.map_err(|e| e.kind()))
+            .collect()
+            .wait()
+            .unwrap();
+        let expected = vec![Ok(1), Err(ErrorKind::Data), Err(ErrorKind::Syntax), Ok(4)];
+        assert_eq!(items, expected);
+
+        codec.framing(Framing::Concatenated);
+        let input = b"1 ? 4".to_vec();
+        let lossy = FramedJson::new(Cursor::new(input), codec).into_lossy_stream();
+        let (item, lossy) = lossy.into_future().wait().map_err(|_| ()).unwrap();
+        assert_eq!(item.map(Result::unwrap), Some(1));
+        assert_eq!(
+            lossy.collect().wait().unwrap_err().kind(),
+            ErrorKind::Syntax
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Merging the streams of many connections into one.
+
+use futures::task::{self, Task};
+use futures::{Async, Poll, Stream};
+use std::mem;
+
+/// What [`Merge`] yields for a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Merged<T, E> {
+    /// An item received on the connection.
+    Item(T),
+    /// The connection failed, and was removed.
+    Error(E),
+    /// The connection ended, and was removed.
+    Closed,
+}
+
+/// A stream merging the streams of many connections, such as [`FramedJson`]s, into one stream of
+/// `(id, Merged)` pairs.
+///
+/// Connections take turns, so a busy one can't starve the others, and each is only polled when
+/// the merged stream is, so a slow consumer holds up reading from every connection. A connection
+/// that fails or ends is removed after its error or end is yielded; the others carry on. The
+/// merged stream never ends by itself: once every connection is removed it waits for more to be
+/// [inserted][`Merge::insert`].
+///
+/// [`FramedJson`]: ::FramedJson
+#[derive(Debug)]
+pub struct Merge<K, S> {
+    streams: Vec<(K, S)>,
+    cursor: usize,
+    task: Option<Task>,
+}
+
+impl<K, S> Default for Merge<K, S> {
+    fn default() -> Self {
+        Merge::new()
+    }
+}
+
+impl<K, S> Merge<K, S> {
+    /// Creates a `Merge` without any connections.
+    pub fn new() -> Self {
+        Merge {
+            streams: Vec::new(),
+            cursor: 0,
+            task: None,
+        }
+    }
+
+    /// Returns how many connections there are.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Returns whether there are no connections.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Returns the ids of the connections.
+    pub fn ids(&self) -> impl Iterator<Item = &K> {
+        self.streams.iter().map(|(id, _)| id)
+    }
+}
+
+impl<K, S> Merge<K, S>
+where
+    K: PartialEq,
+{
+    /// Adds the connection `id`, returning the stream it replaces if there already was one.
+    pub fn insert(&mut self, id: K, stream: S) -> Option<S> {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+        match self.streams.iter_mut().find(|(k, _)| *k == id) {
+            Some((_, existing)) => Some(mem::replace(existing, stream)),
+            None => {
+                self.streams.push((id, stream));
+                None
+            }
+        }
+    }
+
+    /// Removes the connection `id`, returning its stream.
+    pub fn remove(&mut self, id: &K) -> Option<S> {
+        let index = self.streams.iter().position(|(k, _)| k == id)?;
+        Some(self.remove_at(index).1)
+    }
+
+    /// Removes the connection at `index`, keeping the next connection's turn.
+    fn remove_at(&mut self, index: usize) -> (K, S) {
+        if index < self.cursor {
+            self.cursor -= 1;
+        }
+        self.streams.remove(index)
+    }
+}
+
+impl<K, S> Stream for Merge<K, S>
+where
+    K: PartialEq + Clone,
+    S: Stream,
+{
+    type Item = (K, Merged<S::Item, S::Error>);
+    /// The merged stream itself never fails: connection errors are yielded as
+    /// [`Merged::Error`].
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, S::Error> {
+        let len = self.streams.len();
+        for i in 0..len {
+            let index = (self.cursor + i) % len;
+            let merged = match self.streams[index].1.poll() {
+                Ok(Async::NotReady) => continue,
+                Ok(Async::Ready(Some(item))) => {
+                    self.cursor = index + 1;
+                    return Ok(Async::Ready(Some((
+                        self.streams[index].0.clone(),
+                        Merged::Item(item),
+                    ))));
+                }
+                Ok(Async::Ready(None)) => Merged::Closed,
+                Err(e) => Merged::Error(e),
+            };
+            self.cursor = index;
+            let (id, _) = self.remove_at(index);
+            return Ok(Async::Ready(Some((id, merged))));
+        }
+        // Connections inserted from now on haven't been polled, so wake up to poll them.
+        self.task = Some(task::current());
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Merge, Merged};
+    use futures::stream::{empty, iter_ok, iter_result};
+    use futures::{Future, Stream};
+
+    type BoxStream = Box<dyn Stream<Item = u32, Error = &'static str>>;
+
+    #[test]
+    fn merge() {
+        let mut merge: Merge<&str, BoxStream> = Merge::new();
+        merge.insert("a", Box::new(iter_ok(vec![1, 2, 3])));
+        merge.insert("b", Box::new(iter_result(vec![Ok(4), Err("bad"), Ok(5)])));
+        merge.insert("c", Box::new(empty()));
+        assert!(merge.insert("c", Box::new(empty())).is_some());
+        assert_eq!(merge.len(), 3);
+        let merged = merge.take(7).collect().wait().unwrap();
+        assert_eq!(
+            merged,
+            vec![
+                ("a", Merged::Item(1)),
+                ("b", Merged::Item(4)),
+                ("c", Merged::Closed),
+                ("a", Merged::Item(2)),
+                ("b", Merged::Error("bad")),
+                ("a", Merged::Item(3)),
+                ("a", Merged::Closed),
+            ]
+        );
+
+        let mut merge: Merge<&str, BoxStream> = Merge::new();
+        merge.insert("a", Box::new(iter_ok(vec![1])));
+        assert!(merge.remove(&"a").is_some());
+        assert!(merge.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! Decoding values along with metadata about their frames.
+
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use tokio_codec::{Decoder, Encoder};
+use {deserialize_frame, Codec, Error, Position};
+
+/// Metadata about a frame decoded by [`WithMeta`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct FrameMeta {
+    /// The length of the frame in bytes, excluding surrounding whitespace.
+    pub size: usize,
+    /// When the decoder first saw the start of the frame, which is when it was received at the
+    /// latest.
+    pub received: SystemTime,
+    /// When the frame was deserialized.
+    pub decoded: SystemTime,
+    /// The values of the [envelope fields][`WithMeta::field`] that the frame has, by pointer.
+    pub fields: BTreeMap<String, Value>,
+    /// Where the frame is in the input, if [`Codec::track_positions`] is enabled.
+    pub position: Option<Position>,
+}
+
+/// A [`Codec`] that decodes each value along with [`FrameMeta`] about its frame.
+///
+/// This is useful for observability and for accounting by bytes. Encoding is unaffected.
+///
+/// Created by [`Codec::with_meta`].
+#[derive(Clone, Debug)]
+pub struct WithMeta<D, E> {
+    codec: Codec<D, E>,
+    fields: Vec<String>,
+    pending_since: Option<SystemTime>,
+}
+
+impl<D, E> WithMeta<D, E> {
+    /// Adds an envelope field to extract into [`FrameMeta::fields`], such as `/id`.
+    ///
+    /// `pointer` is a JSON Pointer as for [`Codec::subscribe`]. Once any fields are added, each
+    /// frame is also parsed into a [`Value`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pointer` is neither empty nor starts with `/`.
+    pub fn field(&mut self, pointer: &str) {
+        assert!(
+            pointer.is_empty() || pointer.starts_with('/'),
+            "invalid JSON Pointer `{}`",
+            pointer
+        );
+        self.fields.push(pointer.to_owned());
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `WithMeta`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec so that each decoded value comes with metadata about its frame.
+    pub fn with_meta(self) -> WithMeta<D, E> {
+        WithMeta {
+            codec: self,
+            fields: Vec::new(),
+            pending_since: None,
+        }
+    }
+}
+
+impl<D, E> Decoder for WithMeta<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = (FrameMeta, D);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<(FrameMeta, D)>, Error> {
+        let now = SystemTime::now();
+        if src.is_empty() {
+            self.pending_since = None;
+        } else if self.pending_since.is_none() {
+            self.pending_since = Some(now);
+        }
+        let (fields, pending_since) = (&self.fields, &mut self.pending_since);
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(frame) = codec.next_frame(src)? {
+                let received = pending_since.unwrap_or(now);
+                // Anything left in the buffer was there by the time `decode` was called.
+                *pending_since = Some(now).filter(|_| !src.is_empty());
+                let size = frame.len();
+                let position = codec.last_position();
+                let value = if fields.is_empty() {
+                    None
+                } else {
+                    deserialize_frame::<Value>(frame.clone(), false, codec.unbounded_depth).ok()
+                };
+                if let Some(item) = codec.decode_value(frame)? {
+                    let fields = fields
+                        .iter()
+                        .filter_map(|pointer| {
+                            let field = value.as_ref()?.pointer(pointer)?;
+                            Some((pointer.clone(), field.clone()))
+                        })
+                        .collect();
+                    let meta = FrameMeta {
+                        size,
+                        received,
+                        decoded: SystemTime::now(),
+                        fields,
+                        position,
+                    };
+                    return Ok(Some((meta, item)));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Encoder for WithMeta<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn decode_with_meta() {
+        let mut buf = BytesMut::from(&b"{\"id\": 7, \"n\": 1} {\"n\": 2}"[..]);
+        let mut codec = Codec::<Value, ()>::default();
+        codec.track_positions(true);
+        let mut codec = codec.with_meta();
+        codec.field("/id");
+        let (meta, value) = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(value, json!({"id": 7, "n": 1}));
+        assert_eq!(meta.size, 17);
+        assert_eq!(meta.fields, btreemap! {"/id".to_owned() => json!(7)});
+        assert_eq!(meta.position.unwrap().index, 0);
+        assert!(meta.received <= meta.decoded);
+        let (meta, _) = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(meta.fields.is_empty());
+        assert_eq!(meta.position.unwrap().offset, 18);
+    }
+}
@@ -0,0 +1,305 @@
+//! Multiplexing logical channels over one connection.
+
+use futures::sync::mpsc;
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::marker::PhantomData;
+use Error;
+
+/// Multiplexes logical channels over one connection, wrapping each frame in an envelope such as
+/// `{"chan": 3, "payload": ...}`.
+///
+/// The transport is typically a [`Framed`][`tokio_codec::Framed`] with a `Codec<Value, Value>`.
+/// Open channels with [`Mux::channel`], then spawn the `Mux`: it is a future that routes frames
+/// between the connection and the channels until the peer closes the connection.
+///
+/// Each channel buffers up to `buffer` frames in each direction. A channel whose outbound buffer
+/// is full stops accepting items without holding up the others, and channels take turns to send.
+/// A channel that isn't consuming its inbound frames holds up reading from the connection once its
+/// buffer is full, though, so every channel should be polled. Frames for channels that aren't
+/// open are dropped.
+#[derive(Debug)]
+pub struct Mux<S> {
+    transport: S,
+    buffer: usize,
+    routes: BTreeMap<u64, Route>,
+    inbound: Option<(u64, Value)>,
+    outbound: Option<Value>,
+    cursor: u64,
+}
+
+#[derive(Debug)]
+struct Route {
+    inbound: Option<mpsc::Sender<Value>>,
+    outbound: Option<mpsc::Receiver<Value>>,
+}
+
+impl<S> Mux<S> {
+    /// Creates a `Mux` over `transport`, buffering up to `buffer` frames per channel and
+    /// direction.
+    pub fn new(transport: S, buffer: usize) -> Self {
+        Mux {
+            transport,
+            buffer,
+            routes: BTreeMap::new(),
+            inbound: None,
+            outbound: None,
+            cursor: 0,
+        }
+    }
+
+    /// Opens the channel `chan`, replacing any previously opened channel with the same id.
+    pub fn channel<D, E>(&mut self, chan: u64) -> Channel<D, E> {
+        let (inbound_tx, inbound_rx) = mpsc::channel(self.buffer);
+        let (outbound_tx, outbound_rx) = mpsc::channel(self.buffer);
+        self.routes.insert(
+            chan,
+            Route {
+                inbound: Some(inbound_tx),
+                outbound: Some(outbound_rx),
+            },
+        );
+        Channel {
+            inbound: inbound_rx,
+            outbound: outbound_tx,
+            _priv: PhantomData,
+        }
+    }
+}
+
+impl<S> Mux<S>
+where
+    S: Stream<Item = Value, Error = Error> + Sink<SinkItem = Value, SinkError = Error>,
+{
+    /// Routes inbound frames to their channels, returning whether the connection was closed.
+    fn poll_inbound(&mut self) -> Result<bool, Error> {
+        loop {
+            if let Some((chan, payload)) = self.inbound.take() {
+                let sent = match self.routes.get_mut(&chan).and_then(|r| r.inbound.as_mut()) {
+                    Some(inbound) => inbound.start_send(payload),
+                    None => {
+                        tracing::warn!(chan, "dropping frame for a channel that isn't open");
+                        Ok(AsyncSink::Ready)
+                    }
+                };
+                match sent {
+                    Ok(AsyncSink::Ready) => {}
+                    Ok(AsyncSink::NotReady(payload)) => {
+                        self.inbound = Some((chan, payload));
+                        return Ok(false);
+                    }
+                    // The channel's stream was dropped.
+                    Err(_) => {
+                        if let Some(route) = self.routes.get_mut(&chan) {
+                            route.inbound = None;
+                        }
+                    }
+                }
+            }
+            match self.transport.poll()? {
+                Async::Ready(Some(frame)) => self.inbound = Some(open(frame)?),
+                Async::Ready(None) => return Ok(true),
+                Async::NotReady => return Ok(false),
+            }
+        }
+    }
+
+    /// Sends outbound frames from the channels, taking turns between them.
+    fn poll_outbound(&mut self) -> Result<(), Error> {
+        loop {
+            if let Some(frame) = self.outbound.take() {
+                if let AsyncSink::NotReady(frame) = self.transport.start_send(frame)? {
+                    self.outbound = Some(frame);
+                    break;
+                }
+            }
+            let cursor = self.cursor;
+            let next = poll_routes(self.routes.range_mut(cursor..))
+                .or_else(|| poll_routes(self.routes.range_mut(..cursor)));
+            match next {
+                Some((chan, payload)) => {
+                    self.cursor = chan.wrapping_add(1);
+                    self.outbound = Some(seal(chan, payload));
+                }
+                None => break,
+            }
+        }
+        self.transport.poll_complete()?;
+        Ok(())
+    }
+}
+
+impl<S> Future for Mux<S>
+where
+    S: Stream<Item = Value, Error = Error> + Sink<SinkItem = Value, SinkError = Error>,
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        let closed = self.poll_inbound()?;
+        self.poll_outbound()?;
+        if closed {
+            self.transport.close()
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Returns the next outbound payload from `routes`, along with its channel.
+fn poll_routes<'a, I>(routes: I) -> Option<(u64, Value)>
+where
+    I: Iterator<Item = (&'a u64, &'a mut Route)>,
+{
+    for (&chan, route) in routes {
+        let closed = match route.outbound {
+            Some(ref mut outbound) => match outbound.poll() {
+                Ok(Async::Ready(Some(payload))) => return Some((chan, payload)),
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(None)) | Err(()) => true,
+            },
+            None => false,
+        };
+        if closed {
+            route.outbound = None;
+        }
+    }
+    None
+}
+
+/// Wraps `payload` in an envelope for `chan`.
+fn seal(chan: u64, payload: Value) -> Value {
+    let mut envelope = Map::new();
+    envelope.insert("chan".to_owned(), chan.into());
+    envelope.insert("payload".to_owned(), payload);
+    Value::Object(envelope)
+}
+
+/// Unwraps an envelope, returning its channel and payload.
+fn open(frame: Value) -> Result<(u64, Value), Error> {
+    if let Value::Object(mut envelope) = frame {
+        if let Some(chan) = envelope.get("chan").and_then(Value::as_u64) {
+            let payload = envelope.remove("payload").unwrap_or(Value::Null);
+            return Ok((chan, payload));
+        }
+    }
+    Err(serde_json::Error::custom("expected a channel envelope").into())
+}
+
+/// A logical channel opened with [`Mux::channel`], decoding `D`s and encoding `E`s.
+///
+/// A channel is a [`Stream`] of the items received on it and a [`Sink`] for items to send. It
+/// ends when the connection is closed, after which sending fails with a
+/// [`BrokenPipe`][`io::ErrorKind::BrokenPipe`] error.
+#[derive(Debug)]
+pub struct Channel<D, E> {
+    inbound: mpsc::Receiver<Value>,
+    outbound: mpsc::Sender<Value>,
+    _priv: PhantomData<fn(E) -> D>,
+}
+
+impl<D, E> Stream for Channel<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<D>, Error> {
+        match self.inbound.poll() {
+            Ok(Async::Ready(Some(payload))) => Ok(Async::Ready(Some(D::deserialize(payload)?))),
+            Ok(Async::Ready(None)) | Err(()) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl<D, E> Sink for Channel<D, E>
+where
+    E: Serialize,
+{
+    type SinkItem = E;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: E) -> StartSend<E, Error> {
+        match self.outbound.poll_ready() {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(item)),
+            Err(_) => return Err(closed()),
+        }
+        let payload = serde_json::to_value(item)?;
+        self.outbound.start_send(payload).map_err(|_| closed())?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        self.outbound.poll_complete().map_err(|_| closed())
+    }
+
+    fn close(&mut self) -> Poll<(), Error> {
+        self.outbound.close().map_err(|_| closed())
+    }
+}
+
+fn closed() -> Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "multiplexed connection closed").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mux;
+    use futures::sync::mpsc;
+    use futures::{Future, Poll, Sink, StartSend, Stream};
+    use serde_json::Value;
+    use tokio::runtime::current_thread::Runtime;
+    use Error;
+
+    /// A transport that sends frames back to itself.
+    struct Loopback {
+        tx: mpsc::UnboundedSender<Value>,
+        rx: mpsc::UnboundedReceiver<Value>,
+    }
+
+    impl Stream for Loopback {
+        type Item = Value;
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<Option<Value>, Error> {
+            Ok(self.rx.poll().unwrap())
+        }
+    }
+
+    impl Sink for Loopback {
+        type SinkItem = Value;
+        type SinkError = Error;
+
+        fn start_send(&mut self, item: Value) -> StartSend<Value, Error> {
+            Ok(self.tx.start_send(item).unwrap())
+        }
+
+        fn poll_complete(&mut self) -> Poll<(), Error> {
+            Ok(self.tx.poll_complete().unwrap())
+        }
+    }
+
+    #[test]
+    fn channels() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut mux = Mux::new(Loopback { tx, rx }, 1);
+        let numbers = mux.channel::<u32, u32>(1);
+        let strings = mux.channel::<String, String>(2);
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(mux.map_err(|e| panic!("{}", e)));
+        let strings = runtime.block_on(strings.send("a".to_owned())).unwrap();
+        let numbers = runtime.block_on(numbers.send(7)).unwrap();
+        let (number, _) = runtime.block_on(numbers.into_future()).ok().unwrap();
+        let (string, _) = runtime.block_on(strings.into_future()).ok().unwrap();
+        assert_eq!(number, Some(7));
+        assert_eq!(string, Some("a".to_owned()));
+    }
+}
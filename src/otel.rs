@@ -0,0 +1,142 @@
+//! OpenTelemetry trace-context propagation (requires the `otel` feature).
+
+use opentelemetry::global;
+use opentelemetry::trace::{Span, Status, TraceContextExt, Tracer};
+use opentelemetry::Context;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use Error;
+
+/// Name of the tracer decode spans are started with.
+const TRACER: &str = "tokio-jsoncodec";
+
+/// Propagation state; see [`Codec::propagate_trace_context`][`::Codec::propagate_trace_context`].
+#[derive(Clone, Debug)]
+pub(crate) struct TraceContext {
+    field: String,
+    last: Option<Context>,
+}
+
+impl TraceContext {
+    pub(crate) fn new(field: &str) -> Self {
+        TraceContext {
+            field: field.to_owned(),
+            last: None,
+        }
+    }
+
+    pub(crate) fn last(&self) -> Option<&Context> {
+        self.last.as_ref()
+    }
+
+    /// Removes the carrier from `frame`, starts a decode span under the remote context it carries
+    /// and deserializes the rest of the frame with `deserialize` within that span.
+    pub(crate) fn decode<T, F>(&mut self, mut frame: Value, deserialize: F) -> Result<T, Error>
+    where
+        F: FnOnce(Value) -> Result<T, Error>,
+    {
+        let carrier: HashMap<String, String> = match frame {
+            Value::Object(ref mut map) => match map.remove(&self.field) {
+                Some(Value::Object(carrier)) => carrier
+                    .into_iter()
+                    .filter_map(|(k, v)| match v {
+                        Value::String(v) => Some((k, v)),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => HashMap::new(),
+            },
+            _ => HashMap::new(),
+        };
+        let parent = global::get_text_map_propagator(|p| p.extract(&carrier));
+        let mut span = global::tracer(TRACER).start_with_context("decode", &parent);
+        let result = deserialize(frame);
+        if let Err(ref e) = result {
+            span.set_status(Status::error(e.to_string()));
+        }
+        span.end();
+        self.last = Some(parent.with_span(span));
+        result
+    }
+
+    /// Injects the current context into `frame` if it is an object.
+    pub(crate) fn inject(&self, frame: &mut Value) {
+        if let Value::Object(ref mut map) = *frame {
+            let mut carrier = HashMap::new();
+            global::get_text_map_propagator(|p| {
+                p.inject_context(&Context::current(), &mut carrier)
+            });
+            if !carrier.is_empty() {
+                let carrier: Map<String, Value> = carrier
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect();
+                map.insert(self.field.clone(), Value::Object(carrier));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use opentelemetry::global;
+    use opentelemetry::propagation::text_map_propagator::FieldIter;
+    use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+    use opentelemetry::Context;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use Codec;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Marker(String);
+
+    /// Propagates a `Marker` as the `marker` carrier key.
+    #[derive(Debug)]
+    struct MarkerPropagator(Vec<String>);
+
+    impl TextMapPropagator for MarkerPropagator {
+        fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+            if let Some(marker) = cx.get::<Marker>() {
+                injector.set("marker", marker.0.clone());
+            }
+        }
+
+        fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+            match extractor.get("marker") {
+                Some(marker) => cx.with_value(Marker(marker.to_owned())),
+                None => cx.clone(),
+            }
+        }
+
+        fn fields(&self) -> FieldIter<'_> {
+            FieldIter::new(&self.0)
+        }
+    }
+
+    #[test]
+    fn propagate() {
+        global::set_text_map_propagator(MarkerPropagator(vec!["marker".to_owned()]));
+        let mut codec: Codec<Value, _> = Codec::default();
+        codec.propagate_trace_context(Some("trace"));
+
+        let mut buf = BytesMut::new();
+        {
+            let _guard = Context::current_with_value(Marker("abc".to_owned())).attach();
+            codec
+                .encode(hashmap! { "butts" => "lol" }, &mut buf)
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(buf.to_vec()).unwrap(),
+            r#"{"butts":"lol","trace":{"marker":"abc"}}"#
+        );
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(json!({ "butts": "lol" }))
+        );
+        let cx = codec.trace_context().unwrap();
+        assert_eq!(cx.get::<Marker>(), Some(&Marker("abc".to_owned())));
+    }
+}
@@ -0,0 +1,159 @@
+//! Splitting input into frames without a [`Decoder`][`tokio_codec::Decoder`].
+
+use bytes::{Bytes, BytesMut};
+use lex::is_whitespace;
+use serde::Deserialize;
+use {Codec, Error};
+
+/// A frame split off by a [`FrameParser`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Frame {
+    /// The offset of the first byte of the frame in the input.
+    pub offset: u64,
+    /// The frame, excluding surrounding whitespace.
+    pub bytes: Bytes,
+}
+
+/// A frame parser that is driven by hand rather than through a
+/// [`Framed`][`tokio_codec::Framed`], for custom event loops and sans-I/O protocol
+/// implementations.
+///
+/// [Push][`FrameParser::push`] input as it arrives, pull complete frames with
+/// [`FrameParser::next_frame`], and [decode][`FrameParser::decode`] those that are wanted. Frames
+/// are split off as by the codec's decoder, so keepalive tokens are skipped and splitting stops at
+/// the sentinel, and decoding applies the codec's other options.
+///
+/// Created by [`Codec::frame_parser`].
+#[derive(Clone, Debug)]
+pub struct FrameParser<D, E> {
+    codec: Codec<D, E>,
+    buf: BytesMut,
+    offset: u64,
+}
+
+impl<D, E> FrameParser<D, E> {
+    /// Appends `input` to the buffered input.
+    pub fn push(&mut self, input: &[u8]) {
+        self.buf.extend_from_slice(input);
+    }
+
+    /// Returns the buffered input that hasn't been split into frames yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[..]
+    }
+
+    /// Returns whether part of a frame is buffered.
+    pub fn is_pending(&self) -> bool {
+        self.buf.iter().any(|&b| !is_whitespace(b))
+    }
+
+    /// Returns how many bytes of input have been consumed, which is the offset of the buffered
+    /// input.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `FrameParser`, returning the underlying codec and the buffered input.
+    pub fn into_inner(self) -> (Codec<D, E>, BytesMut) {
+        (self.codec, self.buf)
+    }
+}
+
+impl<D, E> FrameParser<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    /// Splits the next complete frame off the buffered input, or returns `None` if more input
+    /// is needed.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let len = self.buf.len();
+        self.codec.pending_frame = None;
+        let frame = self.codec.next_frame(&mut self.buf);
+        let consumed = (len - self.buf.len()) as u64;
+        let frame = frame.map_err(|e| {
+            let start = self.offset.saturating_add(consumed);
+            self.codec.annotate(with_start(e, start))
+        })?;
+        self.offset = self.offset.saturating_add(consumed);
+        Ok(frame.map(|bytes| Frame {
+            offset: self.offset - bytes.len() as u64,
+            bytes,
+        }))
+    }
+
+    /// Decodes a frame split off by [`FrameParser::next_frame`], or returns `None` if it is
+    /// dropped, as by a [subscription][`Codec::subscribe`], or is the sentinel.
+    pub fn decode(&mut self, frame: Frame) -> Result<Option<D>, Error> {
+        let offset = frame.offset;
+        let item = self
+            .codec
+            .decode_value(frame.bytes)
+            .map_err(|e| self.codec.annotate(with_start(e, offset)))?;
+        if item.is_some() {
+            self.codec.decoded = self.codec.decoded.saturating_add(1);
+        }
+        Ok(item)
+    }
+}
+
+/// Records that a decode error's input starts at `start` in the stream.
+fn with_start(mut err: Error, start: u64) -> Error {
+    if let Error::Decode(ref mut e) = err {
+        e.start = Some(start);
+    }
+    err
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec in a [`FrameParser`], to be driven by hand.
+    pub fn frame_parser(self) -> FrameParser<D, E> {
+        FrameParser {
+            codec: self,
+            buf: BytesMut::new(),
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use {Codec, Error};
+
+    #[test]
+    fn frame_parser() {
+        let mut parser = Codec::<Value, ()>::default().frame_parser();
+        parser.push(b"{\"a\": 1} [tr");
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!((frame.offset, &frame.bytes[..]), (0, &b"{\"a\": 1}"[..]));
+        assert_eq!(parser.next_frame().unwrap(), None);
+        assert_eq!(parser.buffered(), b" [tr");
+        assert_eq!(parser.offset(), 8);
+        assert!(parser.is_pending());
+        assert_eq!(parser.decode(frame).unwrap(), Some(json!({"a": 1})));
+
+        parser.push(b"ue]\n");
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(frame.offset, 9);
+        assert_eq!(parser.decode(frame).unwrap(), Some(json!([true])));
+        assert!(!parser.is_pending());
+        assert_eq!(parser.codec().stats_snapshot().decoded, 2);
+
+        parser.push(b"{\"a\": ?}");
+        match parser.next_frame() {
+            Err(Error::Decode(e)) => assert_eq!(e.stream_offset(), Some(22)),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Replicating a state as a snapshot followed by patches.
+
+use bytes::BytesMut;
+use json_patch;
+use serde::de::Error as _;
+use serde_json::{self, Map, Value};
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// How [`Patched`] represents changes to the state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PatchFormat {
+    /// [JSON Patch][RFC 6902] documents, which are arrays of operations.
+    ///
+    /// [RFC 6902]: https://tools.ietf.org/html/rfc6902
+    JsonPatch,
+    /// [JSON Merge Patch][RFC 7386] documents, which mirror the structure of the state.
+    ///
+    /// Merge patches are more compact, but can't set a member to `null`: a `null` member in a
+    /// merge patch removes the member instead. Use [`PatchFormat::JsonPatch`] if the state can
+    /// contain `null` members.
+    ///
+    /// [RFC 7386]: https://tools.ietf.org/html/rfc7386
+    MergePatch,
+}
+
+/// A [`Codec`] that replicates a state as a snapshot followed by patches (requires the
+/// `json-patch` feature).
+///
+/// When decoding, the first frame is taken as the initial state, and each frame after it is
+/// applied to the state as a patch, yielding the updated state. A patch that fails to apply is
+/// reported as [`Error::Patch`] and leaves the state unchanged, so decoding can carry on with the
+/// next frame.
+///
+/// When encoding, items are successive full states: the first is sent as is, and each one after
+/// it as a patch from the one before.
+///
+/// Patches are in the [`PatchFormat::JsonPatch`] format unless configured otherwise. To let the
+/// encoder send a full snapshot every so often, so that a peer can't drift from the state for
+/// long, frames can be tagged as snapshots or patches, as in `{"snapshot": ...}` and
+/// `{"patch": ...}`; see [`Patched::snapshot_interval`]. Both peers must agree on the format and
+/// tagging. Created by [`Codec::patched`].
+#[derive(Clone, Debug)]
+pub struct Patched {
+    codec: Codec<Value, Value>,
+    format: PatchFormat,
+    tagged: bool,
+    snapshot_interval: Option<u32>,
+    state: Option<Value>,
+    sent: Option<Value>,
+    since_snapshot: u32,
+}
+
+impl Patched {
+    /// Set the format of patches, both decoded and encoded.
+    pub fn format(&mut self, format: PatchFormat) {
+        self.format = format;
+    }
+
+    /// Set whether or not frames are tagged as snapshots or patches. Disabled by default.
+    ///
+    /// A decoder with tagging enabled accepts a full snapshot at any time.
+    pub fn tagged(&mut self, tagged: bool) {
+        self.tagged = tagged;
+        if !tagged {
+            self.snapshot_interval = None;
+        }
+    }
+
+    /// Set how many patches to encode between full snapshots, or `None` to only encode the
+    /// initial snapshot, as by default.
+    ///
+    /// Setting an interval also enables [tagging][`Patched::tagged`], so the decoding peer must
+    /// enable it too.
+    pub fn snapshot_interval(&mut self, interval: Option<u32>) {
+        self.snapshot_interval = interval;
+        if interval.is_some() {
+            self.tagged = true;
+        }
+    }
+
+    /// Returns the current decoded state, if the initial snapshot has been decoded.
+    pub fn state(&self) -> Option<&Value> {
+        self.state.as_ref()
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<Value, Value> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<Value, Value> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Patched`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<Value, Value> {
+        self.codec
+    }
+}
+
+impl Codec<Value, Value> {
+    /// Wraps the codec so that it replicates a state as a snapshot followed by patches (requires
+    /// the `json-patch` feature).
+    pub fn patched(self) -> Patched {
+        Patched {
+            codec: self,
+            format: PatchFormat::JsonPatch,
+            tagged: false,
+            snapshot_interval: None,
+            state: None,
+            sent: None,
+            since_snapshot: 0,
+        }
+    }
+}
+
+impl Decoder for Patched {
+    type Item = Value;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Value>, Error> {
+        let frame = match self.codec.decode(src)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let (snapshot, frame) = if self.tagged {
+            untag(frame)?
+        } else {
+            (self.state.is_none(), frame)
+        };
+        if snapshot {
+            self.state = Some(frame.clone());
+            return Ok(Some(frame));
+        }
+        let state = match self.state {
+            Some(ref mut state) => {
+                match self.format {
+                    PatchFormat::JsonPatch => {
+                        let patch: json_patch::Patch = serde_json::from_value(frame)?;
+                        json_patch::patch(state, &patch)?;
+                    }
+                    PatchFormat::MergePatch => json_patch::merge(state, &frame),
+                }
+                state
+            }
+            None => {
+                return Err(serde_json::Error::custom("patch before the initial snapshot").into())
+            }
+        };
+        Ok(Some(state.clone()))
+    }
+}
+
+impl Encoder for Patched {
+    type Item = Value;
+    type Error = Error;
+
+    fn encode(&mut self, item: Value, dst: &mut BytesMut) -> Result<(), Error> {
+        let frame = match self.sent {
+            Some(ref sent) if Some(self.since_snapshot) != self.snapshot_interval => {
+                self.since_snapshot += 1;
+                let patch = match self.format {
+                    PatchFormat::JsonPatch => serde_json::to_value(json_patch::diff(sent, &item))?,
+                    PatchFormat::MergePatch => merge_diff(sent, &item),
+                };
+                tag(self.tagged, "patch", patch)
+            }
+            _ => {
+                self.since_snapshot = 0;
+                tag(self.tagged, "snapshot", item.clone())
+            }
+        };
+        self.codec.encode(frame, dst)?;
+        self.sent = Some(item);
+        Ok(())
+    }
+}
+
+/// Wraps `frame` in an object with the member `tag` if `tagged` is set.
+fn tag(tagged: bool, tag: &str, frame: Value) -> Value {
+    if !tagged {
+        return frame;
+    }
+    let mut map = Map::new();
+    map.insert(tag.to_owned(), frame);
+    Value::Object(map)
+}
+
+/// Unwraps a tagged frame, returning whether it is a snapshot along with its contents.
+fn untag(frame: Value) -> Result<(bool, Value), Error> {
+    if let Value::Object(mut map) = frame {
+        if map.len() == 1 {
+            if let Some(snapshot) = map.remove("snapshot") {
+                return Ok((true, snapshot));
+            }
+            if let Some(patch) = map.remove("patch") {
+                return Ok((false, patch));
+            }
+        }
+    }
+    Err(serde_json::Error::custom("expected a `snapshot` or `patch` frame").into())
+}
+
+/// Computes a merge patch that turns `from` into `to`.
+fn merge_diff(from: &Value, to: &Value) -> Value {
+    let (from, to) = match (from, to) {
+        (Value::Object(from), Value::Object(to)) => (from, to),
+        _ => return to.clone(),
+    };
+    let mut patch = Map::new();
+    for (key, old) in from {
+        match to.get(key) {
+            None => {
+                patch.insert(key.clone(), Value::Null);
+            }
+            Some(new) if new != old => {
+                patch.insert(key.clone(), merge_diff(old, new));
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, new) in to {
+        if !from.contains_key(key) {
+            patch.insert(key.clone(), new.clone());
+        }
+    }
+    Value::Object(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_diff, PatchFormat};
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Error};
+
+    #[test]
+    fn decode_patches() {
+        let mut buf = BytesMut::from(
+            &br#"{"a": 1}
+                [{"op": "add", "path": "/b", "value": [2]}]
+                [{"op": "remove", "path": "/a"}, {"op": "remove", "path": "/c"}]
+                [{"op": "remove", "path": "/a"}]"#[..],
+        );
+        let mut codec = Codec::default().patched();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"a": 1})));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(json!({"a": 1, "b": [2]}))
+        );
+        match codec.decode(&mut buf) {
+            Err(Error::Patch(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(codec.state(), Some(&json!({"a": 1, "b": [2]})));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"b": [2]})));
+    }
+
+    #[test]
+    fn decode_merge_patches() {
+        let mut buf = BytesMut::from(&br#"{"a": {"b": 1, "c": 2}} {"a": {"b": null, "d": 3}}"#[..]);
+        let mut codec = Codec::default().patched();
+        codec.format(PatchFormat::MergePatch);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(json!({"a": {"c": 2, "d": 3}}))
+        );
+    }
+
+    #[test]
+    fn merge_diffs() {
+        let from = json!({"a": {"b": 1, "c": [2]}, "d": true});
+        let to = json!({"a": {"b": 1, "c": [3]}, "e": "f"});
+        assert_eq!(
+            merge_diff(&from, &to),
+            json!({"a": {"c": [3]}, "d": null, "e": "f"})
+        );
+        assert_eq!(merge_diff(&from, &json!([1])), json!([1]));
+    }
+
+    #[test]
+    fn snapshot_interval() {
+        let mut buf = BytesMut::new();
+        let mut encoder = Codec::default().patched();
+        encoder.snapshot_interval(Some(2));
+        for n in 0..5 {
+            encoder.encode(json!({ "n": n }), &mut buf).unwrap();
+            buf.extend_from_slice(b"\n");
+        }
+        let replace = |n| json!({"patch": [{"op": "replace", "path": "/n", "value": n}]});
+        let mut encoded = Codec::<Value, ()>::default();
+        let mut frames = Vec::new();
+        while let Some(frame) = encoded.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![
+                json!({"snapshot": {"n": 0}}),
+                replace(1),
+                replace(2),
+                json!({"snapshot": {"n": 3}}),
+                replace(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_tagged() {
+        let mut buf =
+            BytesMut::from(&br#"{"patch": []} {"snapshot": 1} {"snapshot": 2} {"a": 3}"#[..]);
+        let mut codec = Codec::default().patched();
+        codec.tagged(true);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "patch before the initial snapshot"
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(1)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!(2)));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "expected a `snapshot` or `patch` frame"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let states = vec![
+            json!({"a": 1}),
+            json!({"a": 2, "b": [true]}),
+            json!({"b": [true, false]}),
+            json!([]),
+        ];
+        for &format in &[PatchFormat::JsonPatch, PatchFormat::MergePatch] {
+            for &interval in &[None, Some(1)] {
+                let mut buf = BytesMut::new();
+                let mut encoder = Codec::default().patched();
+                let mut decoder = Codec::default().patched();
+                encoder.format(format);
+                encoder.snapshot_interval(interval);
+                decoder.format(format);
+                decoder.tagged(interval.is_some());
+                for state in &states {
+                    encoder.encode(state.clone(), &mut buf).unwrap();
+                    buf.extend_from_slice(b"\n");
+                    assert_eq!(decoder.decode(&mut buf).unwrap().as_ref(), Some(state));
+                }
+            }
+        }
+    }
+}
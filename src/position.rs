@@ -0,0 +1,44 @@
+//! Tracking where decoded frames are in the input.
+
+/// Where a frame is in the input; see [`Codec::track_positions`][`::Codec::track_positions`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Position {
+    /// The number of frames before this one, counting from 0.
+    pub index: u64,
+    /// The offset of the first byte of the frame, counting from when tracking was enabled.
+    pub offset: u64,
+    /// The line the frame is on, counting from 1, when decoding with [`Lines`][`::Lines`].
+    pub line: Option<u64>,
+}
+
+/// How far into the input the codec is.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Positions {
+    frames: u64,
+    offset: u64,
+    lines: u64,
+    pub(crate) last: Option<Position>,
+}
+
+impl Positions {
+    /// Records that `consumed` bytes of the input were consumed, ending with a frame of `len`
+    /// bytes if one was split off.
+    pub(crate) fn decoded(&mut self, consumed: usize, len: Option<usize>) {
+        let end = self.offset.saturating_add(consumed as u64);
+        if let Some(len) = len {
+            self.last = Some(Position {
+                index: self.frames,
+                offset: end - len as u64,
+                line: Some(self.lines).filter(|&line| line > 0),
+            });
+            self.frames = self.frames.saturating_add(1);
+        }
+        self.offset = end;
+    }
+
+    /// Records that a line is about to be consumed.
+    pub(crate) fn line(&mut self) {
+        self.lines = self.lines.saturating_add(1);
+    }
+}
@@ -0,0 +1,86 @@
+//! Limiting how fast frames are decoded.
+
+use futures::{Async, Future};
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+
+/// A token bucket allowing `max` frames a second, in bursts of up to `max` frames.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    max: u32,
+    tokens: f64,
+    refilled: Instant,
+    delay: Option<Delay>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(max: u32) -> Self {
+        let max = max.max(1);
+        RateLimiter {
+            max,
+            tokens: f64::from(max),
+            refilled: Instant::now(),
+            delay: None,
+        }
+    }
+
+    pub(crate) fn max(&self) -> u32 {
+        self.max
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * f64::from(self.max)).min(f64::from(self.max));
+        self.refilled = now;
+    }
+
+    /// Takes a token for a decoded frame, returning `false` if there wasn't one.
+    pub(crate) fn take(&mut self) -> bool {
+        self.refill(Instant::now());
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Returns `Async::NotReady` until a token is available.
+    pub(crate) fn poll_ready(&mut self) -> Async<()> {
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Async::NotReady,
+                    Ok(Async::Ready(())) => {}
+                    // A timer error means there is no timer to wait for, so don't.
+                    Err(_) => {
+                        self.delay = None;
+                        return Async::Ready(());
+                    }
+                }
+            }
+            self.delay = None;
+            let now = Instant::now();
+            self.refill(now);
+            if self.tokens >= 1.0 {
+                return Async::Ready(());
+            }
+            let wait = (1.0 - self.tokens) / f64::from(self.max);
+            self.delay = Some(Delay::new(now + Duration::from_secs_f64(wait)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+
+    #[test]
+    fn burst() {
+        let mut limiter = RateLimiter::new(3);
+        assert_eq!(limiter.max(), 3);
+        assert!(limiter.take());
+        assert!(limiter.take());
+        assert!(limiter.take());
+        assert!(!limiter.take());
+    }
+}
@@ -0,0 +1,95 @@
+//! Decoding values along with the raw bytes of their frames.
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// A [`Codec`] that decodes each value along with the exact bytes of its frame, as they were
+/// received.
+///
+/// This is useful when the original bytes matter, such as for audit logs or verifying signatures.
+/// Leading whitespace is not part of a frame. Encoding is unaffected.
+///
+/// Created by [`Codec::with_raw`].
+#[derive(Clone, Debug)]
+pub struct WithRaw<D, E> {
+    codec: Codec<D, E>,
+}
+
+impl<D, E> WithRaw<D, E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `WithRaw`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec so that each decoded value comes with the raw bytes of its frame.
+    pub fn with_raw(self) -> WithRaw<D, E> {
+        WithRaw { codec: self }
+    }
+}
+
+impl<D, E> Decoder for WithRaw<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = (D, Bytes);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<(D, Bytes)>, Error> {
+        self.codec.decode_with(src, |codec, src| {
+            while let Some(frame) = codec.next_frame(src)? {
+                if let Some(value) = codec.decode_value(frame.clone())? {
+                    return Ok(Some((value, frame)));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Encoder for WithRaw<D, E>
+where
+    E: Serialize,
+{
+    type Item = E;
+    type Error = Error;
+
+    fn encode(&mut self, item: E, dst: &mut BytesMut) -> Result<(), Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::Decoder;
+    use Codec;
+
+    #[test]
+    fn decode_with_raw() {
+        let mut buf = BytesMut::from(&b" {\"a\":  1}\n[ 2 ] nu"[..]);
+        let mut codec = Codec::<Value, ()>::default().with_raw();
+        let (value, raw) = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(value, json!({"a": 1}));
+        assert_eq!(&raw[..], b"{\"a\":  1}");
+        let (value, raw) = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(value, json!([2]));
+        assert_eq!(&raw[..], b"[ 2 ]");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf, " nu");
+    }
+}
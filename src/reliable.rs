@@ -0,0 +1,368 @@
+//! At-least-once delivery on top of a codec, with sequence numbers and acknowledgements.
+
+use bytes::{Bytes, BytesMut};
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Map, Value};
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// An item to encode with [`Reliable`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outbound<E> {
+    /// A message, which is retained until the peer acknowledges it.
+    Message(E),
+    /// A standalone acknowledgement of everything received so far.
+    ///
+    /// Messages carry an acknowledgement too, so this is only needed when there is nothing else
+    /// to send; see [`Reliable::ack_pending`].
+    Ack,
+}
+
+/// A [`Codec`] that provides at-least-once delivery of messages across connections.
+///
+/// Each outbound message is sent in an envelope with a sequence number, as in
+/// `{"seq": 7, "ack": 3, "msg": ...}`, and retained until the peer acknowledges it. Every
+/// envelope acknowledges all messages received so far, and an acknowledgement can also be sent on
+/// its own, as in `{"ack": 3}`; see [`Outbound::Ack`]. Acknowledgements are handled by the codec
+/// rather than decoded as items, as are messages that were already received.
+///
+/// When a connection drops, move the `Reliable` to the new connection (for instance with
+/// [`Framed::from_parts`][`tokio_codec::Framed::from_parts`]) and call [`Reliable::reconnected`],
+/// so that unacknowledged messages are sent again. Both peers must use `Reliable`.
+///
+/// A client that reconnects to a server with many clients identifies itself with a
+/// [session id][`Reliable::session_id`]. The client then starts each connection with a hello
+/// frame, as in `{"session": "abc", "ack": 3}`, which tells the server which session to resume
+/// and which messages to replay. The server can read the hello with a plain codec, look up the
+/// session's `Reliable` and pass the hello to [`Reliable::resume`]. Unacknowledged messages are
+/// retained in a buffer that can be [bounded][`Reliable::replay_buffer`].
+///
+/// Created by [`Codec::reliable`].
+#[derive(Clone, Debug)]
+pub struct Reliable<D, E> {
+    codec: Codec<Value, Value>,
+    sent: u64,
+    received: u64,
+    unacked: VecDeque<(u64, Bytes)>,
+    ack_pending: bool,
+    retransmit: bool,
+    session: Option<String>,
+    hello_pending: bool,
+    replay_buffer: Option<usize>,
+    dropped: u64,
+    _priv: PhantomData<fn(E) -> D>,
+}
+
+impl<D, E> Reliable<D, E> {
+    /// Returns how many sent messages the peer hasn't acknowledged yet.
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// Returns whether messages have been received since the last acknowledgement was sent.
+    pub fn ack_pending(&self) -> bool {
+        self.ack_pending
+    }
+
+    /// Prepares for a new connection to the peer: unacknowledged messages are sent again before
+    /// the next item is encoded, preceded by a hello if there is a session id.
+    pub fn reconnected(&mut self) {
+        self.retransmit = true;
+        self.hello_pending = self.session.is_some();
+        self.ack_pending = self.received > 0;
+    }
+
+    /// Set the session id that identifies this end of the connection to the peer across
+    /// reconnects. The next item encoded is preceded by a hello.
+    pub fn session_id(&mut self, session: Option<String>) {
+        self.hello_pending = session.is_some();
+        self.session = session;
+    }
+
+    /// Returns the session id, which is either set with [`Reliable::session_id`] or taken from the
+    /// peer's hello.
+    pub fn session(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    /// Resumes the session on a new connection after receiving the peer's `hello` frame, so that
+    /// the messages it missed are sent again before the next item is encoded.
+    ///
+    /// Fails if the hello is malformed or is for a different session. Hellos decoded by the
+    /// `Reliable` itself are handled the same way.
+    pub fn resume(&mut self, hello: &Value) -> Result<(), Error> {
+        let (session, ack) = match (hello.get("session"), hello.get("ack")) {
+            (Some(Value::String(session)), Some(ack)) => (session, ack.as_u64()),
+            _ => return Err(serde_json::Error::custom("expected a hello").into()),
+        };
+        match self.session {
+            Some(ref ours) if ours != session => {
+                let msg = format!("hello for a different session `{}`", session);
+                return Err(serde_json::Error::custom(msg).into());
+            }
+            Some(_) => {}
+            None => self.session = Some(session.clone()),
+        }
+        self.acked(ack.unwrap_or(0));
+        self.retransmit = true;
+        self.ack_pending = self.received > 0;
+        Ok(())
+    }
+
+    /// Set the maximum number of unacknowledged messages to retain for sending again, or `None` to
+    /// retain them all, as by default. When the buffer is full, the oldest message is dropped
+    /// and counted in [`Reliable::dropped`].
+    pub fn replay_buffer(&mut self, max: Option<usize>) {
+        self.replay_buffer = max;
+        self.trim();
+    }
+
+    /// Returns how many unacknowledged messages were dropped from the
+    /// [replay buffer][`Reliable::replay_buffer`], and so won't be sent again.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn trim(&mut self) {
+        if let Some(max) = self.replay_buffer {
+            while self.unacked.len() > max {
+                self.unacked.pop_front();
+                self.dropped = self.dropped.saturating_add(1);
+            }
+        }
+    }
+
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<Value, Value> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<Value, Value> {
+        &mut self.codec
+    }
+
+    /// Consumes the `Reliable`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<Value, Value> {
+        self.codec
+    }
+
+    /// Records an acknowledgement from the peer.
+    fn acked(&mut self, ack: u64) {
+        while self.unacked.front().is_some_and(|&(seq, _)| seq <= ack) {
+            self.unacked.pop_front();
+        }
+    }
+
+    /// Writes an envelope, preceded by any messages that need to be sent again, and returns where
+    /// in `dst` the envelope starts.
+    fn write(&mut self, envelope: Map<String, Value>, dst: &mut BytesMut) -> Result<usize, Error> {
+        if self.hello_pending {
+            let mut hello = Map::new();
+            hello.insert("session".to_owned(), self.session.clone().into());
+            hello.insert("ack".to_owned(), self.received.into());
+            self.codec.encode(Value::Object(hello), dst)?;
+            self.hello_pending = false;
+        }
+        if self.retransmit {
+            for (_, frame) in &self.unacked {
+                dst.extend_from_slice(frame);
+            }
+            self.retransmit = false;
+        }
+        let start = dst.len();
+        self.codec.encode(Value::Object(envelope), dst)?;
+        self.ack_pending = false;
+        Ok(start)
+    }
+}
+
+impl Codec<Value, Value> {
+    /// Wraps the codec so that it provides at-least-once delivery of messages.
+    pub fn reliable<D, E>(self) -> Reliable<D, E> {
+        Reliable {
+            codec: self,
+            sent: 0,
+            received: 0,
+            unacked: VecDeque::new(),
+            ack_pending: false,
+            retransmit: false,
+            session: None,
+            hello_pending: false,
+            replay_buffer: None,
+            dropped: 0,
+            _priv: PhantomData,
+        }
+    }
+}
+
+impl<D, E> Decoder for Reliable<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, Error> {
+        while let Some(frame) = self.codec.decode(src)? {
+            if frame.get("session").is_some() {
+                self.resume(&frame)?;
+                continue;
+            }
+            let mut envelope = match frame {
+                Value::Object(envelope) => envelope,
+                _ => return Err(serde_json::Error::custom("expected an envelope").into()),
+            };
+            if let Some(ack) = envelope.get("ack").and_then(Value::as_u64) {
+                self.acked(ack);
+            }
+            let seq = match envelope.get("seq").and_then(Value::as_u64) {
+                Some(seq) => seq,
+                None => continue,
+            };
+            if seq <= self.received {
+                continue;
+            }
+            let msg = envelope.remove("msg").unwrap_or(Value::Null);
+            // A message that fails to deserialize isn't acknowledged as received.
+            let msg = D::deserialize(msg)?;
+            self.received = seq;
+            self.ack_pending = true;
+            return Ok(Some(msg));
+        }
+        Ok(None)
+    }
+}
+
+impl<D, E> Encoder for Reliable<D, E>
+where
+    E: Serialize,
+{
+    type Item = Outbound<E>;
+    type Error = Error;
+
+    fn encode(&mut self, item: Outbound<E>, dst: &mut BytesMut) -> Result<(), Error> {
+        let mut envelope = Map::new();
+        let msg = match item {
+            Outbound::Message(msg) => serde_json::to_value(msg)?,
+            Outbound::Ack => {
+                envelope.insert("ack".to_owned(), self.received.into());
+                return self.write(envelope, dst).map(|_| ());
+            }
+        };
+        let seq = self.sent + 1;
+        envelope.insert("seq".to_owned(), seq.into());
+        envelope.insert("ack".to_owned(), self.received.into());
+        envelope.insert("msg".to_owned(), msg);
+        let start = self.write(envelope, dst)?;
+        self.sent = seq;
+        self.unacked.push_back((seq, Bytes::from(&dst[start..])));
+        self.trim();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outbound, Reliable};
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use Codec;
+
+    fn reliable() -> Reliable<u32, u32> {
+        Codec::default().reliable()
+    }
+
+    #[test]
+    fn acks() {
+        let (mut a, mut b) = (reliable(), reliable());
+        let mut wire = BytesMut::new();
+        a.encode(Outbound::Message(1), &mut wire).unwrap();
+        a.encode(Outbound::Message(2), &mut wire).unwrap();
+        assert_eq!(a.unacked(), 2);
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(1));
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(2));
+        assert!(b.ack_pending());
+        b.encode(Outbound::Ack, &mut wire).unwrap();
+        assert!(!b.ack_pending());
+        assert_eq!(a.decode(&mut wire).unwrap(), None);
+        assert_eq!(a.unacked(), 0);
+    }
+
+    #[test]
+    fn retransmit() {
+        let (mut a, mut b) = (reliable(), reliable());
+        let mut wire = BytesMut::new();
+        a.encode(Outbound::Message(1), &mut wire).unwrap();
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(1));
+        a.encode(Outbound::Message(2), &mut wire).unwrap();
+        // The connection drops with the second message in flight.
+        wire.clear();
+        a.reconnected();
+        b.reconnected();
+        b.encode(Outbound::Message(10), &mut wire).unwrap();
+        assert_eq!(a.decode(&mut wire).unwrap(), Some(10));
+        assert_eq!(a.unacked(), 1);
+        a.encode(Outbound::Message(3), &mut wire).unwrap();
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(2));
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(3));
+        assert_eq!(b.decode(&mut wire).unwrap(), None);
+    }
+
+    #[test]
+    fn resume() {
+        let (mut client, mut server, mut other) = (reliable(), reliable(), reliable());
+        client.session_id(Some("abc".to_owned()));
+        other.session_id(Some("xyz".to_owned()));
+        let mut wire = BytesMut::new();
+        client.encode(Outbound::Message(1), &mut wire).unwrap();
+        assert_eq!(server.decode(&mut wire).unwrap(), Some(1));
+        assert_eq!(server.session(), Some("abc"));
+        server.replay_buffer(Some(2));
+        for n in 2..6 {
+            server.encode(Outbound::Message(n), &mut wire).unwrap();
+        }
+        assert_eq!(server.dropped(), 2);
+        assert_eq!(client.decode(&mut wire).unwrap(), Some(2));
+        wire.clear();
+
+        client.reconnected();
+        client.encode(Outbound::Ack, &mut wire).unwrap();
+        let mut hello = Codec::<Value, ()>::default();
+        let hello = hello.decode(&mut wire.clone()).unwrap().unwrap();
+        assert!(other.resume(&hello).is_err());
+        assert_eq!(server.decode(&mut wire).unwrap(), None);
+        server.encode(Outbound::Ack, &mut wire).unwrap();
+        // The third message was dropped from the replay buffer.
+        assert_eq!(client.decode(&mut wire).unwrap(), Some(4));
+        assert_eq!(client.decode(&mut wire).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn duplicates() {
+        let (mut a, mut b) = (reliable(), reliable());
+        let mut wire = BytesMut::new();
+        a.encode(Outbound::Message(1), &mut wire).unwrap();
+        let copy = wire.clone();
+        assert_eq!(b.decode(&mut wire).unwrap(), Some(1));
+        wire.extend_from_slice(&copy);
+        assert_eq!(b.decode(&mut wire).unwrap(), None);
+        assert!(wire.is_empty());
+    }
+
+    #[test]
+    fn invalid_message() {
+        let mut a: Reliable<u32, Value> = Codec::default().reliable();
+        let mut b = reliable();
+        let mut wire = BytesMut::new();
+        a.encode(Outbound::Message(json!("x")), &mut wire).unwrap();
+        assert!(b.decode(&mut wire).is_err());
+        assert!(!b.ack_pending());
+        b.encode(Outbound::Ack, &mut wire).unwrap();
+        assert_eq!(wire, &b"{\"ack\":0}"[..]);
+    }
+}
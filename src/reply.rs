@@ -0,0 +1,259 @@
+//! Sending errors to the peer as error frames.
+
+use futures::{AsyncSink, Poll, Sink, StartSend, Stream};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::{Map, Value};
+use std::marker::PhantomData;
+use Error;
+
+/// An error sent to the peer, as in
+/// `{"code": "not_found", "message": "no such user", "details": {"id": 3}}`.
+///
+/// `details` is left out when it is `None`. An [`Error`] converts into an `ErrorFrame` with its
+/// [kind][`Error::kind`] as the code, so that a peer that sent a malformed frame can be told so.
+/// The message leaves out the codec's [peer label][`::Codec::peer_label`] and any
+/// [excerpt of the input][`::Codec::error_context`], which are meant for the log.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct ErrorFrame {
+    /// A machine-readable code for the error, such as `not_found`.
+    pub code: String,
+    /// A human-readable description of the error.
+    pub message: String,
+    /// Any further information about the error.
+    pub details: Option<Value>,
+}
+
+impl ErrorFrame {
+    /// Creates an error frame without details.
+    pub fn new<C, M>(code: C, message: M) -> Self
+    where
+        C: Into<String>,
+        M: Into<String>,
+    {
+        ErrorFrame {
+            code: code.into(),
+            message: message.into(),
+            details: None,
+        }
+    }
+}
+
+impl From<Error> for ErrorFrame {
+    fn from(err: Error) -> Self {
+        let mut err = unlabeled(err);
+        if let Error::Decode(ref mut e) = err {
+            e.context = false;
+        }
+        ErrorFrame::new(err.kind().as_str(), err.to_string())
+    }
+}
+
+/// Takes the error out of its peer label and captured frame, if any.
+fn unlabeled(err: Error) -> Error {
+    match err {
+        Error::WithFrame { error, .. } | Error::Labeled { error, .. } => unlabeled(*error),
+        e => e,
+    }
+}
+
+impl From<ErrorFrame> for Value {
+    fn from(frame: ErrorFrame) -> Self {
+        let mut map = Map::new();
+        map.insert("code".to_owned(), frame.code.into());
+        map.insert("message".to_owned(), frame.message.into());
+        if let Some(details) = frame.details {
+            map.insert("details".to_owned(), details);
+        }
+        Value::Object(map)
+    }
+}
+
+impl Serialize for ErrorFrame {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.details.is_some() { 3 } else { 2 };
+        let mut state = serializer.serialize_struct("ErrorFrame", len)?;
+        state.serialize_field("code", &self.code)?;
+        state.serialize_field("message", &self.message)?;
+        if let Some(ref details) = self.details {
+            state.serialize_field("details", details)?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorFrame {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut map = Map::deserialize(deserializer)?;
+        let mut field = |name: &'static str| match map.remove(name) {
+            Some(Value::String(s)) => Ok(s),
+            Some(_) => Err(de::Error::custom(format_args!(
+                "invalid type for `{}`, expected a string",
+                name
+            ))),
+            None => Err(de::Error::missing_field(name)),
+        };
+        Ok(ErrorFrame {
+            code: field("code")?,
+            message: field("message")?,
+            details: map.remove("details").filter(|details| !details.is_null()),
+        })
+    }
+}
+
+/// A sink that sends the errors of a request handler to the peer as [`ErrorFrame`]s.
+///
+/// Each item is a `Result`: an `Ok` item is sent as it is, and an `Err` is converted into an
+/// `ErrorFrame` and then into the underlying sink's item type, which for a [`Value`] gives the
+/// frame's JSON object. A message type of one's own can implement `From<ErrorFrame>` to wrap
+/// the frame in the protocol's envelope. The underlying stream, if any, is passed through.
+///
+/// Created by [`FramedJson::with_error_replies`][`::FramedJson::with_error_replies`], or by
+/// [`ErrorReplies::new`] for other sinks.
+#[derive(Debug)]
+pub struct ErrorReplies<S, H> {
+    sink: S,
+    _handler: PhantomData<fn(H)>,
+}
+
+impl<S, H> ErrorReplies<S, H> {
+    /// Creates an `ErrorReplies` sending items and errors to `sink`.
+    pub fn new(sink: S) -> Self {
+        ErrorReplies {
+            sink,
+            _handler: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a mutable reference to the underlying sink.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Consumes the `ErrorReplies`, returning the underlying sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S, H> Sink for ErrorReplies<S, H>
+where
+    S: Sink,
+    S::SinkItem: From<ErrorFrame>,
+    H: Into<ErrorFrame>,
+{
+    type SinkItem = Result<S::SinkItem, H>;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, S::SinkError> {
+        let item = item.unwrap_or_else(|e| S::SinkItem::from(e.into()));
+        // The error has been converted already, so it is handed back as an item.
+        Ok(match self.sink.start_send(item)? {
+            AsyncSink::Ready => AsyncSink::Ready,
+            AsyncSink::NotReady(item) => AsyncSink::NotReady(Ok(item)),
+        })
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.sink.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.sink.close()
+    }
+}
+
+impl<S, H> Stream for ErrorReplies<S, H>
+where
+    S: Stream,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        self.sink.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorFrame, ErrorReplies};
+    use bytes::BytesMut;
+    use futures::sync::mpsc;
+    use futures::{Future, Sink, Stream};
+    use serde_json::{self, Value};
+    use std::io::Cursor;
+    use tokio_codec::Decoder;
+    use {Codec, Error, FramedJson};
+
+    #[test]
+    fn error_frame() {
+        let mut frame = ErrorFrame::new("not_found", "no such user");
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            r#"{"code":"not_found","message":"no such user"}"#
+        );
+        frame.details = Some(json!({"id": 3}));
+        let value = json!({"code": "not_found", "message": "no such user", "details": {"id": 3}});
+        assert_eq!(serde_json::to_value(&frame).unwrap(), value);
+        assert_eq!(Value::from(frame.clone()), value);
+        assert_eq!(serde_json::from_value::<ErrorFrame>(value).unwrap(), frame);
+        assert!(serde_json::from_value::<ErrorFrame>(json!({"code": 1})).is_err());
+
+        let frame = ErrorFrame::from(Error::FrameTooLarge {
+            max: 8,
+            resynced: true,
+        });
+        assert_eq!(
+            frame,
+            ErrorFrame::new("too_large", "frame larger than 8 bytes")
+        );
+
+        let mut codec: Codec<Value, ()> = Codec::default();
+        codec.peer_label(Some("10.0.0.7:4000"));
+        codec.error_context(true);
+        let mut buf = BytesMut::from(&b"[\"secret\", ?]"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert!(err.to_string().contains("10.0.0.7:4000") && err.to_string().contains("secret"));
+        let frame = ErrorFrame::from(err);
+        assert_eq!(frame.code, "syntax");
+        assert!(frame
+            .message
+            .starts_with("expected value at line 1 column 12"));
+        assert!(!frame.message.contains("10.0.0.7:4000") && !frame.message.contains("secret"));
+    }
+
+    #[test]
+    fn error_replies() {
+        let (tx, rx) = mpsc::unbounded();
+        let replies = ErrorReplies::<_, ErrorFrame>::new(tx);
+        let replies = replies.send(Ok(json!([1]))).wait().unwrap();
+        replies
+            .send(Err(ErrorFrame::new("busy", "try again")))
+            .wait()
+            .unwrap();
+        let sent: Vec<_> = rx.take(2).collect().wait().unwrap();
+        let expected = vec![json!([1]), json!({"code": "busy", "message": "try again"})];
+        assert_eq!(sent, expected);
+
+        let codec: Codec<(), Value> = Codec::default();
+        let replies = FramedJson::new(Cursor::new(Vec::new()), codec)
+            .with_error_replies::<Error>()
+            .send(Err(Error::FrameTooLarge {
+                max: 8,
+                resynced: true,
+            }))
+            .wait()
+            .unwrap();
+        assert_eq!(
+            replies.get_ref().get_ref().get_ref(),
+            br#"{"code":"too_large","message":"frame larger than 8 bytes"}"#
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Sizing buffers to fit the frames being received and sent.
+
+use bytes::BytesMut;
+
+/// Read-buffer sizing state; see [`Codec::adaptive_buffer`][`::Codec::adaptive_buffer`].
+#[derive(Clone, Debug)]
+pub(crate) struct Reserve {
+    min: usize,
+    max: usize,
+    average: Average,
+}
+
+impl Reserve {
+    pub(crate) fn new(min: usize, max: usize) -> Self {
+        Reserve {
+            min,
+            max,
+            average: Average::new(min),
+        }
+    }
+
+    /// Records the size of a decoded frame, including the whitespace before it.
+    pub(crate) fn record(&mut self, size: usize) {
+        self.average.record(size);
+    }
+
+    /// The buffer capacity frames are expected to need.
+    fn target(&self) -> usize {
+        self.average.get().clamp(self.min, self.max)
+    }
+
+    /// Resizes `src` after decoding: an empty buffer that is much bigger than frames need is
+    /// replaced by a smaller one, and a buffer holding part of a frame gets room for the rest of a
+    /// typical frame at once instead of growing bit by bit.
+    pub(crate) fn adjust(&self, src: &mut BytesMut) {
+        let target = self.target();
+        if src.is_empty() {
+            if src.capacity() > target.saturating_mul(2) {
+                *src = BytesMut::with_capacity(target);
+            }
+        } else if src.len() < target {
+            src.reserve(target - src.len());
+        }
+    }
+}
+
+/// An exponentially weighted moving average of frame sizes.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Average(usize);
+
+impl Average {
+    pub(crate) const fn new(initial: usize) -> Self {
+        Average(initial)
+    }
+
+    pub(crate) fn record(&mut self, size: usize) {
+        // Weight each new frame by 1/8, rounding the step up so that the average reaches the size
+        // of frames that keep the same size, rather than stopping up to 7 bytes short.
+        self.0 = if size >= self.0 {
+            self.0 + (size - self.0).div_ceil(8)
+        } else {
+            self.0 - (self.0 - size).div_ceil(8)
+        };
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Average, Reserve};
+    use bytes::BytesMut;
+
+    #[test]
+    fn adjust() {
+        let mut reserve = Reserve::new(64, 4096);
+        for _ in 0..64 {
+            reserve.record(1000);
+        }
+        assert!(reserve.target() > 900 && reserve.target() <= 1000);
+        let mut buf = BytesMut::from(&b"[1"[..]);
+        reserve.adjust(&mut buf);
+        assert!(buf.capacity() >= reserve.target());
+
+        for _ in 0..64 {
+            reserve.record(10);
+        }
+        assert_eq!(reserve.target(), 64);
+        let mut buf = BytesMut::with_capacity(1 << 20);
+        reserve.adjust(&mut buf);
+        assert!(buf.capacity() < 1 << 10);
+    }
+
+    #[test]
+    fn average() {
+        let mut average = Average::new(0);
+        for _ in 0..8 {
+            average.record(5);
+        }
+        assert_eq!(average.get(), 5);
+        for _ in 0..64 {
+            average.record(1000);
+        }
+        assert_eq!(average.get(), 1000);
+        for _ in 0..64 {
+            average.record(10);
+        }
+        assert_eq!(average.get(), 10);
+    }
+}
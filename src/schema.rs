@@ -0,0 +1,51 @@
+//! Validating frames against a JSON Schema (requires the `jsonschema` feature).
+
+use jsonschema::Validator;
+use serde_json::Value;
+use Error;
+
+/// Validates `frame` against the schema compiled into `validator`, reporting the first error.
+pub(crate) fn validate(validator: &Validator, frame: &Value) -> Result<(), Error> {
+    validator
+        .validate(frame)
+        .map_err(|e| Error::SchemaViolation {
+            path: e.instance_path.to_string(),
+            message: e.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use jsonschema;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Error};
+
+    #[test]
+    fn schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"n": {"type": "integer"}},
+            "required": ["n"]
+        });
+        let mut codec: Codec<Value, Value> = Codec::default();
+        codec.schema(Some(jsonschema::validator_for(&schema).unwrap()));
+        let mut buf = BytesMut::from(&b"{\"n\": 1} {\"n\": \"a\"} [1]"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(json!({"n": 1})));
+        match codec.decode(&mut buf) {
+            Err(Error::SchemaViolation { path, .. }) => assert_eq!(path, "/n"),
+            res => panic!("unexpected {:?}", res),
+        }
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "[1] is not of type \"object\""
+        );
+
+        codec.encode(json!([1]), &mut buf).unwrap();
+        codec.validate_encoded(true);
+        assert!(codec.encode(json!([2]), &mut buf).is_err());
+        codec.encode(json!({"n": 3}), &mut buf).unwrap();
+        assert_eq!(buf, &b"[1]{\"n\":3}"[..]);
+    }
+}
@@ -0,0 +1,384 @@
+//! Decoding and encoding JSON carried by Server-Sent Events.
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::mem;
+use tokio_codec::{Decoder, Encoder};
+use {Codec, Error};
+
+/// An event of a Server-Sent Events stream, as decoded and encoded by [`ServerSentEvents`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SseEvent<T> {
+    /// The event's type, or `None` for the default type, `message`.
+    pub event: Option<String>,
+    /// The last event ID. When decoding, this carries over from earlier events, as the
+    /// `Last-Event-ID` an `EventSource` reconnects with does.
+    pub id: Option<String>,
+    /// The event's data.
+    pub data: T,
+}
+
+impl<T> SseEvent<T> {
+    /// Creates an event of the default type, without an ID.
+    pub fn new(data: T) -> Self {
+        SseEvent {
+            event: None,
+            id: None,
+            data,
+        }
+    }
+}
+
+/// A [`Codec`] for [Server-Sent Events] (`text/event-stream`) whose data is JSON.
+///
+/// The data of each event, joined across its `data` lines, is decoded as a frame, so options
+/// such as limits and subscriptions apply to it. Comments, `retry` fields and events without
+/// data are skipped, and an event left incomplete at the end of the stream is discarded. An event
+/// with more data than the [maximum frame size][`Codec::max_frame_size`] fails as soon as that is
+/// known, and the rest of it is discarded as it arrives rather than buffered. Each encoded event
+/// is written with one `data` line per line of the encoded value.
+///
+/// Created by [`Codec::server_sent_events`].
+///
+/// [Server-Sent Events]: https://html.spec.whatwg.org/multipage/server-sent-events.html
+#[derive(Clone, Debug)]
+pub struct ServerSentEvents<D, E> {
+    codec: Codec<D, E>,
+    parser: Parser,
+}
+
+/// The fields of the event being received.
+#[derive(Clone, Debug, Default)]
+struct Parser {
+    event: Option<String>,
+    id: Option<String>,
+    data: Vec<u8>,
+    /// How many bytes were consumed since the last event.
+    consumed: usize,
+    /// How much of the input was scanned for the end of the current line.
+    scanned: usize,
+    /// Whether the rest of an event that was too large is being discarded.
+    discarding: bool,
+    /// Whether the start of the current line was discarded.
+    partial_line: bool,
+}
+
+impl<D, E> ServerSentEvents<D, E> {
+    /// Returns a reference to the underlying codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the underlying codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `ServerSentEvents`, returning the underlying codec.
+    pub fn into_inner(self) -> Codec<D, E> {
+        self.codec
+    }
+}
+
+impl<D, E> Codec<D, E> {
+    /// Wraps the codec to decode and encode Server-Sent Events carrying JSON data.
+    pub fn server_sent_events(self) -> ServerSentEvents<D, E> {
+        ServerSentEvents {
+            codec: self,
+            parser: Parser::default(),
+        }
+    }
+}
+
+impl Parser {
+    /// Consumes the lines at the start of `src` up to the end of the next event with data,
+    /// returning the event with its data unparsed. An event with more than `max_frame_size`
+    /// bytes of data fails, and the rest of it is discarded as it arrives.
+    fn next_event(
+        &mut self,
+        src: &mut BytesMut,
+        eof: bool,
+        max_frame_size: Option<usize>,
+    ) -> Result<Option<SseEvent<Bytes>>, Error> {
+        loop {
+            let end = match src[self.scanned..]
+                .iter()
+                .position(|&b| b == b'\r' || b == b'\n')
+            {
+                Some(end) => self.scanned + end,
+                None => {
+                    self.scanned = src.len();
+                    // Only a `data` line adds to the data, by about its length past the field
+                    // name; a complete line is checked exactly.
+                    let pending = if src.starts_with(b"data:") {
+                        self.data.len() + src.len().saturating_sub(b"data: ".len())
+                    } else {
+                        src.len()
+                    };
+                    match max_frame_size {
+                        Some(max) if !self.discarding && pending > max => {
+                            self.discard_line(src);
+                            return Err(self.too_large(max));
+                        }
+                        _ if self.discarding => self.discard_line(src),
+                        _ => {}
+                    }
+                    return Ok(None);
+                }
+            };
+            if src[end] == b'\r' && end + 1 == src.len() && !eof {
+                // The line feed of a CRLF may be yet to come.
+                self.scanned = end;
+                return Ok(None);
+            }
+            let terminator = if src[end..].starts_with(b"\r\n") {
+                2
+            } else {
+                1
+            };
+            let line = src.split_to(end).freeze();
+            src.advance(terminator);
+            self.consumed += end + terminator;
+            self.scanned = 0;
+            let partial_line = mem::take(&mut self.partial_line);
+            if line.is_empty() && !partial_line {
+                if mem::take(&mut self.discarding) {
+                    continue;
+                }
+                let event = self.event.take();
+                if self.data.is_empty() {
+                    continue;
+                }
+                self.data.pop();
+                let data = Bytes::from(mem::take(&mut self.data));
+                return Ok(Some(SseEvent {
+                    event,
+                    id: self.id.clone(),
+                    data,
+                }));
+            }
+            if self.discarding {
+                continue;
+            }
+            let (field, value) = match line.iter().position(|&b| b == b':') {
+                Some(0) => continue,
+                Some(colon) => {
+                    let value = &line[colon + 1..];
+                    (&line[..colon], value.strip_prefix(b" ").unwrap_or(value))
+                }
+                None => (&line[..], &b""[..]),
+            };
+            match field {
+                b"data" => {
+                    self.data.extend_from_slice(value);
+                    if let Some(max) = max_frame_size.filter(|&max| self.data.len() > max) {
+                        return Err(self.too_large(max));
+                    }
+                    self.data.push(b'\n');
+                }
+                b"event" => self.event = Some(String::from_utf8_lossy(value).into_owned()),
+                b"id" if !value.contains(&0) => {
+                    self.id = Some(String::from_utf8_lossy(value).into_owned())
+                        .filter(|id| !id.is_empty());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Discards the rest of the event being received, returning the error for it.
+    fn too_large(&mut self, max: usize) -> Error {
+        self.event = None;
+        self.data.clear();
+        self.discarding = true;
+        Error::FrameTooLarge {
+            max,
+            resynced: true,
+        }
+    }
+
+    /// Discards the buffered start of a line.
+    fn discard_line(&mut self, src: &mut BytesMut) {
+        self.consumed += src.len();
+        self.scanned = 0;
+        self.partial_line |= !src.is_empty();
+        src.clear();
+    }
+}
+
+impl<D, E> ServerSentEvents<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    fn decode_events(
+        &mut self,
+        src: &mut BytesMut,
+        eof: bool,
+    ) -> Result<Option<SseEvent<D>>, Error> {
+        let parser = &mut self.parser;
+        self.codec.decode_with(src, |codec, src| {
+            let max_frame_size = codec.max_frame_size;
+            loop {
+                let event = match parser.next_event(src, eof, max_frame_size) {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+                    Err(e) => {
+                        codec.split_off(mem::take(&mut parser.consumed), None);
+                        return Err(e);
+                    }
+                };
+                codec.split_off(mem::take(&mut parser.consumed), Some(event.data.len()));
+                if let Some(data) = codec.decode_value(event.data)? {
+                    return Ok(Some(SseEvent {
+                        event: event.event,
+                        id: event.id,
+                        data,
+                    }));
+                }
+            }
+            if eof {
+                // An incomplete event is discarded, as by an `EventSource`.
+                let consumed = mem::take(&mut parser.consumed) + src.len();
+                codec.split_off(consumed, None);
+                src.clear();
+                *parser = Parser {
+                    id: parser.id.take(),
+                    ..Parser::default()
+                };
+            }
+            Ok(None)
+        })
+    }
+}
+
+impl<D, E> Decoder for ServerSentEvents<D, E>
+where
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = SseEvent<D>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<SseEvent<D>>, Error> {
+        self.decode_events(src, false)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<SseEvent<D>>, Error> {
+        self.decode_events(src, true)
+    }
+}
+
+impl<D, E> Encoder for ServerSentEvents<D, E>
+where
+    E: Serialize,
+{
+    type Item = SseEvent<E>;
+    type Error = Error;
+
+    fn encode(&mut self, item: SseEvent<E>, dst: &mut BytesMut) -> Result<(), Error> {
+        for (name, value) in &[("event", &item.event), ("id", &item.id)] {
+            if let Some(ref value) = **value {
+                if value.contains(['\r', '\n']) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "event fields can't contain line breaks",
+                    )
+                    .into());
+                }
+                dst.extend_from_slice(format!("{}: {}\n", name, value).as_bytes());
+            }
+        }
+        let mut data = BytesMut::new();
+        self.codec.encode(item.data, &mut data)?;
+        for line in data.split(|&b| b == b'\n') {
+            dst.extend_from_slice(b"data: ");
+            dst.extend_from_slice(line);
+            dst.extend_from_slice(b"\n");
+        }
+        dst.extend_from_slice(b"\n");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SseEvent;
+    use bytes::BytesMut;
+    use serde_json::Value;
+    use tokio_codec::{Decoder, Encoder};
+    use {Codec, Error};
+
+    #[test]
+    fn server_sent_events() {
+        let input = b": hello\r\nretry: 10\r\n\r\n\
+            event: update\r\nid: 1\r\ndata: {\"a\":\r\ndata:1}\r\n\r\n\
+            data: [2]\r\r\nid\ndata: 3\n";
+        let mut codec = Codec::<Value, Value>::default().server_sent_events();
+        let mut buf = BytesMut::new();
+        let mut events = Vec::new();
+        // Feed the input a byte at a time to check that decoding resumes correctly.
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            while let Some(event) = codec.decode(&mut buf).unwrap() {
+                events.push(event);
+            }
+        }
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("update".to_owned()),
+                    id: Some("1".to_owned()),
+                    data: json!({"a": 1})
+                },
+                SseEvent {
+                    event: None,
+                    id: Some("1".to_owned()),
+                    data: json!([2])
+                },
+            ]
+        );
+
+        codec.codec_mut().pretty(true);
+        let mut event = SseEvent::new(json!([1]));
+        event.event = Some("update".to_owned());
+        codec.encode(event, &mut buf).unwrap();
+        assert_eq!(buf, &b"event: update\ndata: [\ndata:   1\ndata: ]\n\n"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap().data, json!([1]));
+
+        let mut event = SseEvent::new(json!(null));
+        event.id = Some("\n".to_owned());
+        assert!(codec.encode(event, &mut buf).is_err());
+    }
+
+    #[test]
+    fn max_frame_size() {
+        let mut input = b"data: ".to_vec();
+        input.extend_from_slice(&[b'1'; 100]);
+        input.extend_from_slice(b"\ndata: 2\n\ndata: [3]\n\ndata: 4\ndata: 4444\ndata: 55\n\n6\n");
+        input.extend_from_slice(b"data: [1,\ndata: 2,3]\n\n");
+        let mut codec = Codec::<Value, ()>::default().server_sent_events();
+        codec.codec_mut().max_frame_size(Some(8));
+        let mut buf = BytesMut::new();
+        let (mut events, mut errors) = (Vec::new(), 0);
+        for &b in &input[..] {
+            buf.extend_from_slice(&[b]);
+            loop {
+                match codec.decode(&mut buf) {
+                    Ok(Some(event)) => events.push(event.data),
+                    Ok(None) => break,
+                    Err(Error::FrameTooLarge {
+                        max: 8,
+                        resynced: true,
+                    }) => errors += 1,
+                    Err(e) => panic!("unexpected error: {}", e),
+                }
+            }
+            assert!(buf.len() <= 8 + "data: ".len());
+        }
+        assert_eq!((events, errors), (vec![json!([3]), json!([1, 2, 3])], 2));
+    }
+}
@@ -0,0 +1,46 @@
+//! Snapshots of the codec's counters.
+
+/// A snapshot of the codec's counters; see [`Codec::stats_snapshot`][`::Codec::stats_snapshot`].
+///
+/// Counters saturate instead of wrapping. To measure a window, [reset][`::Codec::stats_reset`]
+/// the counters at the start of each window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of items decoded.
+    pub decoded: u64,
+    /// The number of items encoded.
+    pub encoded: u64,
+    /// The number of frames dropped as [duplicates][`::Codec::deduplicate`].
+    pub duplicates: u64,
+    /// The number of [keepalive tokens][`::Codec::keepalive_token`] skipped.
+    pub keepalives: u64,
+    /// The number of frames that failed to decode.
+    pub malformed: u64,
+    /// The number of bytes consumed by frames that failed to decode.
+    pub malformed_bytes: u64,
+    /// The number of frames [skipped][`::Codec::skip_invalid_frames`] for failing to deserialize.
+    pub skipped: u64,
+    /// The number of frames replaced with a default value for failing to deserialize; see
+    /// [`ErrorPolicy::SubstituteDefault`][`::ErrorPolicy::SubstituteDefault`].
+    pub substituted: u64,
+    /// The size in bytes of the frames that [`Compressed`][`::Compressed`] compressed or
+    /// decompressed, before compression.
+    pub uncompressed_bytes: u64,
+    /// The size in bytes of those frames after compression.
+    pub compressed_bytes: u64,
+    /// The number of [slow frames][`::Codec::slow_frame_threshold`] decoded or encoded.
+    pub slow_frames: u64,
+}
+
+impl Stats {
+    /// Returns the size of compressed frames relative to their uncompressed size, such as 0.25
+    /// for frames compressed to a quarter of their size, or `None` if no frames were compressed.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.uncompressed_bytes == 0 {
+            None
+        } else {
+            Some(self.compressed_bytes as f64 / self.uncompressed_bytes as f64)
+        }
+    }
+}
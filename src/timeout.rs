@@ -0,0 +1,160 @@
+//! Timing out streams that go quiet.
+
+use futures::{Async, Future, Poll, Sink, StartSend, Stream};
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+use Error;
+
+/// A stream that fails with [`Error::Timeout`] when no item arrives within a time limit.
+///
+/// The underlying stream is typically a [`FramedJson`] or [`Framed`][`tokio_codec::Framed`]
+/// using a [`Codec`][`::Codec`]. Only complete frames count: a peer that trickles in a frame a
+/// byte at a time still times out, which partial reads of the socket wouldn't catch. The
+/// [handshake timeout][`TimedFramed::handshake_timeout`], if set, applies to the first item
+/// instead, typically so that a peer gets longer (or shorter) to introduce itself.
+///
+/// After a timeout, the stream can be polled again to keep waiting, with the time limit starting
+/// over; dropping the connection is up to the caller. Timing out requires a Tokio timer, so the
+/// stream must be used on a Tokio runtime, and never times out otherwise. The underlying sink,
+/// if any, is passed through.
+///
+/// [`FramedJson`]: ::FramedJson
+#[derive(Debug)]
+pub struct TimedFramed<S> {
+    stream: S,
+    idle: Duration,
+    handshake: Option<Duration>,
+    received: bool,
+    deadline: Option<Delay>,
+}
+
+impl<S> TimedFramed<S> {
+    /// Creates a `TimedFramed` that fails if no item arrives from `stream` within `idle` of the
+    /// last one.
+    pub fn new(stream: S, idle: Duration) -> Self {
+        TimedFramed {
+            stream,
+            idle,
+            handshake: None,
+            received: false,
+            deadline: None,
+        }
+    }
+
+    /// Set how long to wait for the first item, or `None` to wait as long as for any other, as by
+    /// default.
+    pub fn handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake = timeout;
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the `TimedFramed`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Returns the time limit for the next item, and whether it is the handshake timeout.
+    fn timeout(&self) -> (Duration, bool) {
+        match self.handshake {
+            Some(timeout) if !self.received => (timeout, true),
+            _ => (self.idle, false),
+        }
+    }
+}
+
+impl<S> Stream for TimedFramed<S>
+where
+    S: Stream<Error = Error>,
+{
+    type Item = S::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, Error> {
+        let poll = self.stream.poll();
+        if let Ok(Async::NotReady) = poll {
+            let (timeout, handshake) = self.timeout();
+            let deadline = self
+                .deadline
+                .get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+            return match deadline.poll() {
+                Ok(Async::Ready(())) => {
+                    self.deadline = None;
+                    Err(Error::Timeout { timeout, handshake })
+                }
+                // A timer error means there is no timer to wait for, so don't time out.
+                Ok(Async::NotReady) | Err(_) => Ok(Async::NotReady),
+            };
+        }
+        // Anything from the stream, even an error, shows the peer isn't idle.
+        self.deadline = None;
+        if let Ok(Async::Ready(Some(_))) = poll {
+            self.received = true;
+        }
+        poll
+    }
+}
+
+impl<S> Sink for TimedFramed<S>
+where
+    S: Sink,
+{
+    type SinkItem = S::SinkItem;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: S::SinkItem) -> StartSend<S::SinkItem, S::SinkError> {
+        self.stream.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), S::SinkError> {
+        self.stream.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimedFramed;
+    use futures::sync::mpsc;
+    use futures::{Future, Sink, Stream};
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+    use Error;
+
+    #[test]
+    fn timeout() {
+        let (tx, rx) = mpsc::unbounded::<u32>();
+        let rx = rx.map_err(|()| unreachable!());
+        let mut timed = TimedFramed::new(rx, Duration::from_millis(20));
+        timed.handshake_timeout(Some(Duration::from_millis(10)));
+        let mut runtime = Runtime::new().unwrap();
+
+        let (e, timed) = runtime.block_on(timed.into_future()).err().unwrap();
+        match e {
+            Error::Timeout { timeout, handshake } => {
+                assert_eq!((timeout, handshake), (Duration::from_millis(10), true))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        let tx = tx.send(1).wait().unwrap();
+        let (item, timed) = runtime
+            .block_on(timed.into_future())
+            .map_err(|_| ())
+            .unwrap();
+        assert_eq!(item, Some(1));
+        let (e, _) = runtime.block_on(timed.into_future()).err().unwrap();
+        assert_eq!(e.to_string(), "no frame received within 20ms");
+        drop(tx);
+    }
+}
@@ -0,0 +1,124 @@
+//! Ending a stream at a terminating item without closing the connection.
+
+use futures::{Async, Poll, Stream};
+
+/// What [`TakeUntil`] does with the item that ends the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Terminator {
+    /// Consume the terminator without yielding it.
+    Consume,
+    /// Yield the terminator as the last item.
+    Yield,
+}
+
+/// A stream that ends when an item matches a predicate, without ending the stream underneath.
+///
+/// Wrap a `&mut` reference to a [`Framed`][`tokio_codec::Framed`] or [`FramedJson`] to decode a
+/// sub-protocol with an explicit end marker: once the `TakeUntil` ends, the framed connection
+/// carries on from the frame after the terminator.
+///
+/// Created by [`TakeUntil::new`] or [`FramedJson::take_until`].
+///
+/// [`FramedJson`]: ::FramedJson
+/// [`FramedJson::take_until`]: ::FramedJson::take_until
+#[derive(Debug)]
+pub struct TakeUntil<S, F> {
+    stream: S,
+    predicate: F,
+    terminator: Terminator,
+    done: bool,
+}
+
+impl<S, F> TakeUntil<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> bool,
+{
+    /// Creates a `TakeUntil` that ends `stream` at the first item matching `predicate`.
+    pub fn new(stream: S, predicate: F, terminator: Terminator) -> Self {
+        TakeUntil {
+            stream,
+            predicate,
+            terminator,
+            done: false,
+        }
+    }
+}
+
+impl<S, F> TakeUntil<S, F> {
+    /// Returns whether the terminator has been received.
+    pub fn is_terminated(&self) -> bool {
+        self.done
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the `TakeUntil`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, F> Stream for TakeUntil<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> bool,
+{
+    type Item = S::Item;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, S::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        match try_ready!(self.stream.poll()) {
+            Some(item) => {
+                if !(self.predicate)(&item) {
+                    return Ok(Async::Ready(Some(item)));
+                }
+                self.done = true;
+                match self.terminator {
+                    Terminator::Consume => Ok(Async::Ready(None)),
+                    Terminator::Yield => Ok(Async::Ready(Some(item))),
+                }
+            }
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TakeUntil, Terminator};
+    use futures::{Future, Stream};
+    use serde_json::Value;
+    use std::io::Cursor;
+    use tokio_codec::FramedRead;
+    use Codec;
+
+    #[test]
+    fn take_until() {
+        let io = Cursor::new(&b"1 2 {\"end\":1} 3 4 {\"end\":2} 5"[..]);
+        let mut framed = FramedRead::new(io, Codec::<Value, ()>::default());
+        let is_end = |v: &Value| v.get("end").is_some();
+
+        let items = TakeUntil::new(&mut framed, is_end, Terminator::Consume);
+        assert_eq!(items.collect().wait().unwrap(), vec![json!(1), json!(2)]);
+        let mut items = TakeUntil::new(&mut framed, is_end, Terminator::Yield);
+        assert_eq!(
+            items.by_ref().collect().wait().unwrap(),
+            vec![json!(3), json!(4), json!({"end": 2})]
+        );
+        assert!(items.is_terminated());
+        assert_eq!(framed.collect().wait().unwrap(), vec![json!(5)]);
+    }
+}
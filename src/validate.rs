@@ -0,0 +1,389 @@
+//! Limits checked against the raw text of a frame before it is deserialized.
+
+use lex::{Lexer, Token, TokenKind};
+use serde_json;
+use std::collections::HashSet;
+use {Error, JsonType, Limit};
+
+/// The configured limits; see [`Limit`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits {
+    pub(crate) max_string_len: Option<usize>,
+    pub(crate) max_number_len: Option<usize>,
+    pub(crate) max_array_len: Option<usize>,
+    pub(crate) max_object_len: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) deny_duplicate_keys: bool,
+    pub(crate) top_level_types: Option<Vec<JsonType>>,
+}
+
+impl Limits {
+    pub(crate) const fn new() -> Self {
+        Limits {
+            max_string_len: None,
+            max_number_len: None,
+            max_array_len: None,
+            max_object_len: None,
+            max_depth: None,
+            deny_duplicate_keys: false,
+            top_level_types: None,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_string_len.is_some()
+            || self.max_number_len.is_some()
+            || self.max_array_len.is_some()
+            || self.max_object_len.is_some()
+            || self.max_depth.is_some()
+            || self.deny_duplicate_keys
+            || self.top_level_types.is_some()
+    }
+
+    /// Checks a complete, syntactically valid frame against the limits.
+    pub(crate) fn validate(&self, frame: &[u8]) -> Result<(), Error> {
+        if let Some(ref types) = self.top_level_types {
+            // A frame that isn't JSON at all is left for the parser to report.
+            if let Some(found) = JsonType::of(frame).filter(|found| !types.contains(found)) {
+                return Err(Error::UnexpectedType { found });
+            }
+        }
+        let mut lexer = Lexer::new(frame, 0, true);
+        let mut path = Path::default();
+        // Lexer errors can't happen in a valid frame; if they do anyway, leave reporting them to
+        // the parser.
+        while let Ok(Some(token)) = lexer.next_token() {
+            match token.kind {
+                TokenKind::ObjectEnd | TokenKind::ArrayEnd => path.pop(),
+                TokenKind::Comma => path.next(),
+                TokenKind::Colon => {}
+                TokenKind::String if path.expects_key() => {
+                    self.check_string(&token, &path, frame)?;
+                    let count = path.start_member(token);
+                    check(
+                        self.max_object_len,
+                        count,
+                        Limit::ObjectLength,
+                        &path,
+                        frame,
+                    )?;
+                    if self.deny_duplicate_keys && !path.record_key(frame) {
+                        return Err(Error::DuplicateKey {
+                            path: path.render(frame, path.segments.len()),
+                        });
+                    }
+                }
+                _ => {
+                    if let Some(count) = path.start_element() {
+                        check(self.max_array_len, count, Limit::ArrayLength, &path, frame)?;
+                    }
+                    let segment = match token.kind {
+                        TokenKind::String => {
+                            self.check_string(&token, &path, frame)?;
+                            continue;
+                        }
+                        TokenKind::Number => {
+                            self.check_number(&token, &path, frame)?;
+                            continue;
+                        }
+                        TokenKind::ObjectStart => Segment::Member {
+                            key: None,
+                            count: 0,
+                            keys: HashSet::new(),
+                        },
+                        TokenKind::ArrayStart => Segment::Element { count: 0 },
+                        _ => continue,
+                    };
+                    path.push(segment);
+                    check(
+                        self.max_depth,
+                        path.segments.len(),
+                        Limit::Depth,
+                        &path,
+                        frame,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, token: &Token, path: &Path, frame: &[u8]) -> Result<(), Error> {
+        match self.max_string_len {
+            Some(max) if token.len() - 2 > max => Err(Error::LimitExceeded {
+                limit: Limit::StringLength(max),
+                path: path.render(frame, path.segments.len()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_number(&self, token: &Token, path: &Path, frame: &[u8]) -> Result<(), Error> {
+        match self.max_number_len {
+            Some(max) if token.len() > max => Err(Error::LimitExceeded {
+                limit: Limit::NumberLength(max),
+                path: path.render(frame, path.segments.len()),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks a count for the innermost container on `path`, such as its number of members.
+fn check(
+    max: Option<usize>,
+    count: usize,
+    limit: fn(usize) -> Limit,
+    path: &Path,
+    frame: &[u8],
+) -> Result<(), Error> {
+    match max {
+        Some(max) if count > max => Err(Error::LimitExceeded {
+            limit: limit(max),
+            path: path.render(frame, path.segments.len() - 1),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Where a token is within a frame.
+#[derive(Debug, Default)]
+struct Path {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+enum Segment {
+    /// A member of an object with `count` members so far; `key` is set once it has been read.
+    /// `keys` holds the unescaped keys seen so far if duplicate keys are denied.
+    Member {
+        key: Option<Token>,
+        count: usize,
+        keys: HashSet<Vec<u8>>,
+    },
+    /// An element of an array with `count` elements so far.
+    Element { count: usize },
+}
+
+impl Path {
+    fn push(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    fn pop(&mut self) {
+        self.segments.pop();
+    }
+
+    /// Moves on to the next member after a comma.
+    fn next(&mut self) {
+        if let Some(Segment::Member { key, .. }) = self.segments.last_mut() {
+            *key = None;
+        }
+    }
+
+    /// Returns whether the next string is an object key.
+    fn expects_key(&self) -> bool {
+        match self.segments.last() {
+            Some(Segment::Member { key, .. }) => key.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Records the key of a new object member, returning the number of members so far.
+    fn start_member(&mut self, token: Token) -> usize {
+        match self.segments.last_mut() {
+            Some(Segment::Member { key, count, .. }) => {
+                *key = Some(token);
+                *count += 1;
+                *count
+            }
+            _ => 0,
+        }
+    }
+
+    /// Records the key of the current object member as seen, returning `false` if it was seen
+    /// before.
+    fn record_key(&mut self, frame: &[u8]) -> bool {
+        match self.segments.last_mut() {
+            Some(Segment::Member {
+                key: Some(key),
+                keys,
+                ..
+            }) => {
+                let raw = &frame[key.start..key.end];
+                let unescaped = if raw.contains(&b'\\') {
+                    serde_json::from_slice::<String>(raw).map(String::into_bytes)
+                } else {
+                    Ok(raw[1..raw.len() - 1].to_vec())
+                };
+                // A key that fails to unescape is left for the parser to report.
+                unescaped.map_or(true, |unescaped| keys.insert(unescaped))
+            }
+            _ => true,
+        }
+    }
+
+    /// Records a new array element if the next value is one, returning the number of elements so
+    /// far.
+    fn start_element(&mut self) -> Option<usize> {
+        match self.segments.last_mut() {
+            Some(Segment::Element { count }) => {
+                *count += 1;
+                Some(*count)
+            }
+            _ => None,
+        }
+    }
+
+    /// Renders the first `len` segments like `serde_ignored` does, as in `items.3.price`.
+    fn render(&self, frame: &[u8], len: usize) -> String {
+        let segments: Vec<String> = self.segments[..len]
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Member { key: Some(key), .. } => {
+                    let raw = &frame[key.start..key.end];
+                    Some(
+                        serde_json::from_slice(raw)
+                            .unwrap_or_else(|_| String::from_utf8_lossy(raw).into_owned()),
+                    )
+                }
+                Segment::Member { key: None, .. } => None,
+                Segment::Element { count } => Some((count - 1).to_string()),
+            })
+            .collect();
+        if segments.is_empty() {
+            ".".to_owned()
+        } else {
+            segments.join(".")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limits;
+    use {Error, JsonType, Limit};
+
+    fn check(limits: &Limits, frame: &str) -> Result<(), (Limit, String)> {
+        limits.validate(frame.as_bytes()).map_err(|e| match e {
+            Error::LimitExceeded { limit, path } => (limit, path),
+            e => panic!("unexpected {:?}", e),
+        })
+    }
+
+    #[test]
+    fn string_length() {
+        let limits = Limits {
+            max_string_len: Some(3),
+            ..Limits::default()
+        };
+        let too_long = Limit::StringLength(3);
+        assert_eq!(check(&limits, r#"{"abc": ["def", {"g": "h"}]}"#), Ok(()));
+        assert_eq!(check(&limits, r#""abcd""#), Err((too_long, ".".to_owned())));
+        assert_eq!(
+            check(&limits, r#"{"a": ["b", {"c\n": "d", "e": "fghi"}]}"#),
+            Err((too_long, "a.1.e".to_owned()))
+        );
+        assert_eq!(
+            check(&limits, r#"[{"a": 1}, {"bcde": 2}]"#),
+            Err((too_long, "1".to_owned()))
+        );
+    }
+
+    #[test]
+    fn number_length() {
+        let limits = Limits {
+            max_number_len: Some(4),
+            ..Limits::default()
+        };
+        assert_eq!(check(&limits, r#"[1234, -1.5, {"12345": 1e10}]"#), Ok(()));
+        assert_eq!(
+            check(&limits, r#"{"a": [1, 1.2345]}"#),
+            Err((Limit::NumberLength(4), "a.1".to_owned()))
+        );
+    }
+
+    #[test]
+    fn element_counts() {
+        let limits = Limits {
+            max_array_len: Some(2),
+            max_object_len: Some(1),
+            ..Limits::default()
+        };
+        assert_eq!(check(&limits, r#"[[1, 2], {"a": [{}, []]}]"#), Ok(()));
+        assert_eq!(
+            check(&limits, r#"[1, 2, 3]"#),
+            Err((Limit::ArrayLength(2), ".".to_owned()))
+        );
+        assert_eq!(
+            check(&limits, r#"{"a": [{"b": 1, "c": 2}]}"#),
+            Err((Limit::ObjectLength(1), "a.0".to_owned()))
+        );
+        let limits = Limits {
+            max_array_len: Some(0),
+            ..Limits::default()
+        };
+        assert_eq!(check(&limits, r#"{"a": []}"#), Ok(()));
+        assert_eq!(
+            check(&limits, r#"{"a": [[]]}"#),
+            Err((Limit::ArrayLength(0), "a".to_owned()))
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let limits = Limits {
+            max_depth: Some(2),
+            ..Limits::default()
+        };
+        assert_eq!(check(&limits, r#"[{"a": 1}, [2], 3]"#), Ok(()));
+        assert_eq!(
+            check(&limits, r#"[1, {"a": {}}]"#),
+            Err((Limit::Depth(2), "1.a".to_owned()))
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let limits = Limits {
+            deny_duplicate_keys: true,
+            ..Limits::default()
+        };
+        let duplicate = |frame: &str| match limits.validate(frame.as_bytes()) {
+            Err(Error::DuplicateKey { path }) => Some(path),
+            Err(e) => panic!("unexpected {:?}", e),
+            Ok(()) => None,
+        };
+        assert_eq!(
+            duplicate(r#"{"a": {"a": 1}, "b": [{"a": 1}, {"a": 2}]}"#),
+            None
+        );
+        assert_eq!(
+            duplicate(r#"{"a": 1, "b": 2, "a": 3}"#),
+            Some("a".to_owned())
+        );
+        assert_eq!(
+            duplicate(r#"[{"a": {"b\n": 1, "b\u000a": 2}}]"#),
+            Some("0.a.b\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn top_level_types() {
+        let limits = Limits {
+            top_level_types: Some(vec![JsonType::Object, JsonType::Array]),
+            ..Limits::default()
+        };
+        assert!(limits.validate(b" {\"a\": 1}").is_ok());
+        assert!(limits.validate(b"[1]").is_ok());
+        for (frame, ty) in &[
+            (&b"1"[..], JsonType::Number),
+            (b"\n\"a\"", JsonType::String),
+        ] {
+            match limits.validate(frame) {
+                Err(Error::UnexpectedType { found }) => assert_eq!(found, *ty),
+                res => panic!("unexpected {:?}", res),
+            }
+        }
+    }
+}
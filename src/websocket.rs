@@ -0,0 +1,202 @@
+//! Sending and receiving JSON over WebSockets.
+
+use bytes::BytesMut;
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio_codec::Encoder;
+use tokio_tungstenite::tungstenite::{self, Message};
+use {Codec, Error};
+
+/// A stream and sink of values over a WebSocket, such as a tokio-tungstenite `WebSocketStream`,
+/// with one value per message (requires the `websocket` feature).
+///
+/// Each text message is decoded as one frame, so it must hold exactly one value, and options
+/// such as limits and subscriptions apply to it; a message that fails to decode yields an error
+/// without ending the stream. Binary messages are decoded too if
+/// [enabled][`WebSocketJson::binary_messages`], and are skipped otherwise, as are ping, pong and
+/// close messages. Each item is sent as a text message.
+///
+/// The codec's [framing][`Codec::framing`] should be left as is, since messages delimit values
+/// themselves.
+#[derive(Debug)]
+pub struct WebSocketJson<S, D, E> {
+    inner: S,
+    codec: Codec<D, E>,
+    binary: bool,
+    pending: Option<Message>,
+}
+
+impl<S, D, E> WebSocketJson<S, D, E> {
+    /// Creates a `WebSocketJson` over the WebSocket `inner` using `codec`.
+    pub fn new(inner: S, codec: Codec<D, E>) -> Self {
+        WebSocketJson {
+            inner,
+            codec,
+            binary: false,
+            pending: None,
+        }
+    }
+
+    /// Set whether or not to decode binary messages, rather than skip them. Disabled by default.
+    pub fn binary_messages(&mut self, binary: bool) {
+        self.binary = binary;
+    }
+
+    /// Returns a reference to the underlying WebSocket.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying WebSocket.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the codec.
+    pub fn codec(&self) -> &Codec<D, E> {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the codec.
+    pub fn codec_mut(&mut self) -> &mut Codec<D, E> {
+        &mut self.codec
+    }
+
+    /// Consumes the `WebSocketJson`, returning the underlying WebSocket and the codec. A message
+    /// that is yet to be sent is dropped.
+    pub fn into_inner(self) -> (S, Codec<D, E>) {
+        (self.inner, self.codec)
+    }
+}
+
+/// Converts a WebSocket error into an I/O error.
+fn ws_error(e: tungstenite::Error) -> Error {
+    match e {
+        tungstenite::Error::Io(e) => Error::Io(e),
+        e => Error::Io(io::Error::other(e)),
+    }
+}
+
+impl<S, D, E> Stream for WebSocketJson<S, D, E>
+where
+    S: Stream<Item = Message, Error = tungstenite::Error>,
+    for<'de> D: Deserialize<'de>,
+{
+    type Item = D;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<D>, Error> {
+        loop {
+            let message = match try_ready!(self.inner.poll().map_err(ws_error)) {
+                Some(message) => message,
+                None => return Ok(Async::Ready(None)),
+            };
+            let mut buf = match message {
+                Message::Text(text) => BytesMut::from(text.into_bytes()),
+                Message::Binary(data) if self.binary => BytesMut::from(data),
+                _ => continue,
+            };
+            let item = self.codec.decode_with(&mut buf, Codec::decode_datagram)?;
+            if item.is_some() {
+                return Ok(Async::Ready(item));
+            }
+        }
+    }
+}
+
+impl<S, D, E> WebSocketJson<S, D, E>
+where
+    S: Sink<SinkItem = Message, SinkError = tungstenite::Error>,
+{
+    /// Hands the pending message to the WebSocket, if there is one.
+    fn send_pending(&mut self) -> Poll<(), Error> {
+        if let Some(message) = self.pending.take() {
+            if let AsyncSink::NotReady(message) =
+                self.inner.start_send(message).map_err(ws_error)?
+            {
+                self.pending = Some(message);
+                return Ok(Async::NotReady);
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<S, D, E> Sink for WebSocketJson<S, D, E>
+where
+    S: Sink<SinkItem = Message, SinkError = tungstenite::Error>,
+    E: Serialize,
+{
+    type SinkItem = E;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: E) -> StartSend<E, Error> {
+        if self.send_pending()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        let mut buf = BytesMut::new();
+        self.codec.encode(item, &mut buf)?;
+        let text = String::from_utf8(buf.to_vec())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.pending = Some(Message::Text(text));
+        self.send_pending()?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        try_ready!(self.send_pending());
+        self.inner.poll_complete().map_err(ws_error)
+    }
+
+    fn close(&mut self) -> Poll<(), Error> {
+        try_ready!(self.send_pending());
+        self.inner.close().map_err(ws_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebSocketJson;
+    use futures::stream::iter_ok;
+    use futures::sync::mpsc;
+    use futures::{Future, Sink, Stream};
+    use serde_json::Value;
+    use tokio_tungstenite::tungstenite::{self, Message};
+    use Codec;
+
+    #[test]
+    fn websocket() {
+        let messages = vec![
+            Message::Text("{\"a\": 1}".to_owned()),
+            Message::Ping(vec![]),
+            Message::Text("[1".to_owned()),
+            Message::Binary(b"2".to_vec()),
+            Message::Text(" 3 ".to_owned()),
+        ];
+        let ws = iter_ok::<_, tungstenite::Error>(messages.clone());
+        let items: Vec<_> = WebSocketJson::new(ws, Codec::<Value, ()>::default())
+            .then(|item| Ok::<_, ()>(item.ok()))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(items, vec![Some(json!({"a": 1})), None, Some(json!(3))]);
+
+        let ws = iter_ok::<_, tungstenite::Error>(messages);
+        let mut ws = WebSocketJson::new(ws, Codec::<Value, ()>::default());
+        ws.binary_messages(true);
+        let items: Vec<_> = ws
+            .then(|item| Ok::<_, ()>(item.ok()))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(items[2], Some(json!(2)));
+
+        let (tx, rx) = mpsc::unbounded();
+        let tx = tx.sink_map_err(|_| tungstenite::Error::AlreadyClosed);
+        let ws = WebSocketJson::new(tx, Codec::<(), Value>::default());
+        ws.send(json!([1])).wait().unwrap();
+        let sent: Vec<_> = rx.take(1).collect().wait().unwrap();
+        assert_eq!(sent, vec![Message::Text("[1]".to_owned())]);
+    }
+}